@@ -0,0 +1,294 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::NaiveDate;
+
+use crate::provider::Provider;
+
+/// The Alpha Vantage API
+pub(crate) const DEFAULT_BASE_URL: &str = "https://www.alphavantage.co";
+
+/// The environment variable holding the API key
+pub(crate) const API_KEY_VARIABLE: &str = "ALPHAVANTAGE_API_KEY";
+
+/// TIME_SERIES_DAILY doesn't say, the US equities it's meant for trade in dollars
+const CURRENCY: &str = "USD";
+
+/// The number of past days of the compact series, the full one being requested for
+/// older dates
+const COMPACT_DAYS: i64 = 100;
+
+/// The User-Agent of the requests unless told otherwise
+const DEFAULT_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Get the daily closes from the Alpha Vantage API
+/// E.g. https://www.alphavantage.co/query?function=TIME_SERIES_DAILY&symbol=IBM&outputsize=compact&apikey=demo
+#[derive(Debug)]
+pub struct AlphaVantage {
+    /// the API's scheme and host
+    base_url: String,
+    /// the key of the API, read from ALPHAVANTAGE_API_KEY
+    api_key: Option<String>,
+    client: reqwest::Client,
+    /// each response holds the series of a symbol, kept so that the other dates
+    /// don't use up the daily requests allowed
+    series: Mutex<HashMap<String, HashMap<NaiveDate, f64>>>,
+}
+
+impl AlphaVantage {
+    pub(crate) fn new(base_url: &str, api_key: Option<String>, user_agent: Option<&str>) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent(user_agent.unwrap_or(DEFAULT_USER_AGENT))
+            .build();
+        Self {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            api_key,
+            client: client.unwrap(),
+            series: Mutex::new(HashMap::default()),
+        }
+    }
+
+    /// The raw daily series of a symbol, the compact one if it goes back to the date
+    async fn query(&self, symbol: &str, since: NaiveDate) -> Result<String, std::io::Error> {
+        let Some(api_key) = &self.api_key else {
+            return Err(std::io::Error::other(format!(
+                "Alpha Vantage: {API_KEY_VARIABLE} isn't set, unable to query for {symbol}"
+            )));
+        };
+        let days = (chrono::Utc::now().date_naive() - since).num_days();
+        let output_size = if days < COMPACT_DAYS {
+            "compact"
+        } else {
+            "full"
+        };
+        let url = format!(
+            "{}/query?function=TIME_SERIES_DAILY&symbol={symbol}&outputsize={output_size}&apikey={api_key}",
+            self.base_url
+        );
+        let response = self.client.get(&url).send().await.map_err(|_| {
+            std::io::Error::other(format!(
+                "Alpha Vantage: Invalid response while querying for {symbol}"
+            ))
+        })?;
+        if !response.status().is_success() {
+            return Err(std::io::Error::other(format!(
+                "Alpha Vantage: HTTP status {} while querying for {symbol}",
+                response.status()
+            )));
+        }
+        response.text().await.map_err(|_| {
+            std::io::Error::other(format!(
+                "Alpha Vantage: Invalid text in response while querying for {symbol}"
+            ))
+        })
+    }
+
+    /// The close of each day of a TIME_SERIES_DAILY response. The notes sent instead
+    /// of the series, e.g. once the requests allowed are used up, are errors
+    fn parse_series(
+        symbol: &str,
+        response: &str,
+    ) -> Result<HashMap<NaiveDate, f64>, std::io::Error> {
+        let json: serde_json::Value = serde_json::from_str(response).map_err(|e| {
+            std::io::Error::other(format!("Alpha Vantage: invalid series for {symbol}: {e}"))
+        })?;
+        for key in ["Note", "Information", "Error Message"] {
+            if let Some(message) = json.get(key).and_then(serde_json::Value::as_str) {
+                return Err(std::io::Error::other(format!(
+                    "Alpha Vantage: no series for {symbol}: {message}"
+                )));
+            }
+        }
+        let Some(days) = json
+            .get("Time Series (Daily)")
+            .and_then(serde_json::Value::as_object)
+        else {
+            return Err(std::io::Error::other(format!(
+                "Alpha Vantage: error retrieving the Time Series (Daily) key for {symbol}"
+            )));
+        };
+        Ok(days
+            .iter()
+            .filter_map(|(date, day)| {
+                let close = day.get("4. close")?.as_str()?.parse().ok()?;
+                Some((date.parse().ok()?, close))
+            })
+            .collect())
+    }
+
+    /// The daily closes of a symbol going back to a date at least, queried once
+    async fn closes(
+        &self,
+        symbol: &str,
+        since: NaiveDate,
+    ) -> Result<HashMap<NaiveDate, f64>, std::io::Error> {
+        if let Some(closes) = self.series.lock().unwrap().get(symbol) {
+            if closes.keys().min().is_some_and(|first| *first <= since) {
+                return Ok(closes.clone());
+            }
+        }
+        let response = self.query(symbol, since).await?;
+        let closes = Self::parse_series(symbol, &response)?;
+        self.series
+            .lock()
+            .unwrap()
+            .insert(symbol.to_owned(), closes.clone());
+        Ok(closes)
+    }
+
+    /// Describes how the price of a symbol is derived, for the explain command
+    pub(crate) async fn explain(
+        &self,
+        symbol: &str,
+        date: NaiveDate,
+    ) -> Result<String, std::io::Error> {
+        let response = self.query(symbol, date).await?;
+        let closes = Self::parse_series(symbol, &response)?;
+        let key = date.format("%Y-%m-%d");
+        Ok(match closes.get(&date) {
+            Some(close) => {
+                format!("The \"4. close\" of the \"{key}\" day of TIME_SERIES_DAILY: {close}")
+            }
+            None => format!("No \"{key}\" day in TIME_SERIES_DAILY, not a trading day"),
+        })
+    }
+}
+
+impl Provider for AlphaVantage {
+    type ErrorType = std::io::Error;
+
+    fn get_provider_name(&self) -> String {
+        "Alpha Vantage".to_owned()
+    }
+
+    /// Downloads the close of a symbol on a date, an error if it wasn't traded that day
+    async fn download_price(
+        &self,
+        symbol: String,
+        date: NaiveDate,
+    ) -> Result<(String, NaiveDate, f64, String), Self::ErrorType> {
+        let closes = self.closes(&symbol, date).await?;
+        match closes.get(&date) {
+            Some(close) => Ok((symbol, date, *close, CURRENCY.to_owned())),
+            None => Err(std::io::Error::other(format!(
+                "Alpha Vantage: no price for {symbol} on {date}"
+            ))),
+        }
+    }
+
+    /// Takes the range from the series of a single request
+    async fn download_prices_bulk(
+        &self,
+        symbol: String,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<(String, NaiveDate, f64, String)>, Self::ErrorType> {
+        Ok(self
+            .closes(&symbol, start)
+            .await?
+            .into_iter()
+            .filter(|(date, _)| *date >= start && *date <= end)
+            .map(|(date, close)| (symbol.clone(), date, close, CURRENCY.to_owned()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{ok, serve};
+
+    const DAILY: &str = include_str!("../fixtures/alphavantage/daily.json");
+    const RATE_LIMITED: &str = include_str!("../fixtures/alphavantage/rate_limited.json");
+    const INVALID_SYMBOL: &str = include_str!("../fixtures/alphavantage/invalid_symbol.json");
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    fn alpha_vantage(base_url: &str) -> AlphaVantage {
+        AlphaVantage::new(base_url, Some("demo".to_owned()), None)
+    }
+
+    #[tokio::test]
+    async fn takes_the_close_of_the_date() {
+        let (base_url, requests) = serve(vec![ok(DAILY)]);
+        let (symbol, day, price, currency) = alpha_vantage(&base_url)
+            .download_price("IBM".to_owned(), date(1))
+            .await
+            .unwrap();
+        assert_eq!((symbol.as_str(), day, price), ("IBM", date(1), 185.03));
+        assert_eq!(currency, "USD");
+        assert!(requests.lock().unwrap()[0].starts_with(
+            "get /query?function=time_series_daily&symbol=ibm&outputsize=full&apikey=demo "
+        ));
+    }
+
+    #[tokio::test]
+    async fn queries_each_symbol_once() {
+        // the server answers a single request, the other dates come from its series
+        let (base_url, _) = serve(vec![ok(DAILY)]);
+        let alpha_vantage = alpha_vantage(&base_url);
+        let (_, _, price, _) = alpha_vantage
+            .download_price("IBM".to_owned(), date(1))
+            .await
+            .unwrap();
+        assert_eq!(price, 185.03);
+        let start = NaiveDate::from_ymd_opt(2024, 2, 28).unwrap();
+        let mut prices = alpha_vantage
+            .download_prices_bulk("IBM".to_owned(), start, date(3))
+            .await
+            .unwrap();
+        prices.sort_by_key(|(_, date, _, _)| *date);
+        let closes = prices
+            .iter()
+            .map(|(_, date, close, _)| (*date, *close))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            closes,
+            vec![
+                (start, 185.3),
+                (start.succ_opt().unwrap(), 185.85),
+                (date(1), 185.03)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn fails_on_a_non_trading_day() {
+        let (base_url, _) = serve(vec![ok(DAILY)]);
+        let error = alpha_vantage(&base_url)
+            .download_price("IBM".to_owned(), date(2))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("no price for IBM on 2024-03-02"));
+    }
+
+    #[tokio::test]
+    async fn reports_the_rate_limit_note() {
+        let (base_url, _) = serve(vec![ok(RATE_LIMITED)]);
+        let error = alpha_vantage(&base_url)
+            .download_price("IBM".to_owned(), date(1))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("rate limit"));
+    }
+
+    #[tokio::test]
+    async fn reports_an_invalid_symbol() {
+        let (base_url, _) = serve(vec![ok(INVALID_SYMBOL)]);
+        let error = alpha_vantage(&base_url)
+            .download_price("NOPE".to_owned(), date(1))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("Invalid API call"));
+    }
+
+    #[tokio::test]
+    async fn fails_without_an_api_key() {
+        let error = AlphaVantage::new("http://127.0.0.1:9", None, None)
+            .download_price("IBM".to_owned(), date(1))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains(API_KEY_VARIABLE));
+    }
+}
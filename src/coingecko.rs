@@ -0,0 +1,255 @@
+use chrono::{Days, NaiveDate, Utc};
+
+use crate::provider::Provider;
+
+/// The public CoinGecko API
+pub(crate) const DEFAULT_BASE_URL: &str = "https://api.coingecko.com/api/v3";
+
+/// The currency the prices are taken in
+const CURRENCY: &str = "USD";
+
+/// The User-Agent of the requests unless told otherwise
+const DEFAULT_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Get the daily prices of the coins from the CoinGecko API
+/// E.g. https://api.coingecko.com/api/v3/coins/bitcoin/history?date=02-03-2024&localization=false
+#[derive(Debug)]
+pub struct CoinGecko {
+    /// the API's scheme, host and version path
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl CoinGecko {
+    pub(crate) fn new(base_url: &str, user_agent: Option<&str>) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent(user_agent.unwrap_or(DEFAULT_USER_AGENT))
+            .build();
+        Self {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            client: client.unwrap(),
+        }
+    }
+
+    /// The raw snapshot of a coin at 00:00 UTC of a date. The history endpoint
+    /// takes the date as day-month-year
+    async fn query(&self, coin: &str, date: NaiveDate) -> Result<String, std::io::Error> {
+        let url = format!(
+            "{}/coins/{coin}/history?date={}&localization=false",
+            self.base_url,
+            date.format("%d-%m-%Y")
+        );
+        self.get(coin, &url).await
+    }
+
+    /// The raw current price of a coin
+    async fn query_live(&self, coin: &str) -> Result<String, std::io::Error> {
+        let url = format!(
+            "{}/simple/price?ids={coin}&vs_currencies=usd",
+            self.base_url
+        );
+        self.get(coin, &url).await
+    }
+
+    async fn get(&self, coin: &str, url: &str) -> Result<String, std::io::Error> {
+        let response = self.client.get(url).send().await.map_err(|_| {
+            std::io::Error::other(format!(
+                "CoinGecko: Invalid response while querying for {coin}"
+            ))
+        })?;
+        if !response.status().is_success() {
+            return Err(std::io::Error::other(format!(
+                "CoinGecko: HTTP status {} while querying for {coin}",
+                response.status()
+            )));
+        }
+        response.text().await.map_err(|_| {
+            std::io::Error::other(format!(
+                "CoinGecko: Invalid text in response while querying for {coin}"
+            ))
+        })
+    }
+
+    /// The USD price of a history response, which has no market data for the
+    /// dates before the coin was listed
+    fn parse_price(coin: &str, response: &str) -> Result<f64, std::io::Error> {
+        let json: serde_json::Value = serde_json::from_str(response).map_err(|e| {
+            std::io::Error::other(format!("CoinGecko: invalid history for {coin}: {e}"))
+        })?;
+        if let Some(error) = json.get("error").and_then(serde_json::Value::as_str) {
+            return Err(std::io::Error::other(format!(
+                "CoinGecko: no history for {coin}: {error}"
+            )));
+        }
+        json.get("market_data")
+            .and_then(|market_data| market_data.get("current_price"))
+            .and_then(|current_price| current_price.get("usd"))
+            .and_then(serde_json::Value::as_f64)
+            .ok_or_else(|| {
+                std::io::Error::other(format!(
+                    "CoinGecko: error retrieving the market_data.current_price.usd key for {coin}"
+                ))
+            })
+    }
+
+    /// The USD price of a simple price response, which leaves out the unknown coins
+    fn parse_live_price(coin: &str, response: &str) -> Result<f64, std::io::Error> {
+        let json: serde_json::Value = serde_json::from_str(response).map_err(|e| {
+            std::io::Error::other(format!("CoinGecko: invalid price for {coin}: {e}"))
+        })?;
+        json.get(coin)
+            .and_then(|prices| prices.get("usd"))
+            .and_then(serde_json::Value::as_f64)
+            .ok_or_else(|| {
+                std::io::Error::other(format!(
+                    "CoinGecko: error retrieving the {coin}.usd key, the coin may not exist"
+                ))
+            })
+    }
+
+    /// Whether a date's close is still to come, its snapshot not having been taken
+    pub(crate) fn is_live(date: NaiveDate) -> bool {
+        date >= Utc::now().date_naive()
+    }
+
+    /// The day after a date, whose 00:00 UTC snapshot is the date's close
+    fn snapshot_date(coin: &str, date: NaiveDate) -> Result<NaiveDate, std::io::Error> {
+        date.checked_add_days(Days::new(1)).ok_or_else(|| {
+            std::io::Error::other(format!("CoinGecko: no date after {date} for {coin}"))
+        })
+    }
+
+    /// Describes how the price of a coin is derived, for the explain command
+    pub(crate) async fn explain(
+        &self,
+        coin: &str,
+        date: NaiveDate,
+    ) -> Result<String, std::io::Error> {
+        if Self::is_live(date) {
+            let response = self.query_live(coin).await?;
+            let price = Self::parse_live_price(coin, &response)?;
+            return Ok(format!(
+                "Queried for the current price, {date} not having closed yet\n{coin}.usd: {price}"
+            ));
+        }
+        let snapshot_date = Self::snapshot_date(coin, date)?;
+        let response = self.query(coin, snapshot_date).await?;
+        let price = Self::parse_price(coin, &response)?;
+        Ok(format!(
+            "Queried for {}, the snapshot at 00:00 UTC closing {date}\nmarket_data.current_price.usd: {price}",
+            snapshot_date.format("%d-%m-%Y")
+        ))
+    }
+}
+
+impl Provider for CoinGecko {
+    type ErrorType = std::io::Error;
+
+    fn get_provider_name(&self) -> String {
+        "CoinGecko".to_owned()
+    }
+
+    /// Downloads the USD close of a coin, by its CoinGecko id (e.g. bitcoin), on a
+    /// date: the price at 00:00 UTC of the next day, as coins trade around the clock.
+    /// Today's close being still to come, its price is the current one
+    async fn download_price(
+        &self,
+        coin: String,
+        date: NaiveDate,
+    ) -> Result<(String, NaiveDate, f64, String), Self::ErrorType> {
+        if Self::is_live(date) {
+            let response = self.query_live(&coin).await?;
+            let price = Self::parse_live_price(&coin, &response)?;
+            return Ok((coin, date, price, CURRENCY.to_owned()));
+        }
+        let response = self.query(&coin, Self::snapshot_date(&coin, date)?).await?;
+        let price = Self::parse_price(&coin, &response)?;
+        Ok((coin, date, price, CURRENCY.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{ok, serve};
+
+    const BITCOIN: &str = include_str!("../fixtures/coingecko/bitcoin.json");
+    const NOT_LISTED_YET: &str = include_str!("../fixtures/coingecko/not_listed_yet.json");
+    const UNKNOWN_COIN: &str = include_str!("../fixtures/coingecko/unknown_coin.json");
+    const SIMPLE_PRICE: &str = include_str!("../fixtures/coingecko/simple_price.json");
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    #[tokio::test]
+    async fn takes_the_next_day_snapshot_as_the_close() {
+        let (base_url, requests) = serve(vec![ok(BITCOIN)]);
+        let (coin, day, price, currency) = CoinGecko::new(&base_url, None)
+            .download_price("bitcoin".to_owned(), date(1))
+            .await
+            .unwrap();
+        assert_eq!((coin.as_str(), day, price), ("bitcoin", date(1), 62431.65));
+        assert_eq!(currency, "USD");
+        // day-month-year, the 2nd of March
+        assert!(requests.lock().unwrap()[0]
+            .starts_with("get /coins/bitcoin/history?date=02-03-2024&localization=false "));
+    }
+
+    #[tokio::test]
+    async fn crosses_the_end_of_the_month() {
+        let (base_url, requests) = serve(vec![ok(BITCOIN)]);
+        let last_of_february = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+        CoinGecko::new(&base_url, None)
+            .download_price("bitcoin".to_owned(), last_of_february)
+            .await
+            .unwrap();
+        assert!(requests.lock().unwrap()[0].contains("?date=01-03-2024&"));
+    }
+
+    #[tokio::test]
+    async fn fails_before_the_coin_is_listed() {
+        let (base_url, _) = serve(vec![ok(NOT_LISTED_YET)]);
+        let error = CoinGecko::new(&base_url, None)
+            .download_price(
+                "bitcoin".to_owned(),
+                NaiveDate::from_ymd_opt(2009, 1, 3).unwrap(),
+            )
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("current_price"));
+    }
+
+    #[tokio::test]
+    async fn reports_an_unknown_coin() {
+        let (base_url, _) = serve(vec![ok(UNKNOWN_COIN)]);
+        let error = CoinGecko::new(&base_url, None)
+            .download_price("btc".to_owned(), date(1))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("coin not found"));
+    }
+
+    #[tokio::test]
+    async fn takes_the_current_price_today() {
+        let (base_url, requests) = serve(vec![ok(SIMPLE_PRICE)]);
+        let today = Utc::now().date_naive();
+        let (_, day, price, _) = CoinGecko::new(&base_url, None)
+            .download_price("bitcoin".to_owned(), today)
+            .await
+            .unwrap();
+        assert_eq!((day, price), (today, 67123.45));
+        assert!(requests.lock().unwrap()[0]
+            .starts_with("get /simple/price?ids=bitcoin&vs_currencies=usd "));
+    }
+
+    #[tokio::test]
+    async fn reports_an_unknown_coin_today() {
+        let (base_url, _) = serve(vec![ok("{}")]);
+        let error = CoinGecko::new(&base_url, None)
+            .download_price("btc".to_owned(), Utc::now().date_naive())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("btc.usd"));
+    }
+}
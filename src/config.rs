@@ -0,0 +1,290 @@
+use std::{fmt, marker::PhantomData};
+
+use chrono::NaiveDate;
+
+use crate::providers;
+
+use serde::{
+    de::{Deserializer, MapAccess, Visitor},
+    Deserialize,
+};
+
+/// A JSON object read as the list of its entries, in file order.
+/// Unlike a map, repeated keys are all kept so the caller decides what to do with them.
+#[derive(Debug)]
+pub(crate) struct Entries<V>(pub(crate) Vec<(String, V)>);
+
+struct EntriesVisitor<V>(PhantomData<V>);
+
+impl<'de, V: Deserialize<'de>> Visitor<'de> for EntriesVisitor<V> {
+    type Value = Entries<V>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut entries = Vec::with_capacity(map.size_hint().unwrap_or_default());
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(Entries(entries))
+    }
+}
+
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for Entries<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(EntriesVisitor(PhantomData))
+    }
+}
+
+/// The key of the top-level array listing instruments outside of any provider block
+pub(crate) const INSTRUMENTS_KEY: &str = "instruments";
+
+/// Part of a position sold on a date
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub(crate) struct Sell {
+    pub(crate) date: NaiveDate,
+    pub(crate) quantity: f64,
+}
+
+/// A quantity bought (and possibly sold) at once
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub(crate) struct Lot {
+    pub(crate) quantity: f64,
+    pub(crate) buy_date: Option<NaiveDate>,
+    /// the price paid per unit, the close on buy_date if not specified
+    pub(crate) buy_price: Option<f64>,
+    pub(crate) sell_date: Option<NaiveDate>,
+    /// the price received per unit, the close on sell_date if not specified
+    pub(crate) sell_price: Option<f64>,
+    /// the alerts command warns when the latest price falls below it
+    pub(crate) stop_price: Option<f64>,
+    /// the alerts command warns when the price moves more than this percentage over the period
+    pub(crate) alert_pct: Option<f64>,
+    /// the key of the provider pricing the lot, overriding its block's
+    pub(crate) provider: Option<String>,
+    /// the price increment the instrument trades in, the downloaded prices being
+    /// rounded to the nearest one
+    pub(crate) tick_size: Option<f64>,
+    /// the yearly income paid, as a percentage of the price
+    pub(crate) yield_pct: Option<f64>,
+    /// the parts of the quantity sold over time, the position shrinking on each date
+    #[serde(default)]
+    pub(crate) sells: Vec<Sell>,
+    /// the fund's yearly fees, as a percentage of its value, which --model-fees
+    /// deducts over the time the lot is held
+    pub(crate) expense_ratio: Option<f64>,
+    /// whether the currency risk is hedged, --base-currency converting the value at
+    /// the rate of the buy_date rather than each day's
+    #[serde(default)]
+    pub(crate) hedged: bool,
+    /// the sum invested on buy_date, the quantity being derived from it once the
+    /// price is known
+    #[serde(skip)]
+    pub(crate) amount: Option<f64>,
+    /// the currency of amount, which must be the one the instrument is quoted in
+    #[serde(skip)]
+    pub(crate) currency: Option<String>,
+}
+
+impl Lot {
+    /// The quantity held on a date: none before buy_date or after sell_date, and
+    /// otherwise what's left after the sells up to it
+    pub(crate) fn quantity_on(&self, date: NaiveDate) -> f64 {
+        if self.buy_date.is_some_and(|buy_date| date < buy_date)
+            || self.sell_date.is_some_and(|sell_date| date > sell_date)
+        {
+            return 0.;
+        }
+        let sold = self
+            .sells
+            .iter()
+            .filter(|sell| sell.date <= date)
+            .map(|sell| sell.quantity)
+            .sum::<f64>();
+        (self.quantity - sold).max(0.)
+    }
+
+    /// Whether the two lots differ only by their quantity, or by their amount for
+    /// two investments
+    fn same_transaction(&self, other: &Lot) -> bool {
+        self.amount.is_some() == other.amount.is_some()
+            && Lot {
+                quantity: 0.,
+                amount: None,
+                ..self.clone()
+            } == Lot {
+                quantity: 0.,
+                amount: None,
+                ..other.clone()
+            }
+    }
+}
+
+/// A lot given by the amount invested rather than its quantity, e.g. for funds
+#[derive(Debug, Deserialize)]
+pub(crate) struct Investment {
+    pub(crate) amount: f64,
+    pub(crate) currency: Option<String>,
+    pub(crate) buy_date: NaiveDate,
+    /// the price paid per unit, the close on buy_date if not specified
+    pub(crate) buy_price: Option<f64>,
+    pub(crate) sell_date: Option<NaiveDate>,
+    pub(crate) sell_price: Option<f64>,
+    pub(crate) stop_price: Option<f64>,
+    pub(crate) alert_pct: Option<f64>,
+    pub(crate) provider: Option<String>,
+    pub(crate) tick_size: Option<f64>,
+    pub(crate) yield_pct: Option<f64>,
+    #[serde(default)]
+    pub(crate) sells: Vec<Sell>,
+    pub(crate) expense_ratio: Option<f64>,
+    #[serde(default)]
+    pub(crate) hedged: bool,
+}
+
+/// A holding in a provider block: a plain quantity, a lot or an investment
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Holding {
+    Quantity(f64),
+    Lot(Lot),
+    Investment(Investment),
+}
+
+impl From<Holding> for Lot {
+    fn from(holding: Holding) -> Self {
+        match holding {
+            Holding::Quantity(quantity) => Lot {
+                quantity,
+                ..Default::default()
+            },
+            Holding::Lot(lot) => lot,
+            Holding::Investment(investment) => Lot {
+                quantity: 0.,
+                buy_date: Some(investment.buy_date),
+                buy_price: investment.buy_price,
+                sell_date: investment.sell_date,
+                sell_price: investment.sell_price,
+                stop_price: investment.stop_price,
+                alert_pct: investment.alert_pct,
+                provider: investment.provider,
+                tick_size: investment.tick_size,
+                yield_pct: investment.yield_pct,
+                sells: investment.sells,
+                expense_ratio: investment.expense_ratio,
+                hedged: investment.hedged,
+                amount: Some(investment.amount),
+                currency: investment.currency,
+            },
+        }
+    }
+}
+
+/// An entry of the top-level "instruments" array
+#[derive(Debug, Deserialize)]
+pub(crate) struct InstrumentEntry {
+    pub(crate) symbol: String,
+    #[serde(flatten)]
+    pub(crate) holding: Holding,
+}
+
+/// A top-level value of the configuration file
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Section {
+    /// The symbols and holdings under a provider key
+    Provider(Entries<Holding>),
+    /// The flat "instruments" array, priced by the default provider
+    Instruments(Vec<InstrumentEntry>),
+    /// A named account (e.g. "IRA") holding provider blocks and instruments of its own
+    Account(Entries<Section>),
+}
+
+/// Splits a "provider:symbol" entry into the provider key it names and the symbol.
+/// A lot's own provider takes precedence over the prefix, and symbols with neither
+/// stay with the key of their block
+pub(crate) fn resolve_provider_key(block_key: &str, symbol: String, lot: &Lot) -> (String, String) {
+    let (prefix_key, name) = match symbol.split_once(':') {
+        Some((provider_key, name)) if providers::is_provider_key(provider_key) => {
+            (provider_key.to_owned(), name.to_owned())
+        }
+        _ => (block_key.to_owned(), symbol),
+    };
+    (lot.provider.clone().unwrap_or(prefix_key), name)
+}
+
+/// Sums the quantities of the symbols listed more than once in a provider block
+/// with the same dates and prices, keeping the symbols in file order.
+/// Entries of a symbol with different dates or prices are distinct lots.
+pub(crate) fn merge_duplicate_symbols(
+    provider_key: &str,
+    block: Entries<Lot>,
+) -> Vec<(String, Lot)> {
+    let mut stocks: Vec<(String, Lot)> = vec![];
+    for (symbol, lot) in block.0 {
+        match stocks
+            .iter_mut()
+            .find(|(name, existing)| *name == symbol && existing.same_transaction(&lot))
+        {
+            Some((_, existing)) => {
+                eprintln!(
+                    "Warning: {symbol} is listed more than once under {provider_key}, summing the quantities"
+                );
+                existing.quantity += lot.quantity;
+                if let (Some(amount), Some(other)) = (&mut existing.amount, lot.amount) {
+                    *amount += other;
+                }
+                // the sells being the same, each one sells from both quantities
+                for (sell, other) in existing.sells.iter_mut().zip(&lot.sells) {
+                    sell.quantity += other.quantity;
+                }
+            }
+            None => stocks.push((symbol, lot)),
+        }
+    }
+    stocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(json: &str) -> Entries<Lot> {
+        let holdings: Entries<Holding> = serde_json::from_str(json).unwrap();
+        Entries(
+            holdings
+                .0
+                .into_iter()
+                .map(|(symbol, holding)| (symbol, holding.into()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn sums_the_quantities_of_a_repeated_symbol() {
+        let stocks = merge_duplicate_symbols("Yahoo", block(r#"{"AAPL": 10, "AAPL": 5}"#));
+        assert_eq!(stocks.len(), 1);
+        assert_eq!(stocks[0].1.quantity, 15.);
+    }
+
+    #[test]
+    fn sums_the_amounts_of_a_repeated_investment() {
+        let investment = r#"{"amount": 500, "buy_date": "2024-03-01"}"#;
+        let json = format!(r#"{{"VWCE.DE": {investment}, "VWCE.DE": {investment}}}"#);
+        let stocks = merge_duplicate_symbols("Yahoo", block(&json));
+        assert_eq!(stocks.len(), 1);
+        assert_eq!(stocks[0].1.amount, Some(1000.));
+    }
+
+    #[test]
+    fn keeps_an_investment_apart_from_a_quantity() {
+        let json = r#"{"VWCE.DE": {"amount": 500, "buy_date": "2024-03-01"},
+            "VWCE.DE": {"quantity": 4, "buy_date": "2024-03-01"}}"#;
+        let stocks = merge_duplicate_symbols("Yahoo", block(json));
+        assert_eq!(stocks.len(), 2);
+        assert_eq!(stocks[0].1.amount, Some(500.));
+        assert_eq!(stocks[1].1.quantity, 4.);
+    }
+}
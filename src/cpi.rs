@@ -0,0 +1,105 @@
+use std::fs;
+
+use chrono::NaiveDate;
+
+/// A consumer price index series, in date order
+#[derive(Debug)]
+pub(crate) struct Cpi(Vec<(NaiveDate, f64)>);
+
+impl Cpi {
+    /// Reads a CSV file of "date,index" lines, the dates being days (YYYY-MM-DD) or
+    /// months (YYYY-MM, standing for their first day). A header line is skipped
+    pub(crate) fn from_csv(file: &str) -> Result<Self, std::io::Error> {
+        let content = fs::read_to_string(file)
+            .map_err(|_| std::io::Error::other(format!("Unable to open {file}")))?;
+        let mut series = vec![];
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let parsed = line.split_once(',').and_then(|(date, index)| {
+                let date = date.trim();
+                let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                    .or_else(|_| NaiveDate::parse_from_str(&format!("{date}-01"), "%Y-%m-%d"))
+                    .ok()?;
+                Some((date, index.trim().parse::<f64>().ok()?))
+            });
+            match parsed {
+                Some((date, index)) if index > 0. => series.push((date, index)),
+                _ if i == 0 => {}
+                _ => {
+                    return Err(std::io::Error::other(format!(
+                        "Invalid line {} in {file}: {line}",
+                        i + 1
+                    )))
+                }
+            }
+        }
+        if series.is_empty() {
+            return Err(std::io::Error::other(format!("No index values in {file}")));
+        }
+        series.sort_by_key(|(date, _)| *date);
+        Ok(Self(series))
+    }
+
+    /// The latest index published on or before the date
+    pub(crate) fn index_on(&self, date: NaiveDate) -> Option<f64> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(index_date, _)| *index_date <= date)
+            .map(|(_, index)| *index)
+    }
+
+    /// The values expressed in the money of the first of their dates covered by
+    /// the index, leaving out the dates preceding the index
+    pub(crate) fn deflate(&self, values: &[(NaiveDate, f64)]) -> Vec<(NaiveDate, f64)> {
+        let Some(base) = values.iter().find_map(|(date, _)| self.index_on(*date)) else {
+            return vec![];
+        };
+        values
+            .iter()
+            .filter_map(|(date, value)| Some((*date, value * base / self.index_on(*date)?)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MONTHLY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/cpi/monthly.csv");
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn reads_the_months_and_the_days() {
+        let cpi = Cpi::from_csv(MONTHLY).unwrap();
+        assert_eq!(cpi.index_on(date(2023, 12, 31)), None);
+        assert_eq!(cpi.index_on(date(2024, 2, 1)), Some(102.));
+        assert_eq!(cpi.index_on(date(2024, 3, 14)), Some(102.));
+        assert_eq!(cpi.index_on(date(2024, 3, 15)), Some(105.));
+    }
+
+    #[test]
+    fn deflates_to_the_money_of_the_first_day() {
+        let cpi = Cpi::from_csv(MONTHLY).unwrap();
+        let values = [
+            (date(2023, 12, 29), 990.),
+            (date(2024, 1, 10), 1000.),
+            (date(2024, 2, 5), 1020.),
+            (date(2024, 3, 20), 1155.),
+        ];
+        assert_eq!(
+            cpi.deflate(&values),
+            [
+                (date(2024, 1, 10), 1000.),
+                (date(2024, 2, 5), 1000.),
+                (date(2024, 3, 20), 1100.),
+            ]
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use chrono::NaiveDate;
+
+/// How far each value is below the highest value before it, as a fraction of that
+/// peak: 0 at a new high, -0.2 when 20% below it
+pub(crate) fn underwater(values: &[(NaiveDate, f64)]) -> Vec<(NaiveDate, f64)> {
+    let mut peak = f64::MIN;
+    values
+        .iter()
+        .map(|(date, value)| {
+            peak = peak.max(*value);
+            let drawdown = if peak > 0. { value / peak - 1. } else { 0. };
+            (*date, drawdown)
+        })
+        .collect()
+}
+
+/// The deepest decline from a peak: its size as a negative fraction, the date of
+/// the peak and the date of the trough. None if the values never declined
+pub(crate) fn max_drawdown(values: &[(NaiveDate, f64)]) -> Option<(f64, NaiveDate, NaiveDate)> {
+    let mut peak: Option<(NaiveDate, f64)> = None;
+    let mut deepest: Option<(f64, NaiveDate, NaiveDate)> = None;
+    for (date, value) in values {
+        match peak {
+            Some((_, peak_value)) if *value <= peak_value => {
+                if peak_value <= 0. {
+                    continue;
+                }
+                let drawdown = value / peak_value - 1.;
+                if drawdown < deepest.map_or(0., |(deepest, _, _)| deepest) {
+                    deepest = peak.map(|(peak_date, _)| (drawdown, peak_date, *date));
+                }
+            }
+            _ => peak = Some((*date, *value)),
+        }
+    }
+    deepest
+}
+
+/// The first date after a trough on which the value is back to the peak's, None
+/// if it never recovered
+pub(crate) fn recovery(
+    values: &[(NaiveDate, f64)],
+    peak: NaiveDate,
+    trough: NaiveDate,
+) -> Option<NaiveDate> {
+    let peak_value = values.iter().find(|(date, _)| *date == peak)?.1;
+    values
+        .iter()
+        .find(|(date, value)| *date > trough && *value >= peak_value)
+        .map(|(date, _)| *date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(values: &[f64]) -> Vec<(NaiveDate, f64)> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(day, value)| {
+                (
+                    NaiveDate::from_ymd_opt(2024, 3, day as u32 + 1).unwrap(),
+                    *value,
+                )
+            })
+            .collect()
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    #[test]
+    fn finds_the_deepest_decline() {
+        // a 10% decline, then a deeper 25% one from a new peak
+        let values = series(&[100., 90., 110., 120., 100., 90., 130.]);
+        let (drawdown, peak, trough) = max_drawdown(&values).unwrap();
+        assert!((drawdown + 0.25).abs() < 1e-12);
+        assert_eq!((peak, trough), (date(4), date(6)));
+    }
+
+    #[test]
+    fn finds_the_recovery_date() {
+        let values = series(&[100., 120., 90., 110., 120., 125.]);
+        let (_, peak, trough) = max_drawdown(&values).unwrap();
+        assert_eq!((peak, trough), (date(2), date(3)));
+        assert_eq!(recovery(&values, peak, trough), Some(date(5)));
+    }
+
+    #[test]
+    fn has_no_recovery_while_below_the_peak() {
+        let values = series(&[100., 120., 90., 110., 115.]);
+        let (_, peak, trough) = max_drawdown(&values).unwrap();
+        assert_eq!(recovery(&values, peak, trough), None);
+    }
+
+    #[test]
+    fn has_no_drawdown_without_a_decline() {
+        assert_eq!(max_drawdown(&series(&[100., 100., 110.])), None);
+        assert_eq!(
+            underwater(&series(&[100., 80., 120.])),
+            series(&[0., -0.19999999999999996, 0.])
+        );
+    }
+}
@@ -0,0 +1,35 @@
+use chrono::NaiveDate;
+
+/// The least squares line through the values, fitted against their position in
+/// the series so that the days without a value (e.g. weekends) don't count,
+/// extended to the given later dates. The first point is the line's value on
+/// the last date of the series. None for fewer than two values
+pub(crate) fn linear_projection(
+    values: &[(NaiveDate, f64)],
+    dates: &[NaiveDate],
+) -> Option<Vec<(NaiveDate, f64)>> {
+    let (last_date, _) = values.last()?;
+    if values.len() < 2 {
+        return None;
+    }
+    let n = values.len() as f64;
+    let mean_x = (n - 1.) / 2.;
+    let mean_y = values.iter().map(|(_, value)| value).sum::<f64>() / n;
+    let (covariance, variance) =
+        values
+            .iter()
+            .enumerate()
+            .fold((0., 0.), |(covariance, variance), (x, (_, y))| {
+                let dx = x as f64 - mean_x;
+                (covariance + dx * (y - mean_y), variance + dx * dx)
+            });
+    let slope = covariance / variance;
+    let at = |x: f64| mean_y + slope * (x - mean_x);
+    Some(
+        std::iter::once(*last_date)
+            .chain(dates.iter().copied())
+            .enumerate()
+            .map(|(i, date)| (date, at(n - 1. + i as f64)))
+            .collect(),
+    )
+}
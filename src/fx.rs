@@ -0,0 +1,173 @@
+use chrono::NaiveDate;
+
+use crate::provider::Provider;
+
+/// The Frankfurter API, serving the reference rates of the European Central Bank
+pub(crate) const DEFAULT_BASE_URL: &str = "https://api.frankfurter.app";
+
+/// The User-Agent of the requests unless told otherwise
+const DEFAULT_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Get the daily exchange rates of currency pairs from the Frankfurter API
+/// E.g. https://api.frankfurter.app/2024-03-01?from=EUR&to=USD
+#[derive(Debug)]
+pub struct Fx {
+    /// the API's scheme and host
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl Fx {
+    pub(crate) fn new(base_url: &str, user_agent: Option<&str>) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent(user_agent.unwrap_or(DEFAULT_USER_AGENT))
+            .build();
+        Self {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            client: client.unwrap(),
+        }
+    }
+
+    /// The currencies of a pair, e.g. EUR and USD for EURUSD, the price of a unit
+    /// of the first in the second
+    fn currencies(pair: &str) -> Result<(&str, &str), std::io::Error> {
+        if pair.len() != 6 || !pair.chars().all(|c| c.is_ascii_uppercase()) {
+            return Err(std::io::Error::other(format!(
+                "FX: invalid currency pair {pair}, expected e.g. EURUSD"
+            )));
+        }
+        Ok(pair.split_at(3))
+    }
+
+    /// The raw rate of a pair on a date
+    async fn query(&self, pair: &str, date: NaiveDate) -> Result<String, std::io::Error> {
+        let (from, to) = Self::currencies(pair)?;
+        let url = format!("{}/{date}?from={from}&to={to}", self.base_url);
+        let response = self.client.get(&url).send().await.map_err(|_| {
+            std::io::Error::other(format!("FX: Invalid response while querying for {pair}"))
+        })?;
+        if !response.status().is_success() {
+            return Err(std::io::Error::other(format!(
+                "FX: HTTP status {} while querying for {pair}",
+                response.status()
+            )));
+        }
+        response.text().await.map_err(|_| {
+            std::io::Error::other(format!(
+                "FX: Invalid text in response while querying for {pair}"
+            ))
+        })
+    }
+
+    /// The rate of a response and the date it was published on, the last working
+    /// day on or before the date queried
+    fn parse_rate(pair: &str, response: &str) -> Result<(f64, String), std::io::Error> {
+        let (_, to) = Self::currencies(pair)?;
+        let json: serde_json::Value = serde_json::from_str(response)
+            .map_err(|e| std::io::Error::other(format!("FX: invalid rate for {pair}: {e}")))?;
+        let rate = json
+            .get("rates")
+            .and_then(|rates| rates.get(to))
+            .and_then(serde_json::Value::as_f64)
+            .ok_or_else(|| {
+                std::io::Error::other(format!(
+                    "FX: error retrieving the rates.{to} key for {pair}"
+                ))
+            })?;
+        let date = json
+            .get("date")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or_default();
+        Ok((rate, date.to_owned()))
+    }
+
+    /// Describes how the rate of a pair is derived, for the explain command
+    pub(crate) async fn explain(
+        &self,
+        pair: &str,
+        date: NaiveDate,
+    ) -> Result<String, std::io::Error> {
+        let response = self.query(pair, date).await?;
+        let (rate, published) = Self::parse_rate(pair, &response)?;
+        Ok(format!(
+            "The ECB reference rate published on {published}, the last one on or before {date}: {rate}"
+        ))
+    }
+}
+
+impl Provider for Fx {
+    type ErrorType = std::io::Error;
+
+    fn get_provider_name(&self) -> String {
+        "Frankfurter".to_owned()
+    }
+
+    /// Downloads the rate of a currency pair (e.g. EURUSD) on a date, that of the
+    /// previous working day on weekends and holidays, priced in the second currency
+    async fn download_price(
+        &self,
+        pair: String,
+        date: NaiveDate,
+    ) -> Result<(String, NaiveDate, f64, String), Self::ErrorType> {
+        let response = self.query(&pair, date).await?;
+        let (rate, _) = Self::parse_rate(&pair, &response)?;
+        let (_, to) = Self::currencies(&pair)?;
+        let currency = to.to_owned();
+        Ok((pair, date, rate, currency))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{not_found, ok, serve};
+
+    const EUR_USD: &str = include_str!("../fixtures/frankfurter/eur_usd.json");
+    const NOT_FOUND: &str = include_str!("../fixtures/frankfurter/not_found.json");
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    #[tokio::test]
+    async fn takes_the_rate_of_the_pair() {
+        let (base_url, requests) = serve(vec![ok(EUR_USD)]);
+        let (pair, day, rate, currency) = Fx::new(&base_url, None)
+            .download_price("EURUSD".to_owned(), date(1))
+            .await
+            .unwrap();
+        assert_eq!((pair.as_str(), day, rate), ("EURUSD", date(1), 1.0823));
+        assert_eq!(currency, "USD");
+        assert!(requests.lock().unwrap()[0].starts_with("get /2024-03-01?from=eur&to=usd "));
+    }
+
+    #[tokio::test]
+    async fn takes_the_previous_rate_on_weekends() {
+        // no rate is published on Saturdays, Friday's is returned
+        let (base_url, _) = serve(vec![ok(EUR_USD)]);
+        let (_, day, rate, _) = Fx::new(&base_url, None)
+            .download_price("EURUSD".to_owned(), date(2))
+            .await
+            .unwrap();
+        assert_eq!((day, rate), (date(2), 1.0823));
+    }
+
+    #[tokio::test]
+    async fn fails_on_an_unknown_currency() {
+        let (base_url, _) = serve(vec![not_found(NOT_FOUND)]);
+        let error = Fx::new(&base_url, None)
+            .download_price("XYZUSD".to_owned(), date(1))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("404"));
+    }
+
+    #[tokio::test]
+    async fn rejects_an_invalid_pair() {
+        let error = Fx::new("http://127.0.0.1:9", None)
+            .download_price("EUR/USD".to_owned(), date(1))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("invalid currency pair"));
+    }
+}
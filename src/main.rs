@@ -1,218 +1,2399 @@
-use chrono::{Datelike, Days, Utc, Weekday};
-use clap::Parser;
-use providers::Providers;
+use chrono::{Datelike, Days, Local, NaiveDateTime, NaiveTime, Utc, Weekday};
+use clap::{Parser, Subcommand, ValueEnum};
+use cpi::Cpi;
+use portfolio::{Alert, Instrument, Portfolio, QuoteKey, RealizedGain};
+use price_cacher::{CacheSettings, CacheStats, PriceCacher};
+use providers::ProviderSettings;
+use rgb::RGB8;
+use serde::Serialize;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap},
     fs,
-    sync::Arc,
+    io::{self, Write},
+    path::PathBuf,
+    process,
+    time::{Duration, Instant},
 };
-use textplots::{Chart, LabelBuilder, Plot, Shape};
-use tokio;
+use table::Table;
+use textplots::{Chart, ColorPlot, LabelBuilder, Plot, Shape};
 
+mod alphavantage;
+mod cash;
+mod coingecko;
+mod config;
+mod cpi;
+mod drawdown;
+mod forecast;
+mod fx;
+mod ofx;
+mod parquet;
+mod portfolio;
 mod price_cacher;
-use price_cacher::PriceCacher;
 mod provider;
 mod providers;
+mod splits;
+mod svg;
+mod table;
+#[cfg(test)]
+mod test_server;
 mod xfra;
+mod xirr;
 mod yfinance;
+use yfinance::{PriceField, Session};
 
 use chrono::NaiveDate;
 
+/// Colors of the series when several portfolios share a chart
+const CHART_COLORS: [(&str, RGB8); 4] = [
+    ("red", RGB8::new(255, 0, 0)),
+    ("green", RGB8::new(0, 255, 0)),
+    ("blue", RGB8::new(0, 128, 255)),
+    ("yellow", RGB8::new(255, 255, 0)),
+];
+
+/// The width of the charts, in braille dots
+const CHART_WIDTH: u32 = 150;
+
+/// The eighths of a block used to draw the end of the horizontal bars
+const PARTIAL_BLOCKS: [char; 7] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+/// What to do when the last day of the range lacks quotes for some instruments, or
+/// its session isn't over yet
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum FinalDayFallback {
+    /// Carry the previous close over to the final day
+    PreviousClose,
+    /// Drop the incomplete final day
+    Skip,
+    /// Exit with an error
+    Error,
+}
+
+/// How the quotes whose download failed are valued
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum OnError {
+    /// Leave them out, as if the instrument wasn't held that day
+    Skip,
+    /// Value them at 0
+    Zero,
+    /// Carry the instrument's previous price over
+    Carry,
+}
+
+/// Which value the return over the period is computed from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ReturnBase {
+    /// The value on the first day with any quote
+    FirstAvailable,
+    /// The value on the first day every instrument has a fetched quote
+    FirstFullDay,
+    /// Each instrument's value at its first quote, as if it was bought then
+    BuyWeighted,
+}
+
+/// How the total value is broken down
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum GroupBy {
+    /// By the kind of instruments their provider prices: equities/ETFs or cash
+    AssetClass,
+    /// By the account holding them
+    Account,
+}
+
+/// How the values are drawn on the charts
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ChartStyle {
+    /// Joined by straight lines
+    Line,
+    /// Held until the next value, as a daily close is
+    Steps,
+    /// Unjoined dots, clearer for sparse values
+    Points,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Charts several portfolios side by side
+    Compare {
+        /// The JSON configuration files to compare
+        #[arg(short, long = "file", required = true)]
+        files: Vec<String>,
+    },
+    /// Lists the configured instruments without fetching any price
+    List {
+        /// The JSON configuration file
+        #[arg(short, long)]
+        file: String,
+    },
+    /// Downloads the prices of a date range into the cache, e.g. before going offline
+    Seed {
+        /// The JSON configuration file
+        #[arg(short, long)]
+        file: String,
+
+        /// The first day to download (YYYY-MM-DD)
+        #[arg(long)]
+        from: NaiveDate,
+
+        /// The last day to download (YYYY-MM-DD)
+        #[arg(long)]
+        to: NaiveDate,
+    },
+    /// Warns about the instruments crossing their stop_price or alert_pct over the
+    /// period, exiting with 1 if any did
+    Alerts {
+        /// The JSON configuration file
+        #[arg(short, long)]
+        file: String,
+    },
+    /// Shows how the price of an instrument on a date is derived
+    Explain {
+        /// The JSON configuration file
+        #[arg(short, long)]
+        file: String,
+
+        /// The symbol as listed in the configuration file
+        symbol: String,
+
+        /// The day of the price (YYYY-MM-DD)
+        date: NaiveDate,
+    },
+    /// Lists the gains realized by the lots sold during a tax year
+    TaxReport {
+        /// The JSON configuration file
+        #[arg(short, long)]
+        file: String,
+
+        /// The year the lots were sold in
+        #[arg(long)]
+        tax_year: i32,
+
+        /// Prints comma separated values instead of a table
+        #[arg(long, default_value_t = false)]
+        csv: bool,
+    },
+    /// Lists the jumps between the cached prices of each instrument which look like
+    /// stock splits, e.g. a price halving overnight
+    DetectSplits {
+        /// The JSON configuration file
+        #[arg(short, long)]
+        file: String,
+    },
+    /// Lists how the tickers were matched to provider symbols, flagging the searches
+    /// which returned several quotes
+    Resolutions,
+    /// Lists the provider keys the configuration file can use
+    ListProviders,
+    /// Replays the dated buys and sells of the lots at the historical prices, charting
+    /// the equity over the period
+    Simulate {
+        /// The JSON configuration file listing the trades as lots
+        #[arg(short, long)]
+        file: String,
+    },
+    /// Charts the daily total value over the period and lists it
+    History {
+        /// The JSON configuration file
+        #[arg(short, long)]
+        file: String,
+
+        /// Reads the totals saved by --save-snapshot instead of valuing the current
+        /// holdings, showing the past holdings' values
+        #[arg(long, default_value_t = false)]
+        from_snapshots: bool,
+    },
+    /// Writes the daily prices of the instruments and the total value over the period
+    /// to a Parquet file, one row per date and one column per symbol
+    ExportParquet {
+        /// The JSON configuration file
+        #[arg(short, long)]
+        file: String,
+
+        /// The Parquet file written
+        output: String,
+    },
+}
+
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
-    /// The JSON configuration file
-    #[arg(short, long)]
-    file: String,
+    /// The JSON configuration file, - to read it from stdin
+    #[arg(short, long, required_unless_present = "ofx", conflicts_with = "ofx")]
+    file: Option<String>,
+
+    /// An OFX/QFX statement whose stock positions are read instead of a configuration
+    /// file, priced by the default provider
+    #[arg(long)]
+    ofx: Option<String>,
 
     /// The number of days to look back
-    #[arg(long, default_value_t = 10)]
+    #[arg(long, global = true, default_value_t = 10)]
     days: usize,
 
     /// Displays additional debug information
-    #[arg(long, default_value_t = false)]
+    #[arg(long, global = true, default_value_t = false)]
     debug: bool,
 
     /// Extends the last known price in case no data exists
-    #[arg(long, default_value_t = true)]
+    #[arg(long, global = true, default_value_t = true)]
     extend_price: bool,
 
+    /// How the values are drawn on the charts
+    #[arg(long, global = true, value_enum, default_value_t = ChartStyle::Line)]
+    chart_style: ChartStyle,
+
     /// display the daily portfolio value
     #[arg(long, default_value_t = false)]
     display_daily_value: bool,
+
+    /// How to value the quotes whose download failed
+    #[arg(long, global = true, value_enum, default_value_t = OnError::Skip)]
+    on_error: OnError,
+
+    /// How to handle missing quotes on the final day
+    #[arg(long, global = true, value_enum, default_value_t = FinalDayFallback::PreviousClose)]
+    final_day_fallback: FinalDayFallback,
+
+    /// Exchange suffix tried first for Yahoo tickers without one (e.g. .DE)
+    #[arg(long, global = true)]
+    default_exchange_suffix: Option<String>,
+
+    /// Seconds Yahoo's symbol search may take, the ticker being used as it is when
+    /// the search fails or takes longer
+    #[arg(long, global = true, default_value_t = 10)]
+    search_timeout: u64,
+
+    /// The weekdays the exchange is closed (e.g. fri,sat)
+    #[arg(long, global = true, value_delimiter = ',', default_value = "sat,sun")]
+    non_trading_days: Vec<Weekday>,
+
+    /// The time the exchange closes, in UTC (e.g. 21:00), so that the final day's
+    /// prices are known to be intraday until then
+    #[arg(long, global = true)]
+    close_time: Option<NaiveTime>,
+
+    /// The early-close days of the exchange (e.g. 2024-11-29,2024-12-24)
+    #[arg(long, global = true, value_delimiter = ',')]
+    half_days: Vec<NaiveDate>,
+
+    /// The time the exchange closes on the half-days, in UTC
+    #[arg(long, global = true, default_value = "18:00")]
+    half_day_close: NaiveTime,
+
+    /// Prints each instrument's money-weighted annual return (XIRR) from its lots
+    #[arg(long, default_value_t = false)]
+    xirr: bool,
+
+    /// Breaks the total value down into groups
+    #[arg(long, value_enum)]
+    group_by: Option<GroupBy>,
+
+    /// A partial configuration file whose entries replace the configuration file's
+    /// entries of the same symbols, e.g. to try out a few changed positions
+    #[arg(long, global = true)]
+    overlay: Option<String>,
+
+    /// Only reports on the instruments of this account of the configuration file
+    #[arg(long, global = true)]
+    account: Option<String>,
+
+    /// Prints each instrument's quote currency and the value held in each currency
+    #[arg(long, default_value_t = false)]
+    currency_per_instrument: bool,
+
+    /// Adds a column to the instrument tables with the days since each instrument's
+    /// latest fetched quote, 0 when it's up to date
+    #[arg(long, default_value_t = false)]
+    quote_age_column: bool,
+
+    /// Saves the total value and the instruments' values to the cache, for the
+    /// history command to chart the net worth over time
+    #[arg(long, default_value_t = false)]
+    save_snapshot: bool,
+
+    /// Prints each instrument's yield and the portfolio's average yield weighted by value
+    #[arg(long, default_value_t = false)]
+    show_yield: bool,
+
+    /// Prints how much each instrument contributed to the return over the period
+    #[arg(long, default_value_t = false)]
+    show_contribution: bool,
+
+    /// Prints each instrument's return over the period relative to the whole
+    /// portfolio's, the best performing first
+    #[arg(long, default_value_t = false)]
+    benchmark_from_portfolio: bool,
+
+    /// Redraws the chart every this many seconds, refreshing only the current day's prices
+    #[arg(long)]
+    watch: Option<u64>,
+
+    /// Numbers the buy and sell dates of the lots under the chart and lists them below
+    #[arg(long, default_value_t = false)]
+    annotate_transactions: bool,
+
+    /// Extends the chart with the linear trend of the period over this many
+    /// trading days, dashed
+    #[arg(long)]
+    forecast: Option<usize>,
+
+    /// Prints a single line with the total value, the change since the previous
+    /// day and over the period, e.g. for a status bar. Only the prices of those
+    /// days are downloaded
+    #[arg(long, default_value_t = false)]
+    compact: bool,
+
+    /// Prints the daily total values as a JSON array instead of the chart
+    #[arg(long, default_value_t = false, conflicts_with_all = ["compact", "watch"])]
+    json: bool,
+
+    /// Like --json, indented for reading
+    #[arg(long, default_value_t = false, conflicts_with_all = ["compact", "watch", "json", "ndjson"])]
+    json_pretty: bool,
+
+    /// Prints the daily total values as newline-delimited JSON, one object per date
+    #[arg(long, default_value_t = false, conflicts_with_all = ["compact", "watch", "json"])]
+    ndjson: bool,
+
+    /// Computes the total value and the per-instrument breakdowns for this date
+    /// instead of the last day of the chart, falling back on the latest quote before it
+    #[arg(long, conflicts_with = "watch")]
+    as_of: Option<NaiveDate>,
+
+    /// Prints since when the portfolio has been worth more, or less, than its cost basis
+    #[arg(long, default_value_t = false)]
+    break_even: bool,
+
+    /// Prints the return of each lot since its buy_date, whatever the --days window
+    #[arg(long, default_value_t = false)]
+    since_inception: bool,
+
+    /// Prints each instrument's cost basis and profit or loss, realized by its sales
+    /// and unrealized on what's still held
+    #[arg(long, default_value_t = false)]
+    pnl: bool,
+
+    /// Draws a bar chart of each instrument's current value
+    #[arg(long, default_value_t = false)]
+    bar_chart: bool,
+
+    /// The trading session Yahoo prices are taken from
+    #[arg(long, global = true, value_enum, default_value_t = Session::Regular)]
+    session: Session,
+
+    /// The field of the daily Yahoo quotes taken as the price
+    #[arg(long, global = true, value_enum, default_value_t = PriceField::Close)]
+    price_field: PriceField,
+
+    /// The provider of the instruments in the top-level "instruments" list
+    #[arg(long, global = true, default_value = "Yahoo")]
+    default_provider: String,
+
+    /// The scheme and host XFRA prices are queried from, e.g. a caching proxy
+    #[arg(long, global = true, default_value = xfra::DEFAULT_BASE_URL)]
+    xfra_base_url: String,
+
+    /// The User-Agent header sent to the providers, e.g. a browser's when the
+    /// default one gets blocked. XFRA's default is livestock's name and version
+    #[arg(long, global = true)]
+    user_agent: Option<String>,
+
+    /// The number of threads downloading the prices, each with its own connection to the cache
+    #[arg(long, global = true, default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..))]
+    worker_threads: u16,
+
+    /// Reports how long fetching and computing took, and the cache usage
+    #[arg(long, global = true, default_value_t = false)]
+    profile: bool,
+
+    /// Overrides the quantity of an instrument (e.g. AAPL=100), can be repeated
+    #[arg(long = "override", global = true, value_parser = parse_override)]
+    overrides: Vec<(String, f64)>,
+
+    /// Limits a provider to the prices of the past days (e.g. XFRA=30), skipping the
+    /// older dates instead of requesting them, can be repeated
+    #[arg(long, global = true, value_parser = parse_max_history)]
+    max_history: Vec<(String, u32)>,
+
+    /// Rounds the prices saved to the cache to this many decimals
+    #[arg(long, global = true)]
+    cache_price_decimals: Option<u32>,
+
+    /// Downloads every price again instead of reading the cache, still caching the
+    /// downloaded prices unless --no-cache-write is given
+    #[arg(long, global = true, default_value_t = false)]
+    no_cache: bool,
+
+    /// Doesn't save the downloaded prices to the cache
+    #[arg(long, global = true, default_value_t = false)]
+    no_cache_write: bool,
+
+    /// Rounds each instrument's value to this many decimals (e.g. 2 for cents) and
+    /// sums them exactly, so that the totals reconcile with a statement
+    #[arg(long, global = true)]
+    currency_precision: Option<u32>,
+
+    /// Reduces the total values by the expense_ratio of the funds having one, over
+    /// the time each lot is held
+    #[arg(long, global = true, default_value_t = false)]
+    model_fees: bool,
+
+    /// Converts the values of the instruments quoted in other currencies to this one
+    /// (e.g. USD) at the daily rates of the FX provider before summing them
+    #[arg(long, global = true, value_parser = parse_currency)]
+    base_currency: Option<String>,
+
+    /// Only reads the prices cached on or before this day and downloads nothing,
+    /// e.g. to rerun a backtest with the data known then
+    #[arg(long, global = true)]
+    cache_as_of: Option<NaiveDate>,
+
+    /// Opens the cache read-only, e.g. one shared by the users of a server: the
+    /// prices it lacks are downloaded without being saved
+    #[arg(long, global = true, default_value_t = false)]
+    cache_readonly: bool,
+
+    /// Appends a JSON line to this file for each price lookup: when, the provider,
+    /// the symbol and dates, whether the cache had it, the latency and any error
+    #[arg(long, global = true)]
+    audit_log: Option<String>,
+
+    /// The SQLite database caching the prices, e.g. one per portfolio, instead of
+    /// ~/.livestock.sql
+    #[arg(long, global = true)]
+    cache_file: Option<PathBuf>,
+
+    /// Seconds the prices of the past week stay cached before being downloaded again,
+    /// e.g. to refresh intraday prices. The older prices never expire
+    #[arg(long, global = true, value_name = "SECONDS")]
+    cache_ttl: Option<u64>,
+
+    /// Skips the chart when fewer than this fraction of the dates have fetched quotes
+    /// for every instrument (e.g. 0.5)
+    #[arg(long, global = true, value_parser = parse_fraction)]
+    min_coverage: Option<f64>,
+
+    /// Flags the prices further than this percentage from both the previous and the
+    /// next day's prices of their instrument (e.g. 50), likely bad ticks
+    #[arg(long, global = true)]
+    validate_prices: Option<f64>,
+
+    /// Exits with an error when more than this fraction of the price downloads fail
+    /// (e.g. 0.2), tolerating a few delisted symbols but not a network outage
+    #[arg(long, global = true, value_parser = parse_fraction)]
+    max_failure_rate: Option<f64>,
+
+    /// A benchmark symbol priced by the default provider, with an optional weight
+    /// (e.g. SPY:0.6), can be repeated to chart a weighted blend
+    #[arg(long = "benchmark", value_parser = parse_benchmark)]
+    benchmarks: Vec<(String, f64)>,
+
+    /// Also draws the value chart, with the benchmark, to this SVG file
+    #[arg(long)]
+    export_chart: Option<String>,
+
+    /// Prints the maximum drawdowns of the portfolio and of the benchmark over the
+    /// period and charts how far each one is below its running peak
+    #[arg(long, default_value_t = false, requires = "benchmarks")]
+    benchmark_drawdown_compare: bool,
+
+    /// Charts the portfolio value in units of this symbol, priced by the default
+    /// provider (e.g. BTC-USD), and prints the total value in them too
+    #[arg(long, value_name = "SYMBOL")]
+    denominate_in: Option<String>,
+
+    /// The value the returns over the period are computed from, the instruments
+    /// lacking quotes on the first day understating it otherwise
+    #[arg(long, global = true, value_enum, default_value_t = ReturnBase::FirstAvailable)]
+    return_base: ReturnBase,
+
+    /// Charts the value in the money of the first day, deflated by the consumer price
+    /// index of --cpi-file, and computes the returns in real terms
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "cpi_file",
+        conflicts_with = "denominate_in"
+    )]
+    inflation_adjust: bool,
+
+    /// A CSV file of consumer price index values, one "YYYY-MM,index" or
+    /// "YYYY-MM-DD,index" line per publication
+    #[arg(long)]
+    cpi_file: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[tokio::main(flavor = "multi_thread", worker_threads = 4)]
-async fn main() {
-    let args = Args::parse();
+fn parse_override(s: &str) -> Result<(String, f64), String> {
+    let (symbol, quantity) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected SYMBOL=QUANTITY, got {s}"))?;
+    let quantity = quantity
+        .parse()
+        .map_err(|e| format!("invalid quantity {quantity}: {e}"))?;
+    Ok((symbol.to_owned(), quantity))
+}
 
-    // get the list of dates
-    let today = Utc::now().naive_utc();
-    let start_day = today.checked_sub_days(Days::new(args.days as u64)).unwrap();
+fn parse_currency(s: &str) -> Result<String, String> {
+    if s.len() != 3 || !s.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(format!("expected a currency code (e.g. USD), got {s}"));
+    }
+    Ok(s.to_ascii_uppercase())
+}
 
-    // read the symbol file
-    let file = match fs::File::open(args.file.clone()) {
-        Ok(f) => f,
-        Err(_) => {
-            eprintln!("Unable to open {}", args.file);
-            return;
-        }
+fn parse_max_history(s: &str) -> Result<(String, u32), String> {
+    let (provider_key, days) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected PROVIDER=DAYS, got {s}"))?;
+    if !providers::is_provider_key(provider_key) {
+        return Err(format!(
+            "unknown provider {provider_key}, expected one of {}",
+            providers::provider_keys()
+        ));
+    }
+    let days = days
+        .parse()
+        .map_err(|e| format!("invalid number of days {days}: {e}"))?;
+    Ok((provider_key.to_owned(), days))
+}
+
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    let fraction = s
+        .parse::<f64>()
+        .map_err(|e| format!("invalid fraction {s}: {e}"))?;
+    if !(0. ..=1.).contains(&fraction) {
+        return Err(format!("{s} is not between 0 and 1"));
+    }
+    Ok(fraction)
+}
+
+fn parse_benchmark(s: &str) -> Result<(String, f64), String> {
+    let Some((symbol, weight)) = s.split_once(':') else {
+        return Ok((s.to_owned(), 1.));
     };
-    let json: serde_json::Value = match serde_json::from_reader(file) {
-        Ok(jv) => jv,
-        Err(e) => {
+    let weight = weight
+        .parse::<f64>()
+        .map_err(|e| format!("invalid weight {weight}: {e}"))?;
+    if weight <= 0. {
+        return Err(format!("the weight of {symbol} must be positive"));
+    }
+    Ok((symbol.to_owned(), weight))
+}
+
+impl Args {
+    /// Whether the portfolio has enough fetched quotes to be charted, warning if not
+    fn has_min_coverage(&self, name: &str, portfolio: &Portfolio, dates: &[NaiveDate]) -> bool {
+        let Some(min_coverage) = self.min_coverage else {
+            return true;
+        };
+        let coverage = portfolio.coverage(dates);
+        if coverage < min_coverage {
             eprintln!(
-                "Unable to parse json in file {}. Error: {}",
-                args.file,
-                e.to_string()
+                "Warning: not charting {name}, only {:.0}% of the dates have quotes for every instrument (minimum {:.0}%)",
+                coverage * 100.,
+                min_coverage * 100.
             );
-            return;
+            return false;
         }
-    };
+        true
+    }
 
-    let maps = json.as_object().unwrap();
+    fn provider_settings(&self) -> ProviderSettings {
+        ProviderSettings {
+            default_exchange_suffix: self.default_exchange_suffix.clone(),
+            session: self.session,
+            price_field: self.price_field,
+            default_provider: self.default_provider.clone(),
+            xfra_base_url: self.xfra_base_url.clone(),
+            user_agent: self.user_agent.clone(),
+            search_timeout: Duration::from_secs(self.search_timeout),
+            max_history: self.max_history.clone(),
+        }
+    }
 
-    let mut portfolio: HashMap<NaiveDate, HashMap<String, f64>> = HashMap::default();
-    let mut quotes_join_handles = vec![];
-    let mut stocks_dict: HashMap<String, u32> = HashMap::default();
+    fn cache_settings(&self) -> CacheSettings {
+        CacheSettings {
+            price_decimals: self.cache_price_decimals,
+            skip_reads: self.no_cache,
+            skip_writes: self.no_cache_write,
+            read_only: self.cache_readonly,
+            connections: self.worker_threads as usize,
+            as_of: self.cache_as_of,
+            audit_log: self.audit_log.clone(),
+            file: self.cache_file.clone(),
+            ttl: self.cache_ttl.map(Duration::from_secs),
+        }
+    }
 
-    for provider_key in maps.keys() {
-        let stocks_str = json.get(provider_key).expect("error parsing config key");
-        let provider_stocks_dict: HashMap<String, u32> =
-            serde_json::from_value(stocks_str.clone()).unwrap();
-        stocks_dict.extend(
-            provider_stocks_dict
-                .iter()
-                .map(|(k, v)| (k.clone(), v.clone())),
-        );
+    /// Reads a configuration file, or the --ofx statement, with the command line's
+    /// provider and cache settings
+    async fn read_portfolio(&self, file: &str) -> Result<Portfolio, std::io::Error> {
+        if self.ofx.as_deref() == Some(file) {
+            let mut portfolio =
+                Portfolio::from_ofx(file, &self.provider_settings(), &self.cache_settings())?;
+            portfolio.set_currency_precision(self.currency_precision);
+            portfolio.set_model_fees(self.model_fees);
+            return Ok(portfolio);
+        }
+        let mut portfolio = Portfolio::from_json(
+            file,
+            self.overlay.as_deref(),
+            &self.provider_settings(),
+            &self.cache_settings(),
+        )?;
+        if let Some(account) = &self.account {
+            portfolio.retain_account(account)?;
+        }
+        portfolio.set_currency_precision(self.currency_precision);
+        portfolio.set_model_fees(self.model_fees);
+        portfolio.resolve_amounts().await?;
+        portfolio.match_sells();
+        Ok(portfolio)
+    }
 
-        let price_cacher = Arc::new(PriceCacher::new());
+    /// The time the session of a date ends, if known
+    fn session_close(&self, date: NaiveDate) -> Option<NaiveTime> {
+        if self.half_days.contains(&date) {
+            Some(self.half_day_close)
+        } else {
+            self.close_time
+        }
+    }
+}
 
-        let provider = Providers::build(provider_key);
-        if provider.is_none() {
-            eprintln!("Invalid provider: {}", provider_key);
-            continue;
+/// The trading days between start (inclusive) and end (exclusive)
+fn trading_days(
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    non_trading_days: &[Weekday],
+) -> Vec<NaiveDate> {
+    let mut dates = vec![];
+    let mut current_date = start;
+    while current_date < end {
+        if !non_trading_days.contains(&current_date.weekday()) {
+            dates.push(current_date.date());
+        }
+        current_date = current_date.checked_add_days(Days::new(1)).unwrap();
+    }
+    dates
+}
+
+/// The first, the second to last and the last of the dates
+fn endpoints(dates: &[NaiveDate]) -> Vec<NaiveDate> {
+    let mut endpoints = dates
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i == 0 || *i + 2 >= dates.len())
+        .map(|(_, date)| *date)
+        .collect::<Vec<_>>();
+    endpoints.dedup();
+    endpoints
+}
+
+/// The trading days of the week ending on the --as-of date when it is before the
+/// last of the dates, so that it has a quote to fall back on
+fn as_of_dates(args: &Args, dates: &[NaiveDate]) -> Vec<NaiveDate> {
+    let Some(as_of) = args
+        .as_of
+        .filter(|as_of| dates.last().is_some_and(|last| as_of < last))
+    else {
+        return vec![];
+    };
+    let end = as_of.and_time(NaiveTime::MIN) + Days::new(1);
+    trading_days(end - Days::new(7), end, &args.non_trading_days)
+}
+
+/// Reads a configuration file and downloads the prices of its instruments
+async fn load_portfolio(args: &Args, file: &str, dates: &[NaiveDate]) -> Option<Portfolio> {
+    let mut portfolio = match args.read_portfolio(file).await {
+        Ok(portfolio) => portfolio,
+        Err(e) => {
+            eprintln!("{e}");
+            return None;
+        }
+    };
+    for (symbol, quantity) in &args.overrides {
+        if let Err(e) = portfolio.override_quantity(symbol, *quantity) {
+            eprintln!("{e}");
+            return None;
         }
-        let provider = Arc::new(provider.unwrap());
-        println!("Querying {}...", provider.get_provider_name());
-
-        let mut current_date = start_day.clone();
-        while current_date < today {
-            if current_date.weekday() != Weekday::Sat && current_date.weekday() != Weekday::Sun {
-                for (ticker, _quantity) in &provider_stocks_dict {
-                    let mticker = ticker.clone(); // moved ticker
-                    let price_cacher_ref = Arc::clone(&price_cacher);
-                    let provider_ref = Arc::clone(&provider);
-                    let date = current_date.into();
-                    let jh = tokio::spawn(async move {
-                        price_cacher_ref
-                            .download_price(provider_ref, mticker, date)
-                            .await
-                    });
-                    quotes_join_handles.push(jh);
+    }
+    fetch_prices(args, &mut portfolio, file, dates).await;
+    if let Some(base_currency) = &args.base_currency {
+        load_fx_rates(args, &mut portfolio, base_currency, dates).await;
+    }
+    Some(portfolio)
+}
+
+/// Downloads the rates converting the other currencies the instruments are quoted in
+/// to the base currency, the days without a rate (e.g. holidays) taking the previous one
+async fn load_fx_rates(
+    args: &Args,
+    portfolio: &mut Portfolio,
+    base_currency: &str,
+    dates: &[NaiveDate],
+) {
+    let currencies = portfolio.foreign_currencies(base_currency);
+    let mut fx_rates = HashMap::new();
+    if !currencies.is_empty() {
+        let pairs = currencies
+            .iter()
+            .map(|currency| format!("{currency}{base_currency}"))
+            .collect::<Vec<_>>();
+        match Portfolio::from_symbols(
+            &pairs,
+            "FX",
+            &args.provider_settings(),
+            &args.cache_settings(),
+        ) {
+            Ok(mut rates) => {
+                // the rates of the trades too, converting what they cost or fetched
+                let mut fx_dates = dates.to_vec();
+                fx_dates.extend(portfolio.trade_dates());
+                fx_dates.sort();
+                fx_dates.dedup();
+                fetch_prices(args, &mut rates, "the FX rates", &fx_dates).await;
+                rates.extend_dates();
+                let keys = rates.instruments().map(Instrument::key).collect::<Vec<_>>();
+                for ((currency, pair), key) in currencies.into_iter().zip(&pairs).zip(&keys) {
+                    let pair_rates = fx_dates
+                        .iter()
+                        .filter_map(|date| Some((*date, rates.price(*date, key)?.0)))
+                        .filter(|(_, rate)| *rate > 0.)
+                        .collect::<BTreeMap<_, _>>();
+                    if pair_rates.is_empty() {
+                        eprintln!(
+                            "Warning: no {pair} rates, the values in {currency} are left unconverted"
+                        );
+                    } else {
+                        fx_rates.insert(currency, pair_rates);
+                    }
                 }
             }
-            current_date = current_date.checked_add_days(Days::new(1)).unwrap();
+            Err(e) => eprintln!("{e}"),
         }
     }
+    portfolio.set_fx_rates(base_currency, fx_rates);
+}
 
-    for j in quotes_join_handles {
-        match j.await.unwrap() {
-            Ok((ticker, date, price)) => {
-                let quantity = stocks_dict.get(&ticker).unwrap();
-                if args.debug {
-                    println!(
-                        "Quote at close for {ticker} on {date}: {price} * {} = {}",
-                        *quantity,
-                        price * (*quantity) as f64
+/// Downloads the prices of a portfolio's instruments and fills in the missing ones
+async fn fetch_prices(args: &Args, portfolio: &mut Portfolio, name: &str, dates: &[NaiveDate]) {
+    let fetch_start = Instant::now();
+    let quiet = args.compact || args.json || args.json_pretty || args.ndjson;
+    let (quotes_join_handles, range_join_handles) = portfolio.get_prices(dates, quiet);
+    let downloads = quotes_join_handles.len()
+        + range_join_handles
+            .iter()
+            .map(|(_, dates, _)| dates.len())
+            .sum::<usize>();
+    let mut errors = portfolio
+        .wait_for_prices(quotes_join_handles, args.debug)
+        .await;
+    errors.extend(
+        portfolio
+            .wait_for_price_ranges(range_join_handles, args.debug)
+            .await,
+    );
+    if let Some(max_failure_rate) = args.max_failure_rate {
+        let failure_rate = errors.len() as f64 / downloads.max(1) as f64;
+        if failure_rate > max_failure_rate {
+            eprintln!(
+                "{} of the {downloads} price downloads for {name} failed ({:.0}%, maximum {:.0}%), aborting",
+                errors.len(),
+                failure_rate * 100.,
+                max_failure_rate * 100.
+            );
+            if let Some(e) = errors.first() {
+                eprintln!("First error: {e}");
+            }
+            process::exit(1);
+        }
+    }
+    let fetch_time = fetch_start.elapsed();
+    if let Some(max_pct) = args.validate_prices {
+        for (name, date, price, previous, next) in portfolio.suspicious_quotes(max_pct) {
+            eprintln!(
+                "Suspicious price: {name} at {price} on {date}, between {previous} and {next}"
+            );
+        }
+    }
+    let computation_start = Instant::now();
+
+    for (date, ticker) in portfolio.failed_quotes() {
+        let price = match args.on_error {
+            OnError::Skip => None,
+            OnError::Zero => Some(0.),
+            OnError::Carry => portfolio.previous_close(date, &ticker),
+        };
+        if let Some(price) = price {
+            portfolio.fill_quote(date, ticker, price);
+        }
+    }
+
+    // the final day is usually incomplete while the market is still open
+    if let Some(final_day) = dates.last().copied() {
+        let missing = portfolio.missing_quotes(final_day);
+        let now = Utc::now().naive_utc();
+        let open_session = args
+            .session_close(final_day)
+            .filter(|close| final_day.and_time(*close) > now);
+        if !missing.is_empty() || open_session.is_some() {
+            match args.final_day_fallback {
+                FinalDayFallback::PreviousClose => portfolio.carry_previous_close(final_day),
+                FinalDayFallback::Skip => portfolio.remove_date(final_day),
+                FinalDayFallback::Error if !missing.is_empty() => {
+                    let missing = missing
+                        .iter()
+                        .map(|(_, symbol)| symbol.as_str())
+                        .collect::<Vec<_>>();
+                    eprintln!("Missing quotes on {final_day} for: {}", missing.join(", "));
+                    process::exit(1);
+                }
+                FinalDayFallback::Error => {
+                    eprintln!(
+                        "The session of {final_day} isn't over, it closes at {} UTC",
+                        open_session.unwrap().format("%H:%M")
                     );
+                    process::exit(1);
                 }
-                let day_quotes = portfolio.entry(date).or_default();
-                day_quotes.insert(ticker, price * (*quantity) as f64);
             }
+        }
+    }
+
+    if args.extend_price {
+        portfolio.extend_dates();
+    }
+    let computation_time = computation_start.elapsed();
+
+    if args.debug {
+        print_quotes(portfolio);
+    }
+    if args.profile {
+        print_profile(name, fetch_time, computation_time, &portfolio.cache_stats());
+    }
+}
+
+/// Prints the fetched quote of each instrument on each date, for --debug
+fn print_quotes(portfolio: &Portfolio) {
+    let mut table = Table::new(&["Date", "Provider", "Symbol", "Price"]);
+    for (date, (provider, symbol), price) in portfolio.quotes() {
+        table.add_row(vec![
+            date.to_string(),
+            provider.clone(),
+            symbol.clone(),
+            price.to_string(),
+        ]);
+    }
+    print!("{table}");
+}
+
+fn print_profile(file: &str, fetch_time: Duration, computation_time: Duration, stats: &CacheStats) {
+    println!("Profile for {file}:");
+    println!("  fetching: {:.3}s", fetch_time.as_secs_f64());
+    println!(
+        "  cache lookups: {:.3}s over all tasks ({} hits, {} misses)",
+        stats.lookup_time.as_secs_f64(),
+        stats.hits,
+        stats.misses
+    );
+    println!("  computation: {:.3}s", computation_time.as_secs_f64());
+    let mut requests = stats.requests.iter().collect::<Vec<_>>();
+    requests.sort();
+    for (provider_name, count) in requests {
+        println!("  requests to {provider_name}: {count}");
+    }
+}
+
+/// Charts each series of values, one x unit per date having a value, so that the
+/// days without any (e.g. weekends) take no room, numbering the dated events under
+/// the x-axis and listing them below
+fn display_chart(
+    style: ChartStyle,
+    series: &[Vec<(NaiveDate, f64)>],
+    projection: &[(NaiveDate, f64)],
+    events: &[(NaiveDate, String)],
+) {
+    if let Some(reason) = degenerate_chart(series) {
+        println!("No chart: {reason}");
+        return;
+    }
+    let mut axis_dates = series
+        .iter()
+        .flatten()
+        .chain(projection)
+        .map(|(date, _)| *date)
+        .collect::<Vec<_>>();
+    axis_dates.sort();
+    axis_dates.dedup();
+    // an event on a day without values is placed on the next date having one
+    let x_of = |date: &NaiveDate| axis_dates.partition_point(|axis_date| axis_date < date) as f32;
+    let to_points = |values: &[(NaiveDate, f64)]| {
+        values
+            .iter()
+            .map(|(date, value)| (x_of(date), *value as f32))
+            .collect::<Vec<_>>()
+    };
+    // one point per day with a value, the line joining the actual values
+    let points = series
+        .iter()
+        .map(|values| to_points(values))
+        .collect::<Vec<_>>();
+    // the projection is drawn dashed, one segment out of two
+    let projection_points = to_points(projection);
+    let dashes = projection_points
+        .windows(2)
+        .step_by(2)
+        .map(|segment| segment.to_vec())
+        .collect::<Vec<_>>();
+    let xmax = points
+        .iter()
+        .flatten()
+        .chain(&projection_points)
+        .map(|(x, _)| *x)
+        .fold(1., f32::max);
+    let shapes = points
+        .iter()
+        .map(|points| match style {
+            ChartStyle::Line => Shape::Lines(points),
+            ChartStyle::Steps => Shape::Steps(points),
+            ChartStyle::Points => Shape::Points(points),
+        })
+        .collect::<Vec<_>>();
+    let dash_shapes = dashes
+        .iter()
+        .map(|dash| Shape::Lines(dash))
+        .collect::<Vec<_>>();
+
+    let mut chart = Chart::new(CHART_WIDTH, 40, 0., xmax);
+    let mut chart = chart.x_label_format(textplots::LabelFormat::None);
+    for (shape, (_, color)) in shapes.iter().zip(CHART_COLORS.iter().cycle()) {
+        chart = if shapes.len() > 1 {
+            chart.linecolorplot(shape, *color)
+        } else {
+            chart.lineplot(shape)
+        };
+    }
+    for shape in &dash_shapes {
+        chart = chart.lineplot(shape);
+    }
+    chart.display();
+
+    if events.is_empty() {
+        return;
+    }
+    // two braille dots per character
+    let mut markers = vec![' '; CHART_WIDTH as usize / 2];
+    for (i, (date, _)) in events.iter().enumerate() {
+        let x = x_of(date);
+        let column =
+            ((x / xmax * (CHART_WIDTH - 1) as f32).round() as usize / 2).min(markers.len() - 1);
+        markers[column] = event_marker(i);
+    }
+    println!("{}", markers.iter().collect::<String>().trim_end());
+    for (i, (date, description)) in events.iter().enumerate() {
+        println!("{} {date} {description}", event_marker(i));
+    }
+}
+
+/// Why the series can't be charted: too few points, a value which isn't a number
+/// or no change at all, which textplots draws as a blank plot
+fn degenerate_chart(series: &[Vec<(NaiveDate, f64)>]) -> Option<&'static str> {
+    let values = series.iter().flatten().map(|(_, value)| *value);
+    if values.clone().any(|value| !value.is_finite()) {
+        return Some("some values aren't numbers, e.g. after a failed download");
+    }
+    if series.iter().all(|values| values.len() < 2) {
+        return Some("fewer than two days with prices");
+    }
+    let min = values.clone().fold(f64::INFINITY, f64::min);
+    let max = values.fold(f64::NEG_INFINITY, f64::max);
+    if min == max {
+        return Some("the value is the same on every day");
+    }
+    None
+}
+
+/// The character marking the i-th event under the chart
+fn event_marker(i: usize) -> char {
+    char::from_digit(i as u32 + 1, 36).unwrap_or('*')
+}
+
+/// Prints the configured instruments
+async fn list_instruments(args: &Args, file: &str) {
+    let portfolio = match args.read_portfolio(file).await {
+        Ok(portfolio) => portfolio,
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+    let accounts = portfolio.has_accounts();
+    let mut table = if accounts {
+        Table::new(&["Account", "Provider", "Symbol", "Quantity"])
+    } else {
+        Table::new(&["Provider", "Symbol", "Quantity"])
+    };
+    for instrument in portfolio.instruments() {
+        let mut row = vec![
+            instrument.get_provider_name(),
+            instrument.get_name().to_owned(),
+            instrument.get_quantity().to_string(),
+        ];
+        if accounts {
+            row.insert(0, instrument.get_account().unwrap_or("-").to_owned());
+        }
+        table.add_row(row);
+    }
+    print!("{table}");
+}
+
+/// Charts several portfolios together and prints their end value and return
+async fn compare(args: &Args, files: &[String], dates: &[NaiveDate]) {
+    if files.len() < 2 {
+        eprintln!("compare needs at least two configuration files");
+        return;
+    }
+    let mut portfolios = vec![];
+    for file in files {
+        match load_portfolio(args, file, dates).await {
+            Some(portfolio) => portfolios.push(portfolio),
+            None => return,
+        }
+    }
+
+    // checks every portfolio so that each one lacking quotes is reported
+    let covered = files
+        .iter()
+        .zip(&portfolios)
+        .filter(|(file, portfolio)| !args.has_min_coverage(file, portfolio, dates))
+        .count()
+        == 0;
+    if args.days > 1 && covered {
+        println!("Portfolio evolution for the past {} days", args.days);
+        display_chart(
+            args.chart_style,
+            &portfolios
+                .iter()
+                .map(Portfolio::value_series)
+                .collect::<Vec<_>>(),
+            &[],
+            &[],
+        );
+    }
+
+    for ((file, portfolio), (color_name, _)) in files
+        .iter()
+        .zip(&portfolios)
+        .zip(CHART_COLORS.iter().cycle())
+    {
+        let value_series = return_series(args, portfolio, &portfolio.value_series());
+        let end_value = value_series
+            .last()
+            .map(|(_, value)| *value)
+            .unwrap_or_default();
+        let period_return = match value_series.first() {
+            Some((_, start_value)) if *start_value != 0. => {
+                format!("{:+.2}%", (end_value / start_value - 1.) * 100.)
+            }
+            _ => "n/a".to_owned(),
+        };
+        println!("{file} ({color_name}): total value {end_value:.2}, return {period_return}");
+    }
+}
+
+/// Downloads into the cache the prices of every instrument for the trading days
+/// between from and to (inclusive), so that later runs can work offline.
+/// The days already cached aren't downloaded again
+async fn seed(args: &Args, file: &str, from: NaiveDate, to: NaiveDate) {
+    let portfolio = match args.read_portfolio(file).await {
+        Ok(portfolio) => portfolio,
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+
+    let dates = trading_days(
+        from.and_time(NaiveTime::MIN),
+        to.succ_opt().unwrap().and_time(NaiveTime::MIN),
+        &args.non_trading_days,
+    );
+
+    let quotes_join_handles = portfolio.get_prices_range(&dates);
+    let total = quotes_join_handles.len();
+    let mut failed = 0;
+    let mut prices = 0;
+    for (done, j) in quotes_join_handles.into_iter().enumerate() {
+        match j.await.unwrap() {
+            Ok(range_prices) => prices += range_prices.len(),
             Err(e) => {
+                failed += 1;
                 if args.debug {
                     eprintln!("Error {e:#?}")
                 }
             }
         }
+        print!("\rSeeded {}/{total} instruments", done + 1);
+        let _ = io::stdout().flush();
     }
+    println!();
+    println!("{prices} prices cached");
+    if failed > 0 {
+        println!("{failed} instruments could not be downloaded");
+    }
+}
 
-    let mut sorted_dates = portfolio.keys().copied().collect::<Vec<_>>();
-    sorted_dates.sort();
-    if args.extend_price && sorted_dates.len() > 1 {
-        // right extend the prices in case they are not present for the latest day{s}
-        // YF is well known for this "feature"
-        let mut tickers = HashSet::new();
-        for (_, portfolio_date) in &portfolio {
-            portfolio_date.keys().for_each(|k| {
-                tickers.insert(k.clone());
-            });
-        }
-        for ticker in tickers {
-            let mut last_price = 0.;
-            for date in &sorted_dates[0..sorted_dates.len()] {
-                let portfolio_date = portfolio.get_mut(date).unwrap();
-                last_price = portfolio_date
-                    .entry(ticker.clone())
-                    .or_insert(last_price.clone())
-                    .clone();
-            }
+/// Prints the alerts triggered by the latest prices, exiting with 1 if there's any
+async fn alerts(args: &Args, file: &str, dates: &[NaiveDate]) {
+    let Some(portfolio) = load_portfolio(args, file, dates).await else {
+        process::exit(2);
+    };
+    let alerts = portfolio.alerts();
+    if alerts.is_empty() {
+        println!("No alerts");
+        return;
+    }
+    for alert in &alerts {
+        match alert {
+            Alert::Stop {
+                name,
+                price,
+                stop_price,
+            } => println!("Alert: {name} at {price:.2} is below its stop price {stop_price:.2}"),
+            Alert::Move {
+                name,
+                change_pct,
+                alert_pct,
+            } => println!(
+                "Alert: {name} moved {change_pct:+.2}% over the past {} days, more than {alert_pct}%",
+                args.days
+            ),
         }
     }
+    process::exit(1);
+}
 
-    if args.debug {
-        println!("{:#?}", portfolio);
+/// Prints where the price of an instrument on a date comes from: the cache or the
+/// provider's response, and whether a report ending on that date fills it in
+async fn explain(args: &Args, file: &str, symbol: &str, date: NaiveDate) {
+    let mut portfolio = match args.read_portfolio(file).await {
+        Ok(portfolio) => portfolio,
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+    {
+        let Some(instrument) = portfolio.instruments().find(|i| i.get_name() == symbol) else {
+            eprintln!("{symbol} is not listed in {file}");
+            return;
+        };
+        println!("{symbol} is priced by {}", instrument.get_provider_name());
+        match portfolio.cached_price(instrument, date) {
+            Some((price, currency)) => println!("Cached on {date}: {price} {currency}"),
+            None => println!("Not cached on {date}, a report would fetch it live"),
+        }
+        match instrument.explain(date).await {
+            Ok(explanation) => println!("{explanation}"),
+            Err(e) => println!("The live fetch failed: {e}"),
+        }
     }
 
+    // replays the fetching of a report ending on that date
+    let start_day = date
+        .checked_sub_days(Days::new(args.days as u64))
+        .unwrap()
+        .and_time(NaiveTime::MIN);
+    let end_day = date.succ_opt().unwrap().and_time(NaiveTime::MIN);
+    let dates = trading_days(start_day, end_day, &args.non_trading_days);
+    fetch_prices(args, &mut portfolio, file, &dates).await;
+    let key = match portfolio.instruments().find(|i| i.get_name() == symbol) {
+        Some(instrument) => instrument.key(),
+        None => return,
+    };
+    match portfolio.price(date, &key) {
+        Some((price, true)) => println!(
+            "A report ending on {date} uses {price}, filled in from a previous day's quote"
+        ),
+        Some((price, false)) => println!("A report ending on {date} uses {price}"),
+        None => println!("A report ending on {date} has no price for {symbol}"),
+    }
+}
+
+/// Prints the realized gains of a tax year, split into short and long term
+async fn tax_report(args: &Args, file: &str, tax_year: i32, csv: bool) {
+    let gains = match args.read_portfolio(file).await {
+        Ok(portfolio) => portfolio.realized_gains(tax_year).await,
+        Err(e) => Err(e),
+    };
+    let gains = match gains {
+        Ok(gains) => gains,
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+    let term = |gain: &RealizedGain| if gain.is_long_term() { "long" } else { "short" };
+
+    if csv {
+        println!("symbol,quantity,bought,sold,cost,proceeds,gain,term");
+        for gain in &gains {
+            println!(
+                "{},{},{},{},{:.2},{:.2},{:.2},{}",
+                gain.name,
+                gain.quantity,
+                gain.buy_date,
+                gain.sell_date,
+                gain.cost,
+                gain.proceeds,
+                gain.gain(),
+                term(gain)
+            );
+        }
+        return;
+    }
+
+    if gains.is_empty() {
+        println!("No lots sold in {tax_year}");
+        return;
+    }
+    let accounts = gains.iter().any(|gain| gain.account.is_some());
+    let mut headers = vec![
+        "Symbol", "Quantity", "Bought", "Sold", "Cost", "Proceeds", "Gain", "Term",
+    ];
+    if accounts {
+        headers.insert(0, "Account");
+    }
+    let mut table = Table::new(&headers);
+    for gain in &gains {
+        let mut row = vec![
+            gain.name.clone(),
+            gain.quantity.to_string(),
+            gain.buy_date.to_string(),
+            gain.sell_date.to_string(),
+            format!("{:.2}", gain.cost),
+            format!("{:.2}", gain.proceeds),
+            format!("{:.2}", gain.gain()),
+            term(gain).to_owned(),
+        ];
+        if accounts {
+            row.insert(0, gain.account.clone().unwrap_or_else(|| "-".to_owned()));
+        }
+        table.add_row(row);
+    }
+    print!("{table}");
+    let (long_term, short_term): (Vec<_>, Vec<_>) =
+        gains.iter().partition(|gain| gain.is_long_term());
+    let short_term_gain: f64 = short_term.iter().map(|gain| gain.gain()).sum();
+    let long_term_gain: f64 = long_term.iter().map(|gain| gain.gain()).sum();
+    println!("Short-term gain: {short_term_gain:.2}");
+    println!("Long-term gain: {long_term_gain:.2}");
+    println!(
+        "Total realized gain: {:.2}",
+        short_term_gain + long_term_gain
+    );
+}
+
+/// Charts the portfolio and prints its total value
+async fn report(args: &Args, file: &str, dates: &[NaiveDate]) {
+    // the one-line summary only needs the period's and the day's changes
+    let mut fetched_dates = if args.compact && args.watch.is_none() {
+        endpoints(dates)
+    } else {
+        dates.to_vec()
+    };
+    fetched_dates.extend(as_of_dates(args, dates));
+    fetched_dates.sort();
+    fetched_dates.dedup();
+    let Some(portfolio) = load_portfolio(args, file, &fetched_dates).await else {
+        return;
+    };
+    if let Some(interval) = args.watch {
+        watch(args, portfolio, interval).await;
+        return;
+    }
+    let sorted_dates = portfolio.sorted_dates();
+    // the day the single-date metrics are computed for
+    let metric_day = match args.as_of {
+        Some(as_of) => {
+            let day = sorted_dates
+                .iter()
+                .rev()
+                .find(|date| **date <= as_of)
+                .copied();
+            if day.is_none() {
+                eprintln!("Warning: no quotes on or before {as_of}");
+            }
+            day
+        }
+        None => sorted_dates.last().copied(),
+    };
+    if let (true, Some(day)) = (args.save_snapshot, metric_day) {
+        if let Err(e) = portfolio.save_snapshot(&snapshot_key(file), day) {
+            eprintln!("{e}");
+        }
+    }
+    // the chart and the daily values keep to the window
+    let window_start = dates.first().copied().unwrap_or_default();
+    let value_series = portfolio.value_series();
+    if args.compact {
+        let value_series = value_series
+            .into_iter()
+            .filter(|(date, _)| metric_day.is_some_and(|day| *date <= day))
+            .collect::<Vec<_>>();
+        print_compact(args, &portfolio, &value_series, window_start);
+        return;
+    }
+    let value_series = value_series
+        .into_iter()
+        .filter(|(date, _)| *date >= window_start)
+        .collect::<Vec<_>>();
+    let numeraire = match &args.denominate_in {
+        Some(symbol) => load_numeraire(args, symbol, &fetched_dates).await,
+        None => None,
+    };
+    // in units of the numeraire, leaving out the dates it has no price for
+    let value_series = match &numeraire {
+        Some(prices) => value_series
+            .into_iter()
+            .filter_map(|(date, value)| {
+                prices
+                    .get(&date)
+                    .filter(|price| **price != 0.)
+                    .map(|price| (date, value / price))
+            })
+            .collect(),
+        None => value_series,
+    };
+    let cpi = match (args.inflation_adjust, &args.cpi_file) {
+        (true, Some(cpi_file)) => match Cpi::from_csv(cpi_file) {
+            Ok(cpi) => Some(cpi),
+            Err(e) => {
+                eprintln!("{e}");
+                return;
+            }
+        },
+        _ => None,
+    };
+    let value_series = match &cpi {
+        Some(cpi) => cpi.deflate(&value_series),
+        None => value_series,
+    };
+    if args.json || args.json_pretty || args.ndjson {
+        print_json(args, &value_series);
+        return;
+    }
+    let benchmark_series = if args.benchmarks.is_empty() {
+        None
+    } else {
+        load_benchmark(args, dates)
+            .await
+            .map(|benchmark_series| match &cpi {
+                Some(cpi) => cpi.deflate(&benchmark_series),
+                None => benchmark_series,
+            })
+    };
+
     //
     // graph and print the total value
     //
-    if args.days > 1 {
-        let empty_day_dict = HashMap::default(); // in case we have missing days (e.g. weekends)
-        println!("Portfolio evolution for the past {} days", args.days);
-        Chart::new(150, 40, 0., portfolio.len() as f32 + 1.0)
-            .x_label_format(textplots::LabelFormat::None)
-            .lineplot(&Shape::Continuous(Box::new(|x| {
-                portfolio
-                    .get(
-                        &start_day
-                            .checked_add_days(Days::new(x.round() as u64))
-                            .unwrap()
-                            .date(),
-                    )
-                    .unwrap_or_else(|| &empty_day_dict)
+    if args.days > 1 && args.has_min_coverage(file, &portfolio, dates) {
+        match &args.denominate_in {
+            Some(symbol) => println!(
+                "Portfolio evolution for the past {} days, in {symbol}",
+                args.days
+            ),
+            None if args.inflation_adjust => println!(
+                "Portfolio evolution for the past {} days, adjusted for inflation",
+                args.days
+            ),
+            None => println!("Portfolio evolution for the past {} days", args.days),
+        }
+        let mut series = vec![value_series.clone()];
+        if let Some(benchmark_series) = &benchmark_series {
+            // scaled to start from the portfolio's value
+            let start_value = value_series
+                .first()
+                .map(|(_, value)| *value)
+                .unwrap_or_default();
+            series.push(
+                benchmark_series
                     .iter()
-                    .map(|(_ticker, price)| *price)
-                    .reduce(|acc, p| acc + p)
-                    .unwrap_or_default() as f32
-            })))
-            .display();
+                    .map(|(date, value)| (*date, value * start_value))
+                    .collect(),
+            );
+        }
+        let projection = match (args.forecast, dates.last()) {
+            (Some(days), Some(last_date)) => {
+                let next_day = last_date.succ_opt().unwrap_or(*last_date);
+                let future_dates = trading_days(
+                    next_day.into(),
+                    (next_day + Days::new(days as u64 * 2 + 7)).into(),
+                    &args.non_trading_days,
+                );
+                forecast::linear_projection(
+                    &value_series,
+                    &future_dates[..days.min(future_dates.len())],
+                )
+                .unwrap_or_default()
+            }
+            _ => vec![],
+        };
+        let events = if args.annotate_transactions {
+            portfolio.transactions(window_start, dates.last().copied().unwrap_or_default())
+        } else {
+            vec![]
+        };
+        display_chart(args.chart_style, &series, &projection, &events);
+        if let Some(path) = &args.export_chart {
+            export_chart(args, path, &series);
+        }
+        if let Some((date, value)) = projection.last().filter(|_| projection.len() > 1) {
+            println!(
+                "Forecast for {date}: {value:.2}, extending the period's linear trend (not a prediction)"
+            );
+        }
+    }
+    let period_series = return_series(args, &portfolio, &value_series);
+    if let Some(benchmark_series) = &benchmark_series {
+        print_benchmark_comparison(args, &period_series, benchmark_series);
+        if args.benchmark_drawdown_compare {
+            compare_drawdowns(args, &period_series, benchmark_series);
+        }
+    }
+    if cpi.is_some() {
+        match (period_series.first(), period_series.last()) {
+            (Some((_, start)), Some((_, end))) if *start != 0. => {
+                println!("Real return: {:+.2}%", (end / start - 1.) * 100.)
+            }
+            _ => println!("Real return: n/a"),
+        }
     }
 
-    // and finally prints the total portfolio value
+    // and finally prints the total portfolio value, flagging the totals
+    // relying on prices carried over from a previous day
+    let estimated = |date: &NaiveDate| match portfolio.portfolio_value_real(*date) {
+        Some(_) => "",
+        None => " (estimated)",
+    };
     if args.display_daily_value {
-        for date in &sorted_dates {
+        for (date, value) in &value_series {
             println!(
-                "Portfolio total value on {date}: {:.2}",
-                portfolio[date]
-                    .iter()
-                    .map(|(_, price)| *price)
-                    .reduce(|acc, p| acc + p)
-                    .unwrap()
+                "Portfolio total value on {date}: {value:.2}{}",
+                estimated(date)
             );
         }
+    }
+    match metric_day {
+        Some(day) if args.as_of.is_some() => println!(
+            "Portfolio total value as of {day}: {:.2}{}",
+            portfolio.portfolio_value(day),
+            estimated(&day)
+        ),
+        Some(_) if args.display_daily_value => {}
+        Some(day) => println!(
+            "Portfolio total value: {:.2}{}",
+            portfolio.portfolio_value(day),
+            estimated(&day)
+        ),
+        None => println!("Portfolio total value: {:.2}", 0.),
+    }
+    if let (Some(symbol), Some(prices), Some(day)) = (&args.denominate_in, &numeraire, metric_day) {
+        match prices.get(&day).filter(|price| **price != 0.) {
+            Some(price) => println!(
+                "Portfolio total value in {symbol}: {:.6}",
+                portfolio.portfolio_value(day) / price
+            ),
+            None => eprintln!("No price for {symbol} on {day}"),
+        }
+    }
+    if let Some(day) = metric_day {
+        print_currency_breakdown(&portfolio, day);
+        let quote_ages = args.quote_age_column.then(|| portfolio.quote_ages(day));
+        let ages = quote_ages.as_ref();
+
+        match args.group_by {
+            Some(GroupBy::AssetClass) => {
+                let values = portfolio.portfolio_value_by_asset_class(day);
+                print_groups(
+                    "Asset class",
+                    &values
+                        .iter()
+                        .map(|(asset_class, value)| (asset_class.to_string(), *value))
+                        .collect::<Vec<_>>(),
+                );
+            }
+            Some(GroupBy::Account) => print_groups(
+                "Account",
+                &portfolio
+                    .portfolio_value_by_account(day)
+                    .into_iter()
+                    .collect::<Vec<_>>(),
+            ),
+            None => {}
+        }
+
+        if args.currency_per_instrument {
+            print_currency_exposure(&portfolio, day, ages);
+        }
+
+        if args.show_yield {
+            print_yields(&portfolio, day, ages);
+        }
+
+        if args.show_contribution {
+            print_contributions(&portfolio.contributions(dates), ages);
+        }
+
+        if args.benchmark_from_portfolio {
+            print_relative_strength(&portfolio.instrument_returns(dates), &period_series, ages);
+        }
+
+        if args.xirr {
+            print_xirrs(&portfolio.instrument_xirrs(day).await, ages);
+        }
+
+        if args.break_even {
+            let value_series = portfolio
+                .value_series()
+                .into_iter()
+                .filter(|(date, _)| *date >= window_start && *date <= day)
+                .collect::<Vec<_>>();
+            match portfolio.cost_basis(day).await {
+                Ok(Some(cost_basis)) => print_break_even(&value_series, cost_basis),
+                Ok(None) => println!("No cost basis: a lot has neither a buy_price nor a buy_date"),
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+
+        if args.since_inception {
+            print_since_inception(&portfolio.since_inception_returns(day).await, ages);
+        }
+
+        if args.pnl {
+            print_pnl(&portfolio.cost_basis_and_pnl(day).await, ages);
+        }
+
+        if args.bar_chart {
+            let mut values = portfolio.instruments_and_values(day);
+            values.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+            display_bar_chart(&values, 60);
+        }
+    }
+}
+
+/// A day's total value in the JSON outputs
+#[derive(Serialize)]
+struct DailyTotal {
+    date: NaiveDate,
+    total: f64,
+}
+
+/// Prints the daily total values as a JSON array, or one JSON object per line
+/// for --ndjson, stopping quietly when the reader goes away (e.g. `| head`)
+fn print_json(args: &Args, value_series: &[(NaiveDate, f64)]) {
+    let totals = value_series
+        .iter()
+        .map(|(date, value)| DailyTotal {
+            date: *date,
+            total: *value,
+        })
+        .collect::<Vec<_>>();
+    let mut out = io::stdout().lock();
+    let result = if args.ndjson {
+        totals.iter().try_for_each(|total| {
+            serde_json::to_writer(&mut out, total)?;
+            writeln!(out).map_err(serde_json::Error::io)
+        })
+    } else if args.json_pretty {
+        serde_json::to_writer_pretty(&mut out, &totals)
+            .and_then(|_| writeln!(out).map_err(serde_json::Error::io))
     } else {
-        println!(
-            "Portfolio total value: {:.2}",
-            match sorted_dates.last() {
-                Some(last_day) => portfolio[last_day]
-                    .iter()
-                    .map(|(_, price)| *price)
-                    .reduce(|acc, p| acc + p)
-                    .unwrap(),
-                None => 0.,
+        serde_json::to_writer(&mut out, &totals)
+            .and_then(|_| writeln!(out).map_err(serde_json::Error::io))
+    };
+    if let Err(e) = result {
+        if !e.is_io() {
+            eprintln!("Unable to write the JSON output. Error: {e}");
+        }
+    }
+}
+
+/// Prints the total value with its daily and period changes on one line, the
+/// period starting on window_start
+fn print_compact(
+    args: &Args,
+    portfolio: &Portfolio,
+    value_series: &[(NaiveDate, f64)],
+    window_start: NaiveDate,
+) {
+    let change = |from: Option<&(NaiveDate, f64)>, to: Option<&(NaiveDate, f64)>| match (from, to) {
+        (Some((_, from)), Some((_, to))) if *from != 0. => {
+            format!("{:+.1}%", (to / from - 1.) * 100.)
+        }
+        _ => "n/a".to_owned(),
+    };
+    let last = value_series.last();
+    let previous = value_series.len().checked_sub(2).map(|i| &value_series[i]);
+    let period_series = return_series(
+        args,
+        portfolio,
+        &value_series
+            .iter()
+            .filter(|(date, _)| *date >= window_start)
+            .copied()
+            .collect::<Vec<_>>(),
+    );
+    // the base currency the values are converted to, or else the currency of the
+    // whole portfolio if it's in one
+    let currency = match (
+        portfolio.base_currency(),
+        last.map(|(date, _)| portfolio.portfolio_value_by_currency(*date)),
+    ) {
+        (Some(base_currency), _) => format!(" {base_currency}"),
+        (None, Some(values)) if values.len() == 1 => {
+            format!(" {}", values.keys().next().unwrap())
+        }
+        _ => String::new(),
+    };
+    println!(
+        "Portfolio: {}{currency} ({} today, {} {}d)",
+        format_thousands(last.map(|(_, value)| *value).unwrap_or_default()),
+        change(previous, last),
+        change(period_series.first(), last),
+        args.days
+    );
+}
+
+/// The value series the period's return is computed over, anchored as --return-base asks
+fn return_series(
+    args: &Args,
+    portfolio: &Portfolio,
+    series: &[(NaiveDate, f64)],
+) -> Vec<(NaiveDate, f64)> {
+    let dates = series.iter().map(|(date, _)| *date).collect::<Vec<_>>();
+    match args.return_base {
+        ReturnBase::FirstAvailable => series.to_vec(),
+        ReturnBase::FirstFullDay => match portfolio.first_full_day(&dates) {
+            Some(first) => series
+                .iter()
+                .filter(|(date, _)| *date >= first)
+                .copied()
+                .collect(),
+            None => vec![],
+        },
+        ReturnBase::BuyWeighted => {
+            let mut series = series.to_vec();
+            if let Some((date, value)) = series.first_mut() {
+                // scaled to the units of the series, which may be deflated or
+                // denominated in another asset
+                let nominal = portfolio.portfolio_value(*date);
+                let base = portfolio.first_quotes_value(&dates);
+                *value = if nominal != 0. {
+                    *value * base / nominal
+                } else {
+                    base
+                };
+            }
+            series
+        }
+    }
+}
+
+/// Formats a value rounded to units, with commas between the thousands
+fn format_thousands(value: f64) -> String {
+    let digits = format!("{:.0}", value.abs());
+    let mut formatted = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    if value.round() < 0. {
+        formatted.insert(0, '-');
+    }
+    formatted
+}
+
+/// Downloads the benchmark symbols and blends them by weight, normalized to 1 on the first date
+async fn load_benchmark(args: &Args, dates: &[NaiveDate]) -> Option<Vec<(NaiveDate, f64)>> {
+    let symbols = args
+        .benchmarks
+        .iter()
+        .map(|(symbol, _)| symbol.clone())
+        .collect::<Vec<_>>();
+    let mut benchmark = match Portfolio::from_symbols(
+        &symbols,
+        &args.default_provider,
+        &args.provider_settings(),
+        &args.cache_settings(),
+    ) {
+        Ok(benchmark) => benchmark,
+        Err(e) => {
+            eprintln!("{e}");
+            return None;
+        }
+    };
+    fetch_prices(args, &mut benchmark, "the benchmark", dates).await;
+    let blend = benchmark.weighted_index(&args.benchmarks);
+    if blend.is_empty() {
+        eprintln!("No prices for the benchmark {}", symbols.join(", "));
+        return None;
+    }
+    Some(blend)
+}
+
+/// Prints the split candidates found in the cached prices of each instrument
+async fn detect_splits(args: &Args, file: &str) {
+    let portfolio = match args.read_portfolio(file).await {
+        Ok(portfolio) => portfolio,
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+    let mut table = Table::new(&["Symbol", "Date", "Ratio", "Before", "After"]);
+    let mut candidates = 0;
+    for instrument in portfolio.instruments() {
+        for candidate in splits::detect(&portfolio.cached_history(instrument)) {
+            table.add_row(vec![
+                instrument.get_name().to_owned(),
+                candidate.date.to_string(),
+                candidate.ratio_label(),
+                format!("{:.2}", candidate.price_before),
+                format!("{:.2}", candidate.price_after),
+            ]);
+            candidates += 1;
+        }
+    }
+    if candidates == 0 {
+        println!("No split candidates in the cached prices");
+        return;
+    }
+    print!("{table}");
+}
+
+/// Prints the key and the description of each provider
+fn list_providers() {
+    let mut table = Table::new(&["Key", "Description"]);
+    for entry in &providers::REGISTRY {
+        table.add_row(vec![entry.key.to_owned(), entry.description.to_owned()]);
+    }
+    print!("{table}");
+}
+
+/// Prints the matches of tickers to provider symbols saved in the cache
+fn list_resolutions(args: &Args) {
+    let resolutions = match PriceCacher::new(args.cache_settings()) {
+        Ok(price_cacher) => price_cacher.resolutions(),
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+    if resolutions.is_empty() {
+        println!("No symbol resolutions in the cache");
+        return;
+    }
+    let mut table = Table::new(&[
+        "Provider",
+        "Ticker",
+        "Symbol",
+        "Query",
+        "Type",
+        "Exchange",
+        "Matches",
+        "Ambiguous",
+        "Resolved",
+    ]);
+    for (provider_name, ticker, resolution, resolved_on) in resolutions {
+        table.add_row(vec![
+            provider_name,
+            ticker,
+            resolution.symbol.clone(),
+            resolution.query.clone(),
+            resolution.quote_type.clone(),
+            resolution.exchange.clone(),
+            resolution.matches.to_string(),
+            if resolution.is_ambiguous() {
+                "yes"
+            } else {
+                "no"
+            }
+            .to_owned(),
+            resolved_on,
+        ]);
+    }
+    print!("{table}");
+}
+
+/// Charts the equity of the trades of a configuration file over the period, as if
+/// they had been executed then, and prints the final value
+async fn simulate(args: &Args, file: &str, dates: &[NaiveDate]) {
+    let Some(portfolio) = load_portfolio(args, file, dates).await else {
+        return;
+    };
+    let curve = match portfolio.equity_curve().await {
+        Ok(curve) => curve,
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+    let (Some((_, start)), Some((last_date, end))) = (curve.first(), curve.last()) else {
+        println!("No prices to simulate the trades of {file} with");
+        return;
+    };
+    if args.days > 1 {
+        println!("Simulated equity for the past {} days", args.days);
+        let events = portfolio.transactions(curve[0].0, *last_date);
+        display_chart(args.chart_style, std::slice::from_ref(&curve), &[], &events);
+    }
+    let change = if *start != 0. {
+        format!("{:+.2}%", (end / start - 1.) * 100.)
+    } else {
+        "n/a".to_owned()
+    };
+    println!("Simulated equity on {last_date}: {end:.2} ({change} over the period)");
+}
+
+/// The configuration file the snapshots are saved under: its full path, so that
+/// they're found whatever the working directory
+fn snapshot_key(file: &str) -> String {
+    fs::canonicalize(file)
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|_| file.to_owned())
+}
+
+/// Charts and lists the daily total values over the period, either those of the
+/// current holdings or those saved by the past runs
+async fn history(args: &Args, file: &str, from_snapshots: bool, dates: &[NaiveDate]) {
+    let Some(first_date) = dates.first() else {
+        return;
+    };
+    let values = if from_snapshots {
+        let values = match PriceCacher::new(args.cache_settings()) {
+            Ok(price_cacher) => price_cacher.snapshots(&snapshot_key(file), *first_date),
+            Err(e) => {
+                eprintln!("{e}");
+                return;
             }
+        };
+        if values.is_empty() {
+            println!("No snapshots of {file} since {first_date}, see --save-snapshot");
+            return;
+        }
+        values
+    } else {
+        let Some(portfolio) = load_portfolio(args, file, dates).await else {
+            return;
+        };
+        portfolio.value_series()
+    };
+    if values.len() > 1 {
+        display_chart(args.chart_style, std::slice::from_ref(&values), &[], &[]);
+    }
+    let mut table = Table::new(&["Date", "Total"]);
+    for (date, total) in &values {
+        table.add_row(vec![date.to_string(), format!("{total:.2}")]);
+    }
+    print!("{table}");
+}
+
+/// Writes the price of each instrument and the total value on each date to a Parquet file
+async fn export_parquet(args: &Args, file: &str, output: &str, dates: &[NaiveDate]) {
+    let Some(portfolio) = load_portfolio(args, file, dates).await else {
+        return;
+    };
+    let dates = portfolio.sorted_dates();
+    // the same symbol can be priced by several providers, each getting a column
+    // named after both
+    let mut keys: Vec<QuoteKey> = vec![];
+    for instrument in portfolio.instruments() {
+        if !keys.contains(&instrument.key()) {
+            keys.push(instrument.key());
+        }
+    }
+    let mut columns = keys
+        .iter()
+        .map(|key| {
+            let (provider, symbol) = key;
+            let prices = dates
+                .iter()
+                .map(|date| portfolio.price(*date, key).map(|(price, _)| price))
+                .collect();
+            let name = if keys.iter().filter(|(_, other)| other == symbol).count() > 1 {
+                format!("{provider}:{symbol}")
+            } else {
+                symbol.clone()
+            };
+            (name, prices)
+        })
+        .collect::<Vec<_>>();
+    // parenthesized so that no symbol can be named the same
+    columns.push((
+        "(total)".to_owned(),
+        dates
+            .iter()
+            .map(|date| Some(portfolio.portfolio_value(*date)))
+            .collect(),
+    ));
+    match parquet::write(output, &dates, &columns) {
+        Ok(()) => println!(
+            "Wrote {} dates of {} instruments to {output}",
+            dates.len(),
+            keys.len()
+        ),
+        Err(e) => eprintln!("{e}"),
+    }
+}
+
+/// Writes the value chart's series to an SVG file: the portfolio's values, then the
+/// benchmark's if there's one
+fn export_chart(args: &Args, path: &str, series: &[Vec<(NaiveDate, f64)>]) {
+    let benchmark = args
+        .benchmarks
+        .iter()
+        .map(|(symbol, _)| symbol.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let names = ["Portfolio".to_owned(), format!("Benchmark ({benchmark})")];
+    let named_series = names
+        .into_iter()
+        .zip(series.iter().cloned())
+        .collect::<Vec<_>>();
+    let title = format!("Portfolio evolution for the past {} days", args.days);
+    match svg::write_chart(path, &title, &named_series) {
+        Ok(()) => println!("Wrote the chart to {path}"),
+        Err(e) => eprintln!("{e}"),
+    }
+}
+
+/// Downloads the prices of the symbol the portfolio is denominated in, by date
+async fn load_numeraire(
+    args: &Args,
+    symbol: &str,
+    dates: &[NaiveDate],
+) -> Option<HashMap<NaiveDate, f64>> {
+    let mut numeraire = match Portfolio::from_symbols(
+        &[symbol.to_owned()],
+        &args.default_provider,
+        &args.provider_settings(),
+        &args.cache_settings(),
+    ) {
+        Ok(numeraire) => numeraire,
+        Err(e) => {
+            eprintln!("{e}");
+            return None;
+        }
+    };
+    fetch_prices(args, &mut numeraire, symbol, dates).await;
+    // a single unit is worth the price
+    let prices = numeraire
+        .value_series()
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    if prices.is_empty() {
+        eprintln!("No prices for {symbol}");
+        return None;
+    }
+    Some(prices)
+}
+
+/// Prints the return of the portfolio next to the benchmark's over the same period
+fn print_benchmark_comparison(
+    args: &Args,
+    value_series: &[(NaiveDate, f64)],
+    benchmark_series: &[(NaiveDate, f64)],
+) {
+    let period_return = |series: &[(NaiveDate, f64)]| match (series.first(), series.last()) {
+        (Some((_, start)), Some((_, end))) if *start != 0. => {
+            format!("{:+.2}%", (end / start - 1.) * 100.)
+        }
+        _ => "n/a".to_owned(),
+    };
+    let total_weight: f64 = args.benchmarks.iter().map(|(_, weight)| weight).sum();
+    let components = args
+        .benchmarks
+        .iter()
+        .map(|(symbol, weight)| format!("{symbol} {:.0}%", weight / total_weight * 100.))
+        .collect::<Vec<_>>()
+        .join(", ");
+    println!("Portfolio return: {}", period_return(value_series));
+    println!(
+        "Benchmark ({components}) return: {}",
+        period_return(benchmark_series)
+    );
+}
+
+/// Prints the maximum drawdowns of the portfolio and of the benchmark, then charts
+/// both underwater curves, in percent below their running peaks
+fn compare_drawdowns(
+    args: &Args,
+    value_series: &[(NaiveDate, f64)],
+    benchmark_series: &[(NaiveDate, f64)],
+) {
+    let describe = |series: &[(NaiveDate, f64)]| match drawdown::max_drawdown(series) {
+        Some((drawdown, peak, trough)) => {
+            let recovery = match drawdown::recovery(series, peak, trough) {
+                Some(date) => format!("recovered on {date}"),
+                None => "not recovered".to_owned(),
+            };
+            format!("{:.2}% ({peak} to {trough}, {recovery})", drawdown * 100.)
+        }
+        None => "none".to_owned(),
+    };
+    println!("Portfolio max drawdown: {}", describe(value_series));
+    println!("Benchmark max drawdown: {}", describe(benchmark_series));
+    if args.days > 1 {
+        let in_percent = |series: &[(NaiveDate, f64)]| {
+            drawdown::underwater(series)
+                .into_iter()
+                .map(|(date, drawdown)| (date, drawdown * 100.))
+                .collect::<Vec<_>>()
+        };
+        println!("Drawdowns (%), the portfolio then the benchmark");
+        display_chart(
+            args.chart_style,
+            &[in_percent(value_series), in_percent(benchmark_series)],
+            &[],
+            &[],
+        );
+    }
+}
+
+/// Redraws the chart in place every interval seconds. The past days' values stay
+/// fixed, only the current day's prices are downloaded again
+async fn watch(args: &Args, mut portfolio: Portfolio, interval: u64) {
+    let mut value_series = portfolio.value_series();
+    // cleared once, the redraws then overwrite the previous frame instead of flickering
+    print!("\x1b[2J");
+    loop {
+        let today = Utc::now().date_naive();
+        let live_join_handles = portfolio.get_live_prices(today);
+        portfolio
+            .wait_for_prices(live_join_handles, args.debug)
+            .await;
+        portfolio.carry_previous_close(today);
+
+        if value_series.last().is_some_and(|(date, _)| *date == today) {
+            value_series.pop();
+        }
+        let value = portfolio.portfolio_value(today);
+        value_series.push((today, value));
+
+        print!("\x1b[H");
+        println!("Portfolio evolution for the past {} days", args.days);
+        display_chart(args.chart_style, &[value_series.clone()], &[], &[]);
+        println!(
+            "Portfolio total value: {value:.2} at {}",
+            Local::now().format("%H:%M:%S")
         );
+        print!("\x1b[J");
+        let _ = io::stdout().flush();
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+/// The days since the latest fetched quote of each instrument, by name
+type QuoteAges = HashMap<String, i64>;
+
+/// Adds the --quote-age-column to a table listing an instrument per row
+fn add_quote_ages(table: &mut Table, ages: Option<&QuoteAges>) {
+    if let Some(ages) = ages {
+        table.add_column("Quote age", |name| match ages.get(name) {
+            Some(days) => format!("{days}d"),
+            None => "n/a".to_owned(),
+        });
+    }
+}
+
+/// Prints the quote currency and value of each instrument, then the value in each currency
+fn print_currency_exposure(portfolio: &Portfolio, date: NaiveDate, ages: Option<&QuoteAges>) {
+    let mut table = Table::new(&["Symbol", "Currency", "Value"]);
+    for (instrument, value) in portfolio.instrument_values(date) {
+        let currency = portfolio.currency_of(&instrument.key()).unwrap_or("?");
+        table.add_row(vec![
+            instrument.get_name().to_owned(),
+            currency.to_owned(),
+            format!("{value:.2}"),
+        ]);
+    }
+    add_quote_ages(&mut table, ages);
+    print!("{table}");
+    let values = portfolio.portfolio_value_by_currency(date);
+    print_groups("Currency", &values.into_iter().collect::<Vec<_>>());
+}
+
+/// Prints the yield of each instrument having one and the yearly income it pays at
+/// its value on a date, then the portfolio's weighted average yield
+fn print_yields(portfolio: &Portfolio, date: NaiveDate, ages: Option<&QuoteAges>) {
+    let Some(weighted_yield) = portfolio.weighted_yield(date) else {
+        println!("No yields: add a yield_pct to the lots paying an income");
+        return;
+    };
+    let mut table = Table::new(&["Symbol", "Yield", "Income"]);
+    let mut total_income = 0.;
+    for (instrument, value) in portfolio.instrument_values(date) {
+        let Some(yield_pct) = instrument.yield_pct() else {
+            continue;
+        };
+        let income = value * yield_pct / 100.;
+        total_income += income;
+        table.add_row(vec![
+            instrument.get_name().to_owned(),
+            format!("{yield_pct:.2}%"),
+            format!("{income:.2}"),
+        ]);
+    }
+    table.set_totals(vec![
+        "Portfolio".to_owned(),
+        format!("{weighted_yield:.2}%"),
+        format!("{total_income:.2}"),
+    ]);
+    add_quote_ages(&mut table, ages);
+    print!("{table}");
+}
+
+/// Prints the value of each group and its share of the total
+fn print_groups(title: &str, values: &[(String, f64)]) {
+    let total: f64 = values.iter().map(|(_, value)| value).sum();
+    let mut table = Table::new(&[title, "Value", "Share"]);
+    for (group, value) in values {
+        let share = if total != 0. {
+            value / total * 100.
+        } else {
+            0.
+        };
+        table.add_row(vec![
+            group.clone(),
+            format!("{value:.2}"),
+            format!("{share:.2}%"),
+        ]);
+    }
+    table.set_totals(vec![
+        "Total".to_owned(),
+        format!("{total:.2}"),
+        "100.00%".to_owned(),
+    ]);
+    print!("{table}");
+}
+
+/// Prints each instrument's contribution to the return, in percentage points
+fn print_contributions(contributions: &[(String, f64)], ages: Option<&QuoteAges>) {
+    let mut table = Table::new(&["Symbol", "Contribution"]);
+    for (name, contribution) in contributions {
+        table.add_row(vec![name.clone(), format!("{:+.2}%", contribution * 100.)]);
+    }
+    let total: f64 = contributions
+        .iter()
+        .map(|(_, contribution)| contribution)
+        .sum();
+    table.set_totals(vec!["Total".to_owned(), format!("{:+.2}%", total * 100.)]);
+    add_quote_ages(&mut table, ages);
+    print!("{table}");
+}
+
+/// Prints each instrument's return next to the portfolio's and their difference,
+/// the outperformers first
+fn print_relative_strength(
+    returns: &[(String, f64)],
+    period_series: &[(NaiveDate, f64)],
+    ages: Option<&QuoteAges>,
+) {
+    let portfolio_return = match (period_series.first(), period_series.last()) {
+        (Some((_, start)), Some((_, end))) if *start != 0. => end / start - 1.,
+        _ => {
+            eprintln!("No portfolio return to compare the instruments with");
+            return;
+        }
+    };
+    let mut returns = returns.to_vec();
+    returns.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    let mut table = Table::new(&["Symbol", "Return", "Portfolio", "Relative"]);
+    for (name, instrument_return) in &returns {
+        table.add_row(vec![
+            name.clone(),
+            format!("{:+.2}%", instrument_return * 100.),
+            format!("{:+.2}%", portfolio_return * 100.),
+            format!("{:+.2}%", (instrument_return - portfolio_return) * 100.),
+        ]);
+    }
+    add_quote_ages(&mut table, ages);
+    print!("{table}");
+}
+
+/// Prints since when the values have been above, or below, the cost basis: the
+/// date of their last crossing, or the period's first date if they never crossed
+fn print_break_even(value_series: &[(NaiveDate, f64)], cost_basis: f64) {
+    let Some((_, last_value)) = value_series.last() else {
+        return;
+    };
+    let above = *last_value >= cost_basis;
+    // the first date of the last run of values on the same side of the cost basis
+    let since = value_series
+        .iter()
+        .rev()
+        .take_while(|(_, value)| (*value >= cost_basis) == above)
+        .last()
+        .map(|(date, _)| *date)
+        .unwrap_or_default();
+    let crossed = value_series.first().is_some_and(|(date, _)| *date != since);
+    let since = if crossed {
+        format!("since {since}")
+    } else {
+        format!("over the whole period, since at least {since}")
+    };
+    if above {
+        println!("In the money {since}, the cost basis being {cost_basis:.2}");
+    } else {
+        println!("Below the cost basis of {cost_basis:.2} {since}");
+    }
+}
+
+/// Prints the return of each lot since it was bought
+fn print_since_inception(returns: &[(String, NaiveDate, f64)], ages: Option<&QuoteAges>) {
+    let mut table = Table::new(&["Symbol", "Bought", "Return"]);
+    for (name, buy_date, total_return) in returns {
+        table.add_row(vec![
+            name.clone(),
+            buy_date.to_string(),
+            format!("{:+.2}%", total_return * 100.),
+        ]);
+    }
+    add_quote_ages(&mut table, ages);
+    print!("{table}");
+}
+
+/// Prints the cost basis and profit or loss of each instrument, then their totals
+fn print_pnl(pnls: &[(String, f64, f64)], ages: Option<&QuoteAges>) {
+    let mut table = Table::new(&["Symbol", "Cost basis", "P&L", "Return"]);
+    let percent = |pnl: f64, cost_basis: f64| {
+        if cost_basis == 0. {
+            "n/a".to_owned()
+        } else {
+            format!("{:+.2}%", pnl / cost_basis * 100.)
+        }
+    };
+    for (name, cost_basis, pnl) in pnls {
+        table.add_row(vec![
+            name.clone(),
+            format!("{cost_basis:.2}"),
+            format!("{pnl:+.2}"),
+            percent(*pnl, *cost_basis),
+        ]);
+    }
+    let total_cost_basis: f64 = pnls.iter().map(|(_, cost_basis, _)| cost_basis).sum();
+    let total_pnl: f64 = pnls.iter().map(|(_, _, pnl)| pnl).sum();
+    table.set_totals(vec![
+        "Total".to_owned(),
+        format!("{total_cost_basis:.2}"),
+        format!("{total_pnl:+.2}"),
+        percent(total_pnl, total_cost_basis),
+    ]);
+    add_quote_ages(&mut table, ages);
+    print!("{table}");
+}
+
+/// Prints the XIRR of each instrument
+fn print_xirrs(xirrs: &[(String, Option<f64>)], ages: Option<&QuoteAges>) {
+    let mut table = Table::new(&["Symbol", "XIRR"]);
+    for (name, xirr) in xirrs {
+        let xirr = match xirr {
+            Some(xirr) => format!("{:+.2}%", xirr * 100.),
+            None => "n/a".to_owned(),
+        };
+        table.add_row(vec![name.clone(), xirr]);
+    }
+    add_quote_ages(&mut table, ages);
+    print!("{table}");
+}
+
+/// Draws a horizontal bar for each value, the longest bar being width characters
+fn display_bar_chart(values: &[(String, f64)], width: usize) {
+    let max = values.iter().map(|(_, value)| *value).fold(0., f64::max);
+    let label_width = values
+        .iter()
+        .map(|(label, _)| label.chars().count())
+        .max()
+        .unwrap_or_default();
+    for (label, value) in values {
+        let eighths = if max > 0. {
+            (value.max(0.) / max * (width * 8) as f64).round() as usize
+        } else {
+            0
+        };
+        let mut bar = "█".repeat(eighths / 8);
+        if eighths % 8 > 0 {
+            bar.push(PARTIAL_BLOCKS[eighths % 8 - 1]);
+        }
+        println!("{label:<label_width$} {bar:<width$} {value:.2}");
+    }
+}
+
+/// Warns when the total adds up prices quoted in different currencies
+fn print_currency_breakdown(portfolio: &Portfolio, date: NaiveDate) {
+    // converted to a single currency, only those lacking rates were warned about
+    if portfolio.base_currency().is_some() {
+        return;
+    }
+    let values = portfolio.portfolio_value_by_currency(date);
+    if values.len() > 1 {
+        println!(
+            "Warning: the total mixes currencies: {}",
+            values
+                .iter()
+                .map(|(currency, value)| format!("{value:.2} {currency}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(args.worker_threads as usize)
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(run(args));
+}
+
+async fn run(args: Args) {
+    // get the list of dates
+    let today = Utc::now().naive_utc();
+    let start_day = today.checked_sub_days(Days::new(args.days as u64)).unwrap();
+    let dates = trading_days(start_day, today, &args.non_trading_days);
+
+    match &args.command {
+        Some(Command::List { file }) => list_instruments(&args, file).await,
+        Some(Command::Compare { files }) => compare(&args, files, &dates).await,
+        Some(Command::Seed { file, from, to }) => seed(&args, file, *from, *to).await,
+        Some(Command::Alerts { file }) => alerts(&args, file, &dates).await,
+        Some(Command::Explain { file, symbol, date }) => explain(&args, file, symbol, *date).await,
+        Some(Command::TaxReport {
+            file,
+            tax_year,
+            csv,
+        }) => tax_report(&args, file, *tax_year, *csv).await,
+        Some(Command::DetectSplits { file }) => detect_splits(&args, file).await,
+        Some(Command::Resolutions) => list_resolutions(&args),
+        Some(Command::ListProviders) => list_providers(),
+        Some(Command::Simulate { file }) => simulate(&args, file, &dates).await,
+        Some(Command::History {
+            file,
+            from_snapshots,
+        }) => history(&args, file, *from_snapshots, &dates).await,
+        Some(Command::ExportParquet { file, output }) => {
+            export_parquet(&args, file, output, &dates).await
+        }
+        None => {
+            let file = args.file.as_deref().or(args.ofx.as_deref()).unwrap();
+            report(&args, file, &dates).await
+        }
     }
 }
@@ -0,0 +1,82 @@
+/// The text of the first leaf element named tag in an OFX block. OFX 1.x (SGML)
+/// doesn't close the leaf elements, so the value ends at the next tag either way
+fn leaf<'a>(block: &'a str, tag: &str) -> Option<&'a str> {
+    let start = block.find(&format!("<{tag}>"))? + tag.len() + 2;
+    let value = block[start..].split('<').next()?.trim();
+    (!value.is_empty()).then_some(value)
+}
+
+/// The contents of every aggregate element named tag, which are closed in both
+/// OFX 1.x and 2.x
+fn aggregates<'a>(content: &'a str, tag: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{tag}>"), format!("</{tag}>"));
+    let mut blocks = vec![];
+    let mut rest = content;
+    while let Some(start) = rest.find(&open) {
+        let after_open = &rest[start + open.len()..];
+        let Some(end) = after_open.find(&close) else {
+            break;
+        };
+        blocks.push(&after_open[..end]);
+        rest = &after_open[end + close.len()..];
+    }
+    blocks
+}
+
+/// The ticker and number of units of each stock position (<POSSTOCK>) of an OFX
+/// statement, the tickers being looked up in the security list (<SECINFO>)
+pub(crate) fn stock_positions(content: &str) -> Result<Vec<(String, f64)>, std::io::Error> {
+    let tickers = aggregates(content, "SECINFO")
+        .into_iter()
+        .filter_map(|security| Some((leaf(security, "UNIQUEID")?, leaf(security, "TICKER")?)))
+        .collect::<Vec<_>>();
+
+    let mut positions = vec![];
+    for position in aggregates(content, "POSSTOCK") {
+        let unique_id = leaf(position, "UNIQUEID")
+            .ok_or_else(|| std::io::Error::other("OFX: a stock position has no security id"))?;
+        let ticker = tickers
+            .iter()
+            .find(|(id, _)| *id == unique_id)
+            .map(|(_, ticker)| ticker.to_string())
+            .ok_or_else(|| {
+                std::io::Error::other(format!("OFX: no ticker for the security {unique_id}"))
+            })?;
+        let units = leaf(position, "UNITS")
+            .and_then(|units| units.parse::<f64>().ok())
+            .ok_or_else(|| std::io::Error::other(format!("OFX: invalid units for {ticker}")))?;
+        positions.push((ticker, units));
+    }
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SGML: &str = include_str!("../fixtures/ofx/sgml.ofx");
+    const XML: &str = include_str!("../fixtures/ofx/xml.qfx");
+
+    #[test]
+    fn reads_the_positions_of_an_sgml_statement() {
+        assert_eq!(
+            stock_positions(SGML).unwrap(),
+            vec![("AAPL".to_owned(), 100.), ("MSFT".to_owned(), 12.5)]
+        );
+    }
+
+    #[test]
+    fn reads_the_positions_of_an_xml_statement() {
+        assert_eq!(
+            stock_positions(XML).unwrap(),
+            vec![("VTI".to_owned(), 3.2761)]
+        );
+    }
+
+    #[test]
+    fn fails_on_a_security_missing_from_the_list() {
+        let statement = SGML.replace("<TICKER>MSFT", "");
+        let error = stock_positions(&statement).unwrap_err();
+        assert!(error.to_string().contains("594918104"));
+    }
+}
@@ -0,0 +1,617 @@
+use std::{fs, io};
+
+use chrono::{Datelike, NaiveDate};
+
+// the parts of the Parquet format written: a row group per year of uncompressed,
+// plain encoded columns with their statistics, described by Thrift structures in
+// the compact protocol
+
+const MAGIC: &[u8] = b"PAR1";
+
+const TYPE_INT32: i32 = 1;
+const TYPE_DOUBLE: i32 = 5;
+const REPETITION_REQUIRED: i32 = 0;
+const REPETITION_OPTIONAL: i32 = 1;
+const CONVERTED_TYPE_DATE: i32 = 6;
+const ENCODING_PLAIN: i32 = 0;
+const ENCODING_RLE: i32 = 3;
+const CODEC_UNCOMPRESSED: i32 = 0;
+const PAGE_TYPE_DATA: i32 = 0;
+
+const THRIFT_I32: u8 = 5;
+const THRIFT_I64: u8 = 6;
+const THRIFT_BINARY: u8 = 8;
+const THRIFT_LIST: u8 = 9;
+const THRIFT_STRUCT: u8 = 12;
+
+/// Serializes Thrift structures in the compact protocol
+#[derive(Default)]
+struct ThriftWriter {
+    bytes: Vec<u8>,
+    /// the id of the last field written in each of the structures being written
+    last_field_ids: Vec<i16>,
+}
+
+impl ThriftWriter {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    fn zigzag(&mut self, value: i64) {
+        self.varint(((value << 1) ^ (value >> 63)) as u64);
+    }
+
+    fn field_header(&mut self, id: i16, field_type: u8) {
+        let last_id = self.last_field_ids.last_mut().unwrap();
+        let delta = id - *last_id;
+        *last_id = id;
+        if (1..=15).contains(&delta) {
+            self.bytes.push((delta as u8) << 4 | field_type);
+        } else {
+            self.bytes.push(field_type);
+            self.zigzag(id as i64);
+        }
+    }
+
+    fn list_header(&mut self, len: usize, element_type: u8) {
+        if len < 15 {
+            self.bytes.push((len as u8) << 4 | element_type);
+        } else {
+            self.bytes.push(0xf0 | element_type);
+            self.varint(len as u64);
+        }
+    }
+
+    fn binary(&mut self, value: &[u8]) {
+        self.varint(value.len() as u64);
+        self.bytes.extend_from_slice(value);
+    }
+
+    fn i32_field(&mut self, id: i16, value: i32) {
+        self.field_header(id, THRIFT_I32);
+        self.zigzag(value as i64);
+    }
+
+    fn i64_field(&mut self, id: i16, value: i64) {
+        self.field_header(id, THRIFT_I64);
+        self.zigzag(value);
+    }
+
+    fn binary_field(&mut self, id: i16, value: &[u8]) {
+        self.field_header(id, THRIFT_BINARY);
+        self.binary(value);
+    }
+
+    fn string_field(&mut self, id: i16, value: &str) {
+        self.binary_field(id, value.as_bytes());
+    }
+
+    fn begin_struct(&mut self) {
+        self.last_field_ids.push(0);
+    }
+
+    fn end_struct(&mut self) {
+        self.bytes.push(0);
+        self.last_field_ids.pop();
+    }
+
+    fn struct_field(&mut self, id: i16, write: impl FnOnce(&mut Self)) {
+        self.field_header(id, THRIFT_STRUCT);
+        self.begin_struct();
+        write(self);
+        self.end_struct();
+    }
+
+    fn struct_list_field<T>(&mut self, id: i16, items: &[T], write: impl Fn(&mut Self, &T)) {
+        self.field_header(id, THRIFT_LIST);
+        self.list_header(items.len(), THRIFT_STRUCT);
+        for item in items {
+            self.begin_struct();
+            write(self, item);
+            self.end_struct();
+        }
+    }
+}
+
+/// A column of the file: its name, type and whether it has nulls
+struct Column {
+    name: String,
+    physical_type: i32,
+    optional: bool,
+}
+
+/// The part of a column in a row group: its plain encoded page (definition levels
+/// included), its number of values and its statistics, the bounds plain encoded
+struct Chunk {
+    page: Vec<u8>,
+    num_values: usize,
+    null_count: usize,
+    bounds: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+/// The chunk of the dates, as days since the Unix epoch
+fn date_chunk(dates: &[NaiveDate]) -> Chunk {
+    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+    let days = dates
+        .iter()
+        .map(|date| (*date - epoch).num_days() as i32)
+        .collect::<Vec<_>>();
+    let bounds = days.iter().min().zip(days.iter().max());
+    Chunk {
+        page: days.iter().flat_map(|day| day.to_le_bytes()).collect(),
+        num_values: days.len(),
+        null_count: 0,
+        bounds: bounds.map(|(min, max)| (min.to_le_bytes().to_vec(), max.to_le_bytes().to_vec())),
+    }
+}
+
+/// The chunk of a column of prices: the definition levels of the values, 0 for the
+/// missing ones, run-length encoded, then the values present
+fn price_chunk(values: &[Option<f64>]) -> Chunk {
+    let mut levels = ThriftWriter::default();
+    let mut i = 0;
+    while i < values.len() {
+        let present = values[i].is_some();
+        let run = values[i..]
+            .iter()
+            .take_while(|value| value.is_some() == present)
+            .count();
+        levels.varint((run as u64) << 1);
+        levels.bytes.push(present as u8);
+        i += run;
+    }
+    let mut page = (levels.bytes.len() as u32).to_le_bytes().to_vec();
+    page.extend(levels.bytes);
+    page.extend(
+        values
+            .iter()
+            .flatten()
+            .flat_map(|value| value.to_le_bytes()),
+    );
+    let present = values.iter().flatten().filter(|value| !value.is_nan());
+    let bounds = present
+        .clone()
+        .copied()
+        .reduce(f64::min)
+        .zip(present.copied().reduce(f64::max));
+    Chunk {
+        page,
+        num_values: values.len(),
+        null_count: values.iter().filter(|value| value.is_none()).count(),
+        bounds: bounds.map(|(min, max)| (min.to_le_bytes().to_vec(), max.to_le_bytes().to_vec())),
+    }
+}
+
+/// Writes the dates and the values of each named column to a Parquet file, one row
+/// per date, the missing values being nulls. Each year of dates is a row group, so
+/// that the readers can skip the years they don't need
+pub(crate) fn write(
+    path: &str,
+    dates: &[NaiveDate],
+    columns: &[(String, Vec<Option<f64>>)],
+) -> Result<(), io::Error> {
+    let mut all_columns = vec![Column {
+        name: "date".to_owned(),
+        physical_type: TYPE_INT32,
+        optional: false,
+    }];
+    all_columns.extend(columns.iter().map(|(name, _)| Column {
+        name: name.clone(),
+        physical_type: TYPE_DOUBLE,
+        optional: true,
+    }));
+
+    let mut file = MAGIC.to_vec();
+    // the number of rows of each row group, and the offset and size of each of its
+    // column chunks, page header included
+    let mut row_groups = vec![];
+    let mut start = 0;
+    for year in dates.chunk_by(|a, b| a.year() == b.year()) {
+        let rows = start..start + year.len();
+        start = rows.end;
+        let chunks = std::iter::once(date_chunk(year)).chain(
+            columns
+                .iter()
+                .map(|(_, values)| price_chunk(&values[rows.clone()])),
+        );
+        let mut written = vec![];
+        for chunk in chunks {
+            let mut header = ThriftWriter::default();
+            header.begin_struct();
+            header.i32_field(1, PAGE_TYPE_DATA);
+            header.i32_field(2, chunk.page.len() as i32);
+            header.i32_field(3, chunk.page.len() as i32);
+            header.struct_field(5, |data_page| {
+                data_page.i32_field(1, chunk.num_values as i32);
+                data_page.i32_field(2, ENCODING_PLAIN);
+                data_page.i32_field(3, ENCODING_RLE);
+                data_page.i32_field(4, ENCODING_RLE);
+            });
+            header.end_struct();
+            let offset = file.len();
+            file.extend(header.bytes);
+            file.extend(&chunk.page);
+            written.push((offset, file.len() - offset, chunk));
+        }
+        row_groups.push((year.len(), written));
+    }
+
+    let mut metadata = ThriftWriter::default();
+    metadata.begin_struct();
+    metadata.i32_field(1, 1);
+    let root = (None, "schema".to_owned());
+    let schema = std::iter::once(root)
+        .chain(
+            all_columns
+                .iter()
+                .map(|column| (Some(column), column.name.clone())),
+        )
+        .collect::<Vec<_>>();
+    metadata.struct_list_field(2, &schema, |element, (column, name)| match column {
+        Some(column) => {
+            element.i32_field(1, column.physical_type);
+            element.i32_field(
+                3,
+                if column.optional {
+                    REPETITION_OPTIONAL
+                } else {
+                    REPETITION_REQUIRED
+                },
+            );
+            element.string_field(4, name);
+            if column.physical_type == TYPE_INT32 {
+                element.i32_field(6, CONVERTED_TYPE_DATE);
+            }
+        }
+        None => {
+            element.string_field(4, name);
+            element.i32_field(5, all_columns.len() as i32);
+        }
+    });
+    metadata.i64_field(3, dates.len() as i64);
+    metadata.struct_list_field(4, &row_groups, |row_group, (num_rows, written)| {
+        let chunks = all_columns.iter().zip(written).collect::<Vec<_>>();
+        row_group.struct_list_field(1, &chunks, |meta_chunk, (column, (offset, size, chunk))| {
+            meta_chunk.i64_field(2, *offset as i64);
+            meta_chunk.struct_field(3, |meta| {
+                meta.i32_field(1, column.physical_type);
+                meta.field_header(2, THRIFT_LIST);
+                meta.list_header(2, THRIFT_I32);
+                meta.zigzag(ENCODING_PLAIN as i64);
+                meta.zigzag(ENCODING_RLE as i64);
+                meta.field_header(3, THRIFT_LIST);
+                meta.list_header(1, THRIFT_BINARY);
+                meta.binary(column.name.as_bytes());
+                meta.i32_field(4, CODEC_UNCOMPRESSED);
+                meta.i64_field(5, chunk.num_values as i64);
+                meta.i64_field(6, *size as i64);
+                meta.i64_field(7, *size as i64);
+                meta.i64_field(9, *offset as i64);
+                meta.struct_field(12, |statistics| {
+                    statistics.i64_field(3, chunk.null_count as i64);
+                    if let Some((min, max)) = &chunk.bounds {
+                        statistics.binary_field(5, max);
+                        statistics.binary_field(6, min);
+                    }
+                });
+            });
+        });
+        row_group.i64_field(2, written.iter().map(|(_, size, _)| *size as i64).sum());
+        row_group.i64_field(3, *num_rows as i64);
+    });
+    metadata.string_field(6, concat!("livestock ", env!("CARGO_PKG_VERSION")));
+    metadata.end_struct();
+
+    file.extend(&metadata.bytes);
+    file.extend((metadata.bytes.len() as u32).to_le_bytes());
+    file.extend(MAGIC);
+    fs::write(path, file)
+        .map_err(|e| io::Error::other(format!("Unable to write {path}. Error: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    // a reader of the files written, decoding them from the Parquet and Thrift
+    // specifications independently of the writer above
+
+    /// A Thrift value in the compact protocol, the integers of all sizes being one
+    #[derive(Debug)]
+    enum Value {
+        Int(i64),
+        Binary(Vec<u8>),
+        List(Vec<Value>),
+        Struct(BTreeMap<i16, Value>),
+    }
+
+    impl Value {
+        fn field(&self, id: i16) -> &Value {
+            match self {
+                Value::Struct(fields) => &fields[&id],
+                _ => panic!("{self:?} isn't a structure"),
+            }
+        }
+
+        fn int(&self) -> i64 {
+            match self {
+                Value::Int(value) => *value,
+                _ => panic!("{self:?} isn't an integer"),
+            }
+        }
+
+        fn text(&self) -> &str {
+            match self {
+                Value::Binary(bytes) => std::str::from_utf8(bytes).unwrap(),
+                _ => panic!("{self:?} isn't a string"),
+            }
+        }
+
+        fn list(&self) -> &[Value] {
+            match self {
+                Value::List(items) => items,
+                _ => panic!("{self:?} isn't a list"),
+            }
+        }
+    }
+
+    struct Reader<'a> {
+        bytes: &'a [u8],
+        position: usize,
+    }
+
+    impl Reader<'_> {
+        fn byte(&mut self) -> u8 {
+            self.position += 1;
+            self.bytes[self.position - 1]
+        }
+
+        fn take(&mut self, len: usize) -> &[u8] {
+            self.position += len;
+            &self.bytes[self.position - len..self.position]
+        }
+
+        fn varint(&mut self) -> u64 {
+            let mut value = 0;
+            for shift in (0..64).step_by(7) {
+                let byte = self.byte();
+                value |= ((byte & 0x7f) as u64) << shift;
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+            value
+        }
+
+        fn zigzag(&mut self) -> i64 {
+            let value = self.varint();
+            (value >> 1) as i64 ^ -((value & 1) as i64)
+        }
+
+        fn value(&mut self, value_type: u8) -> Value {
+            match value_type {
+                1 => Value::Int(1),
+                2 => Value::Int(0),
+                3 => Value::Int(self.byte() as i8 as i64),
+                4..=6 => Value::Int(self.zigzag()),
+                8 => {
+                    let len = self.varint() as usize;
+                    Value::Binary(self.take(len).to_vec())
+                }
+                9 | 10 => {
+                    let header = self.byte();
+                    let len = match header >> 4 {
+                        15 => self.varint() as usize,
+                        len => len as usize,
+                    };
+                    Value::List((0..len).map(|_| self.value(header & 0x0f)).collect())
+                }
+                12 => self.structure(),
+                _ => panic!("unexpected Thrift type {value_type}"),
+            }
+        }
+
+        fn structure(&mut self) -> Value {
+            let mut fields = BTreeMap::new();
+            let mut last_id = 0;
+            loop {
+                let header = self.byte();
+                if header == 0 {
+                    return Value::Struct(fields);
+                }
+                let id = match header >> 4 {
+                    0 => self.zigzag() as i16,
+                    delta => last_id + delta as i16,
+                };
+                last_id = id;
+                fields.insert(id, self.value(header & 0x0f));
+            }
+        }
+    }
+
+    /// The metadata in the footer of a file
+    fn footer(bytes: &[u8]) -> Value {
+        assert_eq!(&bytes[..4], MAGIC);
+        assert_eq!(&bytes[bytes.len() - 4..], MAGIC);
+        let footer_end = bytes.len() - 8;
+        let footer_len = u32::from_le_bytes(bytes[footer_end..][..4].try_into().unwrap());
+        let mut footer = Reader {
+            bytes,
+            position: footer_end - footer_len as usize,
+        };
+        let metadata = footer.structure();
+        assert_eq!(footer.position, footer_end);
+        metadata
+    }
+
+    /// The column names of a file and the values of each row, the dates as days
+    /// since the Unix epoch
+    fn read(bytes: &[u8]) -> (Vec<String>, Vec<Vec<Option<f64>>>) {
+        let metadata = footer(bytes);
+        let schema = metadata.field(2).list();
+        assert_eq!(schema[0].field(5).int() as usize, schema.len() - 1);
+        let names = schema[1..]
+            .iter()
+            .map(|element| element.field(4).text().to_owned())
+            .collect::<Vec<_>>();
+        let mut rows = vec![];
+        for row_group in metadata.field(4).list() {
+            let num_rows = row_group.field(3).int() as usize;
+            let mut group_rows = vec![vec![]; num_rows];
+            for (element, chunk) in schema[1..].iter().zip(row_group.field(1).list()) {
+                let mut reader = Reader {
+                    bytes,
+                    position: chunk.field(3).field(9).int() as usize,
+                };
+                let header = reader.structure();
+                assert_eq!(header.field(5).field(1).int() as usize, num_rows);
+                let page_len = header.field(3).int() as usize;
+                let mut page = Reader {
+                    bytes: reader.take(page_len),
+                    position: 0,
+                };
+                let levels = if element.field(3).int() == REPETITION_OPTIONAL as i64 {
+                    // run-length encoded definition levels, one byte wide
+                    let levels_len = u32::from_le_bytes(page.take(4).try_into().unwrap());
+                    let levels_end = page.position + levels_len as usize;
+                    let mut levels = vec![];
+                    while page.position < levels_end {
+                        let run = page.varint();
+                        assert_eq!(run & 1, 0, "bit-packed runs aren't written");
+                        let level = page.byte();
+                        levels.extend(std::iter::repeat_n(level == 1, (run >> 1) as usize));
+                    }
+                    levels
+                } else {
+                    vec![true; num_rows]
+                };
+                for (row, present) in group_rows.iter_mut().zip(levels) {
+                    row.push(present.then(|| match element.field(1).int() as i32 {
+                        TYPE_INT32 => i32::from_le_bytes(page.take(4).try_into().unwrap()) as f64,
+                        TYPE_DOUBLE => f64::from_le_bytes(page.take(8).try_into().unwrap()),
+                        physical_type => panic!("unexpected physical type {physical_type}"),
+                    }));
+                }
+                assert_eq!(page.position, page_len);
+            }
+            rows.extend(group_rows);
+        }
+        assert_eq!(rows.len(), metadata.field(3).int() as usize);
+        (names, rows)
+    }
+
+    /// The bytes of the file written with the columns
+    fn written(dates: &[NaiveDate], columns: &[(String, Vec<Option<f64>>)]) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!(
+            "livestock-{}-{}-{}.parquet",
+            std::process::id(),
+            dates.len(),
+            columns.len()
+        ));
+        write(path.to_str().unwrap(), dates, columns).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        bytes
+    }
+
+    /// Writes the columns to a temporary file and reads it back
+    fn round_trip(
+        dates: &[NaiveDate],
+        columns: &[(String, Vec<Option<f64>>)],
+    ) -> (Vec<String>, Vec<Vec<Option<f64>>>) {
+        read(&written(dates, columns))
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    #[test]
+    fn reads_back_the_values_and_the_nulls() {
+        let dates = (1..=4).map(date).collect::<Vec<_>>();
+        let columns = vec![
+            (
+                "AAPL".to_owned(),
+                vec![Some(179.66), None, None, Some(170.12)],
+            ),
+            (
+                "total".to_owned(),
+                vec![Some(1796.6), Some(1796.6), Some(1720.0), Some(1701.2)],
+            ),
+        ];
+        let (names, rows) = round_trip(&dates, &columns);
+        assert_eq!(names, ["date", "AAPL", "total"]);
+        // 2024-03-01 is 19783 days after the epoch
+        assert_eq!(
+            rows,
+            [
+                [Some(19783.0), Some(179.66), Some(1796.6)],
+                [Some(19784.0), None, Some(1796.6)],
+                [Some(19785.0), None, Some(1720.0)],
+                [Some(19786.0), Some(170.12), Some(1701.2)],
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_back_more_than_fifteen_columns() {
+        // the lists of 15 elements or more have a longer header
+        let dates = vec![date(1)];
+        let columns = (0..20)
+            .map(|i| (format!("S{i}"), vec![Some(i as f64)]))
+            .collect::<Vec<_>>();
+        let (names, rows) = round_trip(&dates, &columns);
+        assert_eq!(names.len(), 21);
+        assert_eq!(names[20], "S19");
+        assert_eq!(rows[0][20], Some(19.0));
+    }
+
+    #[test]
+    fn writes_a_row_group_per_year_with_statistics() {
+        let dates = [
+            NaiveDate::from_ymd_opt(2023, 12, 29).unwrap(),
+            NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(),
+            NaiveDate::from_ymd_opt(2024, 1, 3).unwrap(),
+        ];
+        let columns = vec![("AAPL".to_owned(), vec![None, Some(185.64), Some(184.25)])];
+        let bytes = written(&dates, &columns);
+        let (_, rows) = read(&bytes);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[1][1], Some(185.64));
+
+        let metadata = footer(&bytes);
+        let row_groups = metadata.field(4).list();
+        assert_eq!(
+            row_groups
+                .iter()
+                .map(|row_group| row_group.field(3).int())
+                .collect::<Vec<_>>(),
+            [1, 2]
+        );
+        let statistics = |row_group: usize, column: usize| {
+            row_groups[row_group].field(1).list()[column]
+                .field(3)
+                .field(12)
+        };
+        let bound = |value: &Value| match value {
+            Value::Binary(bytes) => f64::from_le_bytes(bytes[..].try_into().unwrap()),
+            _ => panic!("{value:?} isn't a bound"),
+        };
+        // the 2023 prices are all missing, so they have no bounds
+        assert_eq!(statistics(0, 1).field(3).int(), 1);
+        assert!(matches!(statistics(0, 1), Value::Struct(fields) if !fields.contains_key(&5)));
+        assert_eq!(statistics(1, 1).field(3).int(), 0);
+        assert_eq!(bound(statistics(1, 1).field(5)), 185.64);
+        assert_eq!(bound(statistics(1, 1).field(6)), 184.25);
+        // 2024-01-02 is 19724 days after the epoch
+        let Value::Binary(min_date) = statistics(1, 0).field(6) else {
+            panic!("the dates have no bounds");
+        };
+        assert_eq!(i32::from_le_bytes(min_date[..].try_into().unwrap()), 19724);
+    }
+}
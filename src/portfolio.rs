@@ -0,0 +1,2325 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    io::Read,
+    sync::Arc,
+};
+
+use chrono::{Datelike, Days, Months, NaiveDate, Utc};
+use tokio::task::JoinHandle;
+
+use crate::{
+    config::{self, Entries, Lot, Section, Sell, INSTRUMENTS_KEY},
+    ofx,
+    price_cacher::{CacheSettings, CacheStats, PriceCacher},
+    providers::{AssetClass, ProviderSettings, Providers},
+    xirr,
+};
+
+pub(crate) type PriceResult = Result<(String, NaiveDate, f64, String), std::io::Error>;
+/// The key of an instrument's quotes: the name of its provider and its symbol, as two
+/// providers may use the same symbol for different instruments
+pub(crate) type QuoteKey = (String, String);
+
+/// The download of an instrument's price on a date
+pub(crate) type PriceDownload = (QuoteKey, NaiveDate, JoinHandle<PriceResult>);
+pub(crate) type PriceRangeResult = Result<Vec<(String, NaiveDate, f64, String)>, std::io::Error>;
+/// The download of an instrument's prices on several dates at once
+pub(crate) type PriceRangeDownload = (QuoteKey, Vec<NaiveDate>, JoinHandle<PriceRangeResult>);
+
+/// A position from the configuration file: every lot of a symbol priced by a provider
+#[derive(Debug)]
+pub(crate) struct Instrument {
+    name: String,
+    provider: Arc<Providers>,
+    /// the account holding the position, None outside of any account
+    account: Option<String>,
+    /// the lots making up the position, in file order, kept apart for their cost basis
+    lots: Vec<Lot>,
+}
+
+impl Instrument {
+    pub(crate) fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn get_provider_name(&self) -> String {
+        self.provider.get_provider_name()
+    }
+
+    /// The key of the instrument's quotes
+    pub(crate) fn key(&self) -> QuoteKey {
+        (self.provider.get_provider_name(), self.name.clone())
+    }
+
+    pub(crate) fn get_account(&self) -> Option<&str> {
+        self.account.as_deref()
+    }
+
+    /// The quantity of all the lots, before any of their sells
+    pub(crate) fn get_quantity(&self) -> f64 {
+        self.lots.iter().map(|lot| lot.quantity).sum()
+    }
+
+    /// The quantity of all the lots left after their sells up to a date
+    pub(crate) fn quantity_on(&self, date: NaiveDate) -> f64 {
+        self.lots.iter().map(|lot| lot.quantity_on(date)).sum()
+    }
+
+    /// The quantity of a lot left on a date less the fees of the expense ratio, as if
+    /// they were paid in units since its buy_date, or since a date without one
+    fn lot_quantity_net_of_fees(&self, lot: &Lot, date: NaiveDate, since: NaiveDate) -> f64 {
+        let Some(expense_ratio) = self.expense_ratio() else {
+            return lot.quantity_on(date);
+        };
+        let held_days = (date - lot.buy_date.unwrap_or(since)).num_days().max(0);
+        let kept = (1. - expense_ratio / 100.).powf(held_days as f64 / 365.25);
+        lot.quantity_on(date) * kept
+    }
+
+    /// The expense ratio of the first lot having one, in percent
+    fn expense_ratio(&self) -> Option<f64> {
+        self.lots.iter().find_map(|lot| lot.expense_ratio)
+    }
+
+    /// The stop price of the first lot having one
+    fn stop_price(&self) -> Option<f64> {
+        self.lots.iter().find_map(|lot| lot.stop_price)
+    }
+
+    /// The move threshold of the first lot having one
+    fn alert_pct(&self) -> Option<f64> {
+        self.lots.iter().find_map(|lot| lot.alert_pct)
+    }
+
+    /// Reassigns the sells of the lots first in, first out, returning the quantity
+    /// sold beyond what the lots held
+    fn match_sells(&mut self) -> f64 {
+        let mut sells = self
+            .lots
+            .iter_mut()
+            .flat_map(|lot| std::mem::take(&mut lot.sells))
+            .collect::<Vec<_>>();
+        sells.sort_by_key(|sell| sell.date);
+        // the lots without a buy_date are held from the start, the oldest
+        let mut order = (0..self.lots.len()).collect::<Vec<_>>();
+        order.sort_by_key(|index| self.lots[*index].buy_date);
+        let mut unmatched = 0.;
+        for sell in sells {
+            let mut left = sell.quantity;
+            for index in &order {
+                let lot = &mut self.lots[*index];
+                let taken = lot.quantity_on(sell.date).min(left);
+                if taken > 0. {
+                    lot.sells.push(Sell {
+                        date: sell.date,
+                        quantity: taken,
+                    });
+                    left -= taken;
+                }
+            }
+            unmatched += left.max(0.);
+        }
+        unmatched
+    }
+
+    /// The tick size of the first lot having one
+    fn tick_size(&self) -> Option<f64> {
+        self.lots
+            .iter()
+            .find_map(|lot| lot.tick_size)
+            .filter(|tick_size| *tick_size > 0.)
+    }
+
+    /// The yield of the first lot having one, in percent
+    pub(crate) fn yield_pct(&self) -> Option<f64> {
+        self.lots.iter().find_map(|lot| lot.yield_pct)
+    }
+
+    /// Describes how the provider derives the instrument's price on a date
+    pub(crate) async fn explain(&self, date: NaiveDate) -> Result<String, std::io::Error> {
+        self.provider.explain(&self.name, date).await
+    }
+}
+
+/// A lot sold, as listed by the tax report
+#[derive(Debug)]
+pub(crate) struct RealizedGain {
+    pub(crate) name: String,
+    pub(crate) account: Option<String>,
+    pub(crate) quantity: f64,
+    pub(crate) buy_date: NaiveDate,
+    pub(crate) sell_date: NaiveDate,
+    pub(crate) cost: f64,
+    pub(crate) proceeds: f64,
+}
+
+impl RealizedGain {
+    pub(crate) fn gain(&self) -> f64 {
+        self.proceeds - self.cost
+    }
+
+    /// Whether the lot was held for more than a year
+    pub(crate) fn is_long_term(&self) -> bool {
+        self.buy_date
+            .checked_add_months(Months::new(12))
+            .is_some_and(|anniversary| self.sell_date > anniversary)
+    }
+}
+
+/// A stop or a move threshold crossed by an instrument's price
+#[derive(Debug)]
+pub(crate) enum Alert {
+    /// The latest price is below the stop price
+    Stop {
+        name: String,
+        price: f64,
+        stop_price: f64,
+    },
+    /// The price moved more than alert_pct percent between the first and last dates
+    Move {
+        name: String,
+        change_pct: f64,
+        alert_pct: f64,
+    },
+}
+
+#[derive(Debug)]
+pub(crate) struct Portfolio {
+    instruments: Vec<Instrument>,
+    /// the close price of each instrument by date and quote key
+    portfolio: HashMap<NaiveDate, HashMap<QuoteKey, f64>>,
+    /// the quotes filled in from a previous close rather than fetched
+    extended: HashSet<(NaiveDate, QuoteKey)>,
+    /// the currency each instrument is quoted in, by quote key
+    currencies: HashMap<QuoteKey, String>,
+    /// the number of past days each provider has prices for, by provider name
+    history_limits: HashMap<String, u32>,
+    /// the quotes whose download failed
+    failed: HashSet<(NaiveDate, QuoteKey)>,
+    /// the decimals each instrument's value is rounded to before summing the total,
+    /// summed unrounded if None
+    currency_precision: Option<u32>,
+    /// whether the values are reduced by the instruments' expense ratios
+    model_fees: bool,
+    /// the currency the values are converted to, left in the instruments' own if None
+    base_currency: Option<String>,
+    /// the rates converting each other currency to the base currency, by date
+    fx_rates: HashMap<String, BTreeMap<NaiveDate, f64>>,
+    price_cacher: Arc<PriceCacher>,
+}
+
+/// The lots of a provider key within an account, or outside of any
+type Block = (Option<String>, String, Entries<Lot>);
+
+impl Portfolio {
+    /// Reads the instruments from a JSON configuration file, or from stdin if file is "-",
+    /// the lots of an overlay file replacing the base file's lots of the same symbols
+    pub(crate) fn from_json(
+        file: &str,
+        overlay: Option<&str>,
+        settings: &ProviderSettings,
+        cache_settings: &CacheSettings,
+    ) -> Result<Self, std::io::Error> {
+        let mut blocks = Self::read_blocks(file, settings)?;
+        if let Some(overlay) = overlay {
+            for (account, provider_key, overlay_block) in Self::read_blocks(overlay, settings)? {
+                let block = match blocks.iter_mut().position(|(block_account, key, _)| {
+                    *block_account == account && *key == provider_key
+                }) {
+                    Some(i) => &mut blocks[i].2,
+                    None => {
+                        blocks.push((account, provider_key, Entries(vec![])));
+                        &mut blocks.last_mut().unwrap().2
+                    }
+                };
+                block
+                    .0
+                    .retain(|(symbol, _)| !overlay_block.0.iter().any(|(name, _)| name == symbol));
+                // a quantity of 0 only removes the symbol
+                block.0.extend(
+                    overlay_block
+                        .0
+                        .into_iter()
+                        .filter(|(_, lot)| lot.quantity > 0. || lot.amount.is_some()),
+                );
+            }
+        }
+
+        let mut instruments = vec![];
+        let mut history_limits = HashMap::new();
+        for (account, provider_key, block) in blocks {
+            let stocks = config::merge_duplicate_symbols(&provider_key, block);
+            let Some(provider) = Providers::build(&provider_key, settings) else {
+                eprintln!("Invalid provider: {}", provider_key);
+                continue;
+            };
+            if let Some(days) = settings.max_history_days(&provider_key, &provider) {
+                history_limits.insert(provider.get_provider_name(), days);
+            }
+            let provider = Arc::new(provider);
+            // the lots of a symbol make up a single position
+            let mut positions: Vec<Instrument> = vec![];
+            for (name, lot) in stocks {
+                match positions.iter_mut().find(|position| position.name == name) {
+                    Some(position) => position.lots.push(lot),
+                    None => positions.push(Instrument {
+                        name,
+                        provider: Arc::clone(&provider),
+                        account: account.clone(),
+                        lots: vec![lot],
+                    }),
+                }
+            }
+            instruments.extend(positions);
+        }
+
+        Ok(Self {
+            instruments,
+            portfolio: HashMap::default(),
+            extended: HashSet::default(),
+            currencies: HashMap::default(),
+            history_limits,
+            failed: HashSet::default(),
+            currency_precision: None,
+            model_fees: false,
+            base_currency: None,
+            fx_rates: HashMap::default(),
+            price_cacher: Arc::new(PriceCacher::new(cache_settings.clone())?),
+        })
+    }
+
+    /// The lots of a configuration file by account and provider key, in file order
+    fn read_blocks(file: &str, settings: &ProviderSettings) -> Result<Vec<Block>, std::io::Error> {
+        // "-" reads the configuration piped in, e.g. by a script generating it
+        let reader: Box<dyn Read> = if file == "-" {
+            Box::new(std::io::stdin())
+        } else {
+            Box::new(
+                fs::File::open(file)
+                    .map_err(|_| std::io::Error::other(format!("Unable to open {file}")))?,
+            )
+        };
+        let json: Entries<Section> = serde_json::from_reader(reader).map_err(|e| {
+            std::io::Error::other(format!("Unable to parse json in file {file}. Error: {e}"))
+        })?;
+
+        // the sections of each account in file order, those outside of any account first
+        let mut sections = vec![];
+        let mut accounts = vec![];
+        for (key, section) in json.0 {
+            match section {
+                Section::Account(account) => accounts.push((key, account)),
+                section => sections.push((None, key, section)),
+            }
+        }
+        for (account, account_sections) in accounts {
+            for (key, section) in account_sections.0 {
+                if let Section::Account(_) = section {
+                    eprintln!("Unexpected account {key} inside the account {account}");
+                    continue;
+                }
+                sections.push((Some(account.clone()), key, section));
+            }
+        }
+
+        // a key repeated within an account is kept by the loader, its blocks merged
+        let mut seen_keys = HashSet::new();
+        for (account, key, _) in &sections {
+            if !seen_keys.insert((account, key)) {
+                let scope = match account {
+                    Some(account) => format!("the account {account} of {file}"),
+                    None => file.to_owned(),
+                };
+                eprintln!("Warning: {key} appears more than once in {scope}, merging its blocks");
+            }
+        }
+
+        // the entries of each account and provider key in file order, the entries
+        // naming their provider going to it whatever their block
+        let mut blocks: Vec<Block> = vec![];
+        for (account, key, section) in sections {
+            let (block_key, entries) = match section {
+                Section::Provider(block) => (
+                    key,
+                    block
+                        .0
+                        .into_iter()
+                        .map(|(symbol, holding)| (symbol, Lot::from(holding)))
+                        .collect::<Vec<_>>(),
+                ),
+                Section::Instruments(entries) if key == INSTRUMENTS_KEY => (
+                    settings.default_provider.clone(),
+                    entries
+                        .into_iter()
+                        .map(|entry| (entry.symbol, Lot::from(entry.holding)))
+                        .collect(),
+                ),
+                Section::Instruments(_) => {
+                    eprintln!(
+                        "Unexpected list under {key}, only \"{INSTRUMENTS_KEY}\" can be a list"
+                    );
+                    continue;
+                }
+                Section::Account(_) => continue,
+            };
+            for (symbol, lot) in entries {
+                let (provider_key, symbol) = config::resolve_provider_key(&block_key, symbol, &lot);
+                match blocks.iter_mut().find(|(block_account, key, _)| {
+                    *block_account == account && *key == provider_key
+                }) {
+                    Some((_, _, block)) => block.0.push((symbol, lot)),
+                    None => {
+                        blocks.push((account.clone(), provider_key, Entries(vec![(symbol, lot)])))
+                    }
+                }
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Reads the stock positions of an OFX/QFX statement, priced by the default provider
+    pub(crate) fn from_ofx(
+        file: &str,
+        settings: &ProviderSettings,
+        cache_settings: &CacheSettings,
+    ) -> Result<Self, std::io::Error> {
+        let content = fs::read_to_string(file)
+            .map_err(|_| std::io::Error::other(format!("Unable to open {file}")))?;
+        let provider = Providers::build(&settings.default_provider, settings).ok_or_else(|| {
+            std::io::Error::other(format!("Invalid provider: {}", settings.default_provider))
+        })?;
+        let history_limits = settings
+            .max_history_days(&settings.default_provider, &provider)
+            .map(|days| (provider.get_provider_name(), days))
+            .into_iter()
+            .collect();
+        let provider = Arc::new(provider);
+
+        let mut instruments = vec![];
+        for (name, units) in ofx::stock_positions(&content)? {
+            instruments.push(Instrument {
+                name,
+                provider: Arc::clone(&provider),
+                account: None,
+                lots: vec![Lot {
+                    quantity: units.max(0.),
+                    ..Default::default()
+                }],
+            });
+        }
+
+        Ok(Self {
+            instruments,
+            portfolio: HashMap::default(),
+            extended: HashSet::default(),
+            currencies: HashMap::default(),
+            history_limits,
+            failed: HashSet::default(),
+            currency_precision: None,
+            model_fees: false,
+            base_currency: None,
+            fx_rates: HashMap::default(),
+            price_cacher: Arc::new(PriceCacher::new(cache_settings.clone())?),
+        })
+    }
+
+    /// A portfolio holding one unit of each symbol, all priced by the same provider
+    pub(crate) fn from_symbols(
+        symbols: &[String],
+        provider_key: &str,
+        settings: &ProviderSettings,
+        cache_settings: &CacheSettings,
+    ) -> Result<Self, std::io::Error> {
+        let provider = Providers::build(provider_key, settings)
+            .ok_or_else(|| std::io::Error::other(format!("Invalid provider: {provider_key}")))?;
+        let history_limits = settings
+            .max_history_days(provider_key, &provider)
+            .map(|days| (provider.get_provider_name(), days))
+            .into_iter()
+            .collect();
+        let provider = Arc::new(provider);
+        Ok(Self {
+            instruments: symbols
+                .iter()
+                .map(|name| Instrument {
+                    name: name.clone(),
+                    provider: Arc::clone(&provider),
+                    account: None,
+                    lots: vec![Lot {
+                        quantity: 1.,
+                        ..Default::default()
+                    }],
+                })
+                .collect(),
+            portfolio: HashMap::default(),
+            extended: HashSet::default(),
+            currencies: HashMap::default(),
+            history_limits,
+            failed: HashSet::default(),
+            currency_precision: None,
+            model_fees: false,
+            base_currency: None,
+            fx_rates: HashMap::default(),
+            price_cacher: Arc::new(PriceCacher::new(cache_settings.clone())?),
+        })
+    }
+
+    /// The configured instruments, in file order
+    pub(crate) fn instruments(&self) -> impl Iterator<Item = &Instrument> {
+        self.instruments.iter()
+    }
+
+    /// Derives the quantity of the lots given by the amount invested from the price
+    /// paid, downloading the close on their buy_date if it's not configured
+    pub(crate) async fn resolve_amounts(&mut self) -> Result<(), std::io::Error> {
+        for instrument in &mut self.instruments {
+            for lot in &mut instrument.lots {
+                let (Some(amount), Some(buy_date)) = (lot.amount, lot.buy_date) else {
+                    continue;
+                };
+                let (_, _, close, quote_currency) = self
+                    .price_cacher
+                    .download_price(
+                        Arc::clone(&instrument.provider),
+                        instrument.name.clone(),
+                        buy_date,
+                    )
+                    .await?;
+                if let Some(currency) = lot.currency.as_ref().filter(|c| **c != quote_currency) {
+                    return Err(std::io::Error::other(format!(
+                        "The amount invested in {} is in {currency} but it's quoted in {quote_currency}",
+                        instrument.name
+                    )));
+                }
+                let price = lot.buy_price.unwrap_or(close);
+                if price <= 0. {
+                    return Err(std::io::Error::other(format!(
+                        "Unable to derive the quantity of {} bought on {buy_date} from its price {price}",
+                        instrument.name
+                    )));
+                }
+                lot.quantity = amount / price;
+                lot.buy_price = Some(price);
+            }
+        }
+        Ok(())
+    }
+
+    /// Matches the sells of each instrument against its lots first in, first out:
+    /// a sell takes from the oldest lot held on its date, whichever lot lists it, and
+    /// goes on to the next ones for the rest. Warns about the quantities sold beyond
+    /// what was held
+    pub(crate) fn match_sells(&mut self) {
+        for instrument in &mut self.instruments {
+            let unmatched = instrument.match_sells();
+            if unmatched > 0. {
+                eprintln!(
+                    "Warning: the sells of {} exceed its lots by {unmatched}",
+                    instrument.name
+                );
+            }
+        }
+    }
+
+    /// Keeps only the instruments held in an account
+    pub(crate) fn retain_account(&mut self, account: &str) -> Result<(), std::io::Error> {
+        if !self
+            .instruments
+            .iter()
+            .any(|instrument| instrument.get_account() == Some(account))
+        {
+            return Err(std::io::Error::other(format!("Unknown account: {account}")));
+        }
+        self.instruments
+            .retain(|instrument| instrument.get_account() == Some(account));
+        Ok(())
+    }
+
+    /// Whether the instruments are organized in accounts
+    pub(crate) fn has_accounts(&self) -> bool {
+        self.instruments
+            .iter()
+            .any(|instrument| instrument.account.is_some())
+    }
+
+    /// Replaces the quantity held of an instrument, e.g. for what-if analysis. Its
+    /// lots are replaced by a single undated lot of the quantity, held on every date
+    pub(crate) fn override_quantity(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+    ) -> Result<(), std::io::Error> {
+        let mut found = false;
+        for instrument in self.instruments.iter_mut().filter(|i| i.name == symbol) {
+            instrument.lots = if found {
+                vec![]
+            } else {
+                vec![Lot {
+                    quantity,
+                    ..Default::default()
+                }]
+            };
+            found = true;
+        }
+        if !found {
+            return Err(std::io::Error::other(format!(
+                "Unable to override the quantity of {symbol}: unknown symbol"
+            )));
+        }
+        Ok(())
+    }
+
+    /// The price and currency of an instrument cached on a date
+    pub(crate) fn cached_price(
+        &self,
+        instrument: &Instrument,
+        date: NaiveDate,
+    ) -> Option<(f64, String)> {
+        self.price_cacher
+            .cached_price(&instrument.provider, &instrument.name, date)
+    }
+
+    /// Every price of an instrument in the cache, in date order
+    pub(crate) fn cached_history(&self, instrument: &Instrument) -> Vec<(NaiveDate, f64)> {
+        self.price_cacher
+            .cached_prices(
+                &instrument.provider,
+                &instrument.name,
+                NaiveDate::from_ymd_opt(1, 1, 1).unwrap(),
+                NaiveDate::from_ymd_opt(9999, 12, 31).unwrap(),
+            )
+            .into_iter()
+            .map(|(_, date, price, _)| (date, price))
+            .collect()
+    }
+
+    /// Spawns the download of every instrument's price for each of the dates,
+    /// announcing each provider queried unless quiet. The instruments of providers
+    /// supporting ranges are downloaded with one request each, the others date by date
+    pub(crate) fn get_prices(
+        &self,
+        dates: &[NaiveDate],
+        quiet: bool,
+    ) -> (Vec<PriceDownload>, Vec<PriceRangeDownload>) {
+        let mut quotes_join_handles = vec![];
+        let mut range_join_handles = vec![];
+        let mut queried_providers = HashSet::new();
+        for instrument in &self.instruments {
+            let provider_name = instrument.provider.get_provider_name();
+            let first_query = queried_providers.insert(provider_name.clone());
+            if first_query && !quiet {
+                println!("Querying {provider_name}...");
+            }
+            let dates = self.within_history(&provider_name, dates, first_query);
+            if instrument.provider.supports_ranges() {
+                let jh = self.spawn_range(instrument, dates.clone());
+                range_join_handles.push((instrument.key(), dates, jh));
+                continue;
+            }
+            for date in dates {
+                let ticker = instrument.name.clone();
+                let price_cacher_ref = Arc::clone(&self.price_cacher);
+                let provider_ref = Arc::clone(&instrument.provider);
+                let jh = tokio::spawn(async move {
+                    price_cacher_ref
+                        .download_price(provider_ref, ticker, date)
+                        .await
+                });
+                quotes_join_handles.push((instrument.key(), date, jh));
+            }
+        }
+        (quotes_join_handles, range_join_handles)
+    }
+
+    /// Spawns the download of every instrument's prices for the dates not cached yet,
+    /// one request per instrument for the providers supporting ranges
+    pub(crate) fn get_prices_range(
+        &self,
+        dates: &[NaiveDate],
+    ) -> Vec<JoinHandle<PriceRangeResult>> {
+        let mut queried_providers = HashSet::new();
+        self.instruments
+            .iter()
+            .map(|instrument| {
+                let provider_name = instrument.provider.get_provider_name();
+                let first_query = queried_providers.insert(provider_name.clone());
+                let dates = self.within_history(&provider_name, dates, first_query);
+                self.spawn_range(instrument, dates)
+            })
+            .collect()
+    }
+
+    fn spawn_range(
+        &self,
+        instrument: &Instrument,
+        dates: Vec<NaiveDate>,
+    ) -> JoinHandle<PriceRangeResult> {
+        let ticker = instrument.name.clone();
+        let price_cacher_ref = Arc::clone(&self.price_cacher);
+        let provider_ref = Arc::clone(&instrument.provider);
+        tokio::spawn(async move {
+            price_cacher_ref
+                .download_prices_bulk(provider_ref, ticker, dates)
+                .await
+        })
+    }
+
+    /// The dates a provider has prices for, warning about the older ones if asked to
+    fn within_history(
+        &self,
+        provider_name: &str,
+        dates: &[NaiveDate],
+        warn: bool,
+    ) -> Vec<NaiveDate> {
+        let Some(days) = self.history_limits.get(provider_name) else {
+            return dates.to_vec();
+        };
+        let oldest = Utc::now().date_naive() - Days::new(*days as u64);
+        let (recent, old): (Vec<NaiveDate>, Vec<NaiveDate>) =
+            dates.iter().partition(|date| **date >= oldest);
+        if warn && !old.is_empty() {
+            eprintln!(
+                "Warning: {provider_name} only has prices for the past {days} days, skipping {} older dates",
+                old.len()
+            );
+        }
+        recent
+    }
+
+    /// Spawns the download of every instrument's latest price on a date straight from
+    /// the providers, as the cached price of a trading day in progress is outdated
+    pub(crate) fn get_live_prices(&self, date: NaiveDate) -> Vec<PriceDownload> {
+        self.instruments
+            .iter()
+            .map(|instrument| {
+                let ticker = instrument.name.clone();
+                let provider_ref = Arc::clone(&instrument.provider);
+                let jh =
+                    tokio::spawn(async move { provider_ref.download_price(&ticker, date).await });
+                (instrument.key(), date, jh)
+            })
+            .collect()
+    }
+
+    /// Stores the prices as the downloads complete, returning the errors of those
+    /// which failed
+    pub(crate) async fn wait_for_prices(
+        &mut self,
+        quotes_join_handles: Vec<PriceDownload>,
+        debug: bool,
+    ) -> Vec<std::io::Error> {
+        let mut errors = vec![];
+        for (key, date, j) in quotes_join_handles {
+            if let Err(e) = self.store_price(key, date, j.await.unwrap(), debug) {
+                errors.push(e);
+            }
+        }
+        errors
+    }
+
+    /// Stores the prices as the range downloads complete, returning an error for
+    /// each date whose price couldn't be downloaded
+    pub(crate) async fn wait_for_price_ranges(
+        &mut self,
+        range_join_handles: Vec<PriceRangeDownload>,
+        debug: bool,
+    ) -> Vec<std::io::Error> {
+        let mut errors = vec![];
+        for (key, dates, j) in range_join_handles {
+            let mut prices = match j.await.unwrap() {
+                Ok(prices) => prices,
+                Err(e) => {
+                    if debug {
+                        eprintln!("Error {e:#?}")
+                    }
+                    for date in dates {
+                        let e = std::io::Error::other(e.to_string());
+                        if let Err(e) = self.store_price(key.clone(), date, Err(e), false) {
+                            errors.push(e);
+                        }
+                    }
+                    continue;
+                }
+            };
+            for date in dates {
+                let result = match prices.iter().position(|price| price.1 == date) {
+                    Some(index) => Ok(prices.swap_remove(index)),
+                    None => Err(std::io::Error::other(format!(
+                        "No price for {} on {date}",
+                        key.1
+                    ))),
+                };
+                if let Err(e) = self.store_price(key.clone(), date, result, debug) {
+                    errors.push(e);
+                }
+            }
+        }
+        errors
+    }
+
+    /// Stores a downloaded price, or records the failure of its download
+    fn store_price(
+        &mut self,
+        key: QuoteKey,
+        date: NaiveDate,
+        result: PriceResult,
+        debug: bool,
+    ) -> Result<(), std::io::Error> {
+        match result {
+            Ok((ticker, date, price, currency)) => {
+                let price = self.round_to_tick(&key, price);
+                if debug {
+                    let quantity = self.quantity_of(&key, date);
+                    println!(
+                        "Quote at close for {ticker} on {date}: {price} {currency} * {} = {}",
+                        quantity,
+                        price * quantity
+                    );
+                }
+                self.currencies.insert(key.clone(), currency);
+                self.extended.remove(&(date, key.clone()));
+                self.failed.remove(&(date, key.clone()));
+                let day_quotes = self.portfolio.entry(date).or_default();
+                day_quotes.insert(key, price);
+                Ok(())
+            }
+            Err(e) => {
+                if debug {
+                    eprintln!("Error {e:#?}")
+                }
+                // a later successful download supersedes the failure
+                if !self
+                    .portfolio
+                    .get(&date)
+                    .is_some_and(|day_quotes| day_quotes.contains_key(&key))
+                {
+                    self.failed.insert((date, key));
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Rounds a price to the nearest tick of the instrument, if it has a tick size
+    fn round_to_tick(&self, key: &QuoteKey, price: f64) -> f64 {
+        match self
+            .instruments
+            .iter()
+            .filter(|instrument| instrument.key() == *key)
+            .find_map(Instrument::tick_size)
+        {
+            Some(tick_size) => {
+                let ticks = (price / tick_size).round();
+                // dividing by the ticks per unit avoids the float noise of
+                // multiplying by a decimal tick size, e.g. 95 * 0.01
+                if tick_size < 1. {
+                    ticks / tick_size.recip()
+                } else {
+                    ticks * tick_size
+                }
+            }
+            None => price,
+        }
+    }
+
+    /// The first date having quotes, or else a date
+    fn first_quoted_date(&self, date: NaiveDate) -> NaiveDate {
+        self.portfolio.keys().min().copied().unwrap_or(date)
+    }
+
+    /// The quantity of a lot valued on a date: net of the fees when they are
+    /// modelled, charged since the first date having quotes without a buy_date
+    fn valued_lot_quantity(&self, instrument: &Instrument, lot: &Lot, date: NaiveDate) -> f64 {
+        if !self.model_fees {
+            return lot.quantity_on(date);
+        }
+        instrument.lot_quantity_net_of_fees(lot, date, self.first_quoted_date(date))
+    }
+
+    /// The value of an instrument's lots at a price on a date, each lot's quantity
+    /// converted at its own rate
+    fn lots_value(
+        &self,
+        instrument: &Instrument,
+        price: f64,
+        date: NaiveDate,
+        quantity: impl Fn(&Lot) -> f64,
+    ) -> f64 {
+        instrument
+            .lots
+            .iter()
+            .map(|lot| price * quantity(lot) * self.lot_fx_rate(instrument, lot, date))
+            .sum()
+    }
+
+    fn quantity_of(&self, key: &QuoteKey, date: NaiveDate) -> f64 {
+        self.instruments
+            .iter()
+            .filter(|instrument| instrument.key() == *key)
+            .map(|instrument| instrument.quantity_on(date))
+            .sum()
+    }
+
+    /// Keys of the instruments without a quote on the given date
+    pub(crate) fn missing_quotes(&self, date: NaiveDate) -> Vec<QuoteKey> {
+        let mut missing = self
+            .instruments
+            .iter()
+            .map(Instrument::key)
+            .filter(|key| {
+                !self
+                    .portfolio
+                    .get(&date)
+                    .is_some_and(|day_quotes| day_quotes.contains_key(key))
+            })
+            .collect::<Vec<_>>();
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+
+    /// Fills the missing quotes of a date with each instrument's previous close
+    pub(crate) fn carry_previous_close(&mut self, date: NaiveDate) {
+        for key in self.missing_quotes(date) {
+            if let Some(price) = self.previous_close(date, &key) {
+                self.fill_quote(date, key, price);
+            }
+        }
+    }
+
+    /// The latest price of an instrument before a date
+    pub(crate) fn previous_close(&self, date: NaiveDate, key: &QuoteKey) -> Option<f64> {
+        self.portfolio
+            .iter()
+            .filter(|(quote_date, day_quotes)| **quote_date < date && day_quotes.contains_key(key))
+            .max_by_key(|(quote_date, _)| **quote_date)
+            .map(|(_, day_quotes)| day_quotes[key])
+    }
+
+    /// Sets a quote filled in rather than fetched
+    pub(crate) fn fill_quote(&mut self, date: NaiveDate, key: QuoteKey, price: f64) {
+        self.portfolio
+            .entry(date)
+            .or_default()
+            .insert(key.clone(), price);
+        self.extended.insert((date, key));
+    }
+
+    /// The quotes whose download failed, in date order
+    pub(crate) fn failed_quotes(&self) -> Vec<(NaiveDate, QuoteKey)> {
+        let mut failed = self.failed.iter().cloned().collect::<Vec<_>>();
+        failed.sort();
+        failed
+    }
+
+    /// Drops all the quotes of a date
+    pub(crate) fn remove_date(&mut self, date: NaiveDate) {
+        self.portfolio.remove(&date);
+        self.extended
+            .retain(|(extended_date, _)| *extended_date != date);
+    }
+
+    /// Right extends the prices in case they are not present for the latest day{s}.
+    /// YF is well known for this "feature"
+    pub(crate) fn extend_dates(&mut self) {
+        let sorted_dates = self.sorted_dates();
+        if sorted_dates.len() < 2 {
+            return;
+        }
+        let mut tickers = HashSet::new();
+        for portfolio_date in self.portfolio.values() {
+            portfolio_date.keys().for_each(|k| {
+                tickers.insert(k.clone());
+            });
+        }
+        for ticker in tickers {
+            let mut last_price = 0.;
+            for date in &sorted_dates {
+                let portfolio_date = self.portfolio.get_mut(date).unwrap();
+                match portfolio_date.get(&ticker) {
+                    Some(price) => last_price = *price,
+                    None => {
+                        portfolio_date.insert(ticker.clone(), last_price);
+                        self.extended.insert((*date, ticker.clone()));
+                    }
+                }
+            }
+        }
+    }
+
+    /// The price of an instrument on a date and whether it was filled in rather than fetched
+    pub(crate) fn price(&self, date: NaiveDate, key: &QuoteKey) -> Option<(f64, bool)> {
+        let price = self.portfolio.get(&date)?.get(key)?;
+        Some((*price, self.extended.contains(&(date, key.clone()))))
+    }
+
+    /// The latest date on or before a date with a fetched quote of an instrument
+    fn last_real_quote(&self, date: NaiveDate, key: &QuoteKey) -> Option<NaiveDate> {
+        self.sorted_dates()
+            .into_iter()
+            .rev()
+            .filter(|quote_date| *quote_date <= date)
+            .find(|quote_date| matches!(self.price(*quote_date, key), Some((_, false))))
+    }
+
+    /// The days between a date and each instrument's latest fetched quote on or
+    /// before it, leaving out the instruments never quoted
+    pub(crate) fn quote_ages(&self, date: NaiveDate) -> HashMap<String, i64> {
+        self.instruments
+            .iter()
+            .filter_map(|instrument| {
+                let quote_date = self.last_real_quote(date, &instrument.key())?;
+                Some((instrument.name.clone(), (date - quote_date).num_days()))
+            })
+            .collect()
+    }
+
+    /// The dates having at least one quote, in order
+    pub(crate) fn sorted_dates(&self) -> Vec<NaiveDate> {
+        let mut sorted_dates = self.portfolio.keys().copied().collect::<Vec<_>>();
+        sorted_dates.sort();
+        sorted_dates
+    }
+
+    /// The total value of the portfolio on a date, 0 if there are no quotes
+    pub(crate) fn portfolio_value(&self, date: NaiveDate) -> f64 {
+        self.sum_values(
+            self.instrument_values(date)
+                .into_iter()
+                .map(|(_, value)| value),
+        )
+    }
+
+    /// Sums the instruments' values, rounding each to --currency-precision first
+    fn sum_values(&self, values: impl Iterator<Item = f64>) -> f64 {
+        match self.currency_precision {
+            // summed as integers, e.g. of cents, so that the total is exact to them
+            Some(decimals) => {
+                let scale = 10f64.powi(decimals as i32);
+                values
+                    .map(|value| (value * scale).round() as i64)
+                    .sum::<i64>() as f64
+                    / scale
+            }
+            None => values.sum(),
+        }
+    }
+
+    /// Reduces the total values by the instruments' expense ratios
+    pub(crate) fn set_model_fees(&mut self, model_fees: bool) {
+        self.model_fees = model_fees;
+    }
+
+    /// Converts the values to the base currency at the rates of the other currencies,
+    /// by currency and date
+    pub(crate) fn set_fx_rates(
+        &mut self,
+        base_currency: &str,
+        fx_rates: HashMap<String, BTreeMap<NaiveDate, f64>>,
+    ) {
+        self.base_currency = Some(base_currency.to_owned());
+        self.fx_rates = fx_rates;
+    }
+
+    pub(crate) fn base_currency(&self) -> Option<&str> {
+        self.base_currency.as_deref()
+    }
+
+    /// The currencies the instruments are quoted in other than the base currency,
+    /// those quoted in a minor unit (e.g. GBp) counting as quoted in its currency
+    pub(crate) fn foreign_currencies(&self, base_currency: &str) -> Vec<String> {
+        let mut currencies = self
+            .currencies
+            .values()
+            .map(|currency| major_currency(currency).0.to_owned())
+            .filter(|currency| currency != base_currency)
+            .collect::<Vec<_>>();
+        currencies.sort();
+        currencies.dedup();
+        currencies
+    }
+
+    /// The rate converting the price of an instrument on a date to the base currency:
+    /// the latest one on or before it, or else the first. 1 without a base currency,
+    /// and only the minor unit scaled for a currency without rates
+    pub(crate) fn fx_rate(&self, key: &QuoteKey, date: NaiveDate) -> f64 {
+        let Some(currency) = self.base_currency.as_ref().and(self.currencies.get(key)) else {
+            return 1.;
+        };
+        let (currency, units) = major_currency(currency);
+        let rate = self
+            .fx_rates
+            .get(currency)
+            .and_then(|rates| {
+                rates
+                    .range(..=date)
+                    .next_back()
+                    .map(|(_, rate)| rate)
+                    .or_else(|| rates.values().next())
+            })
+            .copied()
+            .unwrap_or(1.);
+        units * rate
+    }
+
+    /// The rate converting a lot's price on a date to the base currency: a hedged
+    /// lot keeps the rate of its buy_date, or else of the first date having quotes
+    fn lot_fx_rate(&self, instrument: &Instrument, lot: &Lot, date: NaiveDate) -> f64 {
+        let date = match lot.hedged {
+            true => lot.buy_date.unwrap_or_else(|| self.first_quoted_date(date)),
+            false => date,
+        };
+        self.fx_rate(&instrument.key(), date)
+    }
+
+    /// Rounds the instruments' values to these decimals before summing the totals
+    pub(crate) fn set_currency_precision(&mut self, decimals: Option<u32>) {
+        self.currency_precision = decimals;
+    }
+
+    /// The value of each instrument having a quote on a date, in file order
+    pub(crate) fn instruments_and_values(&self, date: NaiveDate) -> Vec<(String, f64)> {
+        self.instrument_values(date)
+            .into_iter()
+            .map(|(instrument, value)| (instrument.name.clone(), value))
+            .collect()
+    }
+
+    /// Each instrument having a quote on a date with its value, in file order
+    pub(crate) fn instrument_values(&self, date: NaiveDate) -> Vec<(&Instrument, f64)> {
+        let Some(day_quotes) = self.portfolio.get(&date) else {
+            return vec![];
+        };
+        self.instruments
+            .iter()
+            .filter_map(|instrument| {
+                day_quotes.get(&instrument.key()).map(|price| {
+                    let value = self.lots_value(instrument, *price, date, |lot| {
+                        self.valued_lot_quantity(instrument, lot, date)
+                    });
+                    (instrument, value)
+                })
+            })
+            .collect()
+    }
+
+    /// The total value of the portfolio on a date computed only from fetched quotes,
+    /// None if any instrument's quote was filled in or is missing
+    pub(crate) fn portfolio_value_real(&self, date: NaiveDate) -> Option<f64> {
+        let day_quotes = self.portfolio.get(&date)?;
+        self.instruments
+            .iter()
+            .map(|instrument| {
+                if self.extended.contains(&(date, instrument.key())) {
+                    return None;
+                }
+                day_quotes.get(&instrument.key()).map(|price| {
+                    self.lots_value(instrument, *price, date, |lot| lot.quantity_on(date))
+                })
+            })
+            .sum()
+    }
+
+    /// The first of the dates on which every instrument has a fetched quote
+    pub(crate) fn first_full_day(&self, dates: &[NaiveDate]) -> Option<NaiveDate> {
+        dates
+            .iter()
+            .find(|date| self.portfolio_value_real(**date).is_some())
+            .copied()
+    }
+
+    /// The value of the instruments at their first fetched quote among the dates, as
+    /// if each one was bought on that day
+    pub(crate) fn first_quotes_value(&self, dates: &[NaiveDate]) -> f64 {
+        self.instruments
+            .iter()
+            .filter_map(|instrument| {
+                let (date, price) =
+                    dates
+                        .iter()
+                        .find_map(|date| match self.price(*date, &instrument.key()) {
+                            Some((price, false)) => Some((*date, price)),
+                            _ => None,
+                        })?;
+                Some(self.lots_value(instrument, price, date, |lot| lot.quantity_on(date)))
+            })
+            .sum()
+    }
+
+    /// The fraction of the dates on which every instrument has a fetched quote
+    pub(crate) fn coverage(&self, dates: &[NaiveDate]) -> f64 {
+        if dates.is_empty() {
+            return 0.;
+        }
+        let covered = dates
+            .iter()
+            .filter(|date| self.portfolio_value_real(**date).is_some())
+            .count();
+        covered as f64 / dates.len() as f64
+    }
+
+    /// The average yield of the instruments on a date weighted by their value, in
+    /// percent, those without a yield counting as paying nothing. None if nothing
+    /// has a yield or a value
+    pub(crate) fn weighted_yield(&self, date: NaiveDate) -> Option<f64> {
+        let mut total = 0.;
+        let mut income = None;
+        for (instrument, value) in self.instrument_values(date) {
+            total += value;
+            if let Some(yield_pct) = instrument.yield_pct() {
+                *income.get_or_insert(0.) += value * yield_pct;
+            }
+        }
+        income.filter(|_| total > 0.).map(|income| income / total)
+    }
+
+    /// The value of the portfolio on a date for each asset class
+    pub(crate) fn portfolio_value_by_asset_class(
+        &self,
+        date: NaiveDate,
+    ) -> BTreeMap<AssetClass, f64> {
+        self.sum_by(
+            self.instrument_values(date)
+                .into_iter()
+                .map(|(instrument, value)| (instrument.provider.asset_class(), value)),
+        )
+    }
+
+    /// The value of the portfolio on a date for each account, "-" standing for the
+    /// instruments outside of any account
+    pub(crate) fn portfolio_value_by_account(&self, date: NaiveDate) -> BTreeMap<String, f64> {
+        self.sum_by(
+            self.instrument_values(date)
+                .into_iter()
+                .map(|(instrument, value)| {
+                    (instrument.get_account().unwrap_or("-").to_owned(), value)
+                }),
+        )
+    }
+
+    /// The currency an instrument is quoted in, known once a price was downloaded
+    pub(crate) fn currency_of(&self, key: &QuoteKey) -> Option<&str> {
+        self.currencies.get(key).map(String::as_str)
+    }
+
+    /// The value of the portfolio on a date for each currency the instruments are quoted
+    /// in, converted to the base currency if there's one
+    pub(crate) fn portfolio_value_by_currency(&self, date: NaiveDate) -> BTreeMap<String, f64> {
+        self.sum_by(
+            self.instrument_values(date)
+                .into_iter()
+                .map(|(instrument, value)| {
+                    let currency = self.currency_of(&instrument.key()).unwrap_or_default();
+                    (currency.to_owned(), value)
+                }),
+        )
+    }
+
+    /// Sums the instruments' values of each group like the total, so that the groups
+    /// add up to it
+    fn sum_by<K: Ord>(&self, values: impl Iterator<Item = (K, f64)>) -> BTreeMap<K, f64> {
+        let mut groups: BTreeMap<K, Vec<f64>> = BTreeMap::default();
+        for (group, value) in values {
+            groups.entry(group).or_default().push(value);
+        }
+        groups
+            .into_iter()
+            .map(|(group, values)| (group, self.sum_values(values.into_iter())))
+            .collect()
+    }
+
+    /// Each instrument's contribution to the return between the first and last of the
+    /// dates having quotes, in file order: its return weighted by its average share of
+    /// the portfolio over those dates. The contributions sum to the total return when
+    /// the shares stay put, and approximately when they drift
+    pub(crate) fn contributions(&self, dates: &[NaiveDate]) -> Vec<(String, f64)> {
+        let quoted_dates = dates
+            .iter()
+            .copied()
+            .filter(|date| self.portfolio.contains_key(date) && self.portfolio_value(*date) != 0.)
+            .collect::<Vec<_>>();
+        let (Some(first), Some(last)) = (quoted_dates.first(), quoted_dates.last()) else {
+            return vec![];
+        };
+        let mut contributions: Vec<(String, f64)> = vec![];
+        let mut seen = HashSet::new();
+        for instrument in &self.instruments {
+            let key = instrument.key();
+            if !seen.insert(key.clone()) {
+                continue;
+            }
+            let weight = quoted_dates
+                .iter()
+                .map(|date| {
+                    self.instrument_values(*date)
+                        .into_iter()
+                        .filter(|(other, _)| other.key() == key)
+                        .map(|(_, value)| value)
+                        .sum::<f64>()
+                        / self.portfolio_value(*date)
+                })
+                .sum::<f64>()
+                / quoted_dates.len() as f64;
+            // the value of the quantities held at the start, converted like the lots
+            let start_value = |date: NaiveDate| {
+                let price = self.portfolio[&date].get(&key).copied().unwrap_or_default();
+                self.instruments
+                    .iter()
+                    .filter(|other| other.key() == key)
+                    .map(|other| self.lots_value(other, price, date, |lot| lot.quantity_on(*first)))
+                    .sum::<f64>()
+            };
+            let start = start_value(*first);
+            let instrument_return = if start != 0. {
+                start_value(*last) / start - 1.
+            } else {
+                0.
+            };
+            contributions.push((instrument.name.clone(), instrument_return * weight));
+        }
+        contributions
+    }
+
+    /// Each instrument's price return between its first fetched quote among the dates
+    /// and its last quote, in file order
+    pub(crate) fn instrument_returns(&self, dates: &[NaiveDate]) -> Vec<(String, f64)> {
+        let mut returns: Vec<(String, f64)> = vec![];
+        let mut seen = HashSet::new();
+        for instrument in &self.instruments {
+            if !seen.insert(instrument.key()) {
+                continue;
+            }
+            let first = dates
+                .iter()
+                .find_map(|date| match self.price(*date, &instrument.key()) {
+                    Some((price, false)) if price != 0. => Some(price),
+                    _ => None,
+                });
+            let last = dates
+                .iter()
+                .rev()
+                .find_map(|date| self.price(*date, &instrument.key()));
+            if let (Some(first), Some((last, _))) = (first, last) {
+                returns.push((instrument.name.clone(), last / first - 1.));
+            }
+        }
+        returns
+    }
+
+    /// The total value for each date having quotes, in order
+    pub(crate) fn value_series(&self) -> Vec<(NaiveDate, f64)> {
+        self.sorted_dates()
+            .into_iter()
+            .map(|date| (date, self.portfolio_value(date)))
+            .collect()
+    }
+
+    /// The blend of the instruments' prices, each normalized to 1 on the first date
+    /// and weighted so that the blend starts at 1. Instruments without a quote on
+    /// the first date are left out
+    pub(crate) fn weighted_index(&self, weights: &[(String, f64)]) -> Vec<(NaiveDate, f64)> {
+        let sorted_dates = self.sorted_dates();
+        let Some(first_quotes) = sorted_dates.first().map(|date| &self.portfolio[date]) else {
+            return vec![];
+        };
+        let components = weights
+            .iter()
+            .filter_map(|(name, weight)| {
+                let instrument = self.instruments.iter().find(|i| i.name == *name)?;
+                let key = instrument.key();
+                first_quotes
+                    .get(&key)
+                    .filter(|price| **price != 0.)
+                    .map(|price| (key, *weight, *price))
+            })
+            .collect::<Vec<_>>();
+        let total_weight: f64 = components.iter().map(|(_, weight, _)| weight).sum();
+        if total_weight == 0. {
+            return vec![];
+        }
+        sorted_dates
+            .into_iter()
+            .map(|date| {
+                let day_quotes = &self.portfolio[&date];
+                let value = components
+                    .iter()
+                    .map(|(key, weight, start_price)| {
+                        let price = day_quotes.get(key).copied().unwrap_or(*start_price);
+                        weight * price / start_price
+                    })
+                    .sum::<f64>();
+                (date, value / total_weight)
+            })
+            .collect()
+    }
+
+    /// The money-weighted annual return of each instrument, in file order: its lots are
+    /// bought at buy_price and either sold at sell_price or valued at the close of date.
+    /// None for the instruments without any lot bought before date
+    pub(crate) async fn instrument_xirrs(&self, date: NaiveDate) -> Vec<(String, Option<f64>)> {
+        let mut xirrs = vec![];
+        for instrument in &self.instruments {
+            let mut cash_flows = vec![];
+            for lot in &instrument.lots {
+                match self.lot_cash_flows(instrument, lot, date).await {
+                    Ok(lot_cash_flows) => cash_flows.extend(lot_cash_flows),
+                    Err(e) => eprintln!("{e}"),
+                }
+            }
+            xirrs.push((instrument.name.clone(), xirr::xirr(&cash_flows)));
+        }
+        xirrs
+    }
+
+    /// The return of each lot bought before date, in file order: from its buy_price
+    /// (or else the close on its buy_date) to its price on date, whatever the window
+    pub(crate) async fn since_inception_returns(
+        &self,
+        date: NaiveDate,
+    ) -> Vec<(String, NaiveDate, f64)> {
+        let mut returns = vec![];
+        for (instrument, lot) in self.lots() {
+            let Some(buy_date) = lot.buy_date.filter(|buy_date| *buy_date <= date) else {
+                continue;
+            };
+            let buy_price = match lot.buy_price {
+                Some(price) => Ok(price),
+                None => self.close_on(instrument, buy_date).await,
+            };
+            let current_price = match self.price(date, &instrument.key()) {
+                Some((price, _)) => Ok(price),
+                None => self.close_on(instrument, date).await,
+            };
+            match (buy_price, current_price) {
+                (Ok(buy_price), Ok(current_price)) if buy_price != 0. => returns.push((
+                    instrument.name.clone(),
+                    buy_date,
+                    current_price / buy_price - 1.,
+                )),
+                (Err(e), _) | (_, Err(e)) => eprintln!("{e}"),
+                _ => {}
+            }
+        }
+        returns
+    }
+
+    /// The equity of a simulation replaying the lots' buys and sells on each date
+    /// having quotes: the capital invested in them all, less the purchases made,
+    /// plus the sales made and the value of the lots held. The lots trade at their
+    /// buy_price and sell_price or else the close, their partial sells at the close,
+    /// and the lots without a buy_date are left out
+    pub(crate) async fn equity_curve(&self) -> Result<Vec<(NaiveDate, f64)>, std::io::Error> {
+        // each lot's instrument, purchase as (date, price), partial sells as (date, quantity,
+        // price) and sale as (date, price)
+        let mut trades = vec![];
+        for (instrument, lot) in self.lots() {
+            let Some(buy_date) = lot.buy_date else {
+                eprintln!(
+                    "Warning: a lot of {} has no buy_date, leaving it out",
+                    instrument.name
+                );
+                continue;
+            };
+            let buy_price = match lot.buy_price {
+                Some(price) => price,
+                None => self.close_on(instrument, buy_date).await?,
+            } * self.lot_fx_rate(instrument, lot, buy_date);
+            let sale = match (lot.sell_date, lot.sell_price) {
+                (Some(sell_date), Some(price)) => Some((sell_date, price)),
+                (Some(sell_date), None) => {
+                    Some((sell_date, self.close_on(instrument, sell_date).await?))
+                }
+                (None, _) => None,
+            }
+            .map(|(sell_date, price)| {
+                (
+                    sell_date,
+                    price * self.lot_fx_rate(instrument, lot, sell_date),
+                )
+            });
+            let mut sells = vec![];
+            for sell in &lot.sells {
+                let price = self.close_on(instrument, sell.date).await?
+                    * self.lot_fx_rate(instrument, lot, sell.date);
+                sells.push((sell.date, sell.quantity, price));
+            }
+            trades.push((instrument, lot, (buy_date, buy_price), sells, sale));
+        }
+        let capital: f64 = trades
+            .iter()
+            .map(|(_, lot, (_, buy_price), _, _)| lot.quantity * buy_price)
+            .sum();
+
+        let mut curve = vec![];
+        // the latest price of each instrument, carried over the dates lacking one
+        let mut last_prices: HashMap<&QuoteKey, &f64> = HashMap::new();
+        for date in self.sorted_dates() {
+            last_prices.extend(&self.portfolio[&date]);
+            let mut equity = capital;
+            for (instrument, lot, (buy_date, buy_price), sells, sale) in &trades {
+                if *buy_date > date {
+                    continue;
+                }
+                equity -= lot.quantity * buy_price;
+                equity += sells
+                    .iter()
+                    .filter(|(sell_date, _, _)| *sell_date <= date)
+                    .map(|(_, quantity, price)| quantity * price)
+                    .sum::<f64>();
+                equity += match sale {
+                    Some((sell_date, sell_price)) if *sell_date <= date => {
+                        lot.quantity_on(*sell_date) * sell_price
+                    }
+                    _ => {
+                        lot.quantity_on(date)
+                            * last_prices
+                                .get(&instrument.key())
+                                .map(|price| *price * self.lot_fx_rate(instrument, lot, date))
+                                .unwrap_or(*buy_price)
+                    }
+                };
+            }
+            curve.push((date, equity));
+        }
+        Ok(curve)
+    }
+
+    /// What the lots still held on a date cost: their buy_price, or else the close on
+    /// their buy_date, times the quantity left after their sells, in the base currency
+    /// at the rate of their buy_date. None if a lot has neither
+    pub(crate) async fn cost_basis(&self, date: NaiveDate) -> Result<Option<f64>, std::io::Error> {
+        let mut cost_basis = 0.;
+        for (instrument, lot) in self.lots() {
+            let quantity = lot.quantity_on(date);
+            if quantity == 0. || lot.sell_date.is_some_and(|sell_date| sell_date <= date) {
+                continue;
+            }
+            let buy_price = match (lot.buy_price, lot.buy_date) {
+                (Some(price), _) => price,
+                (None, Some(buy_date)) => self.close_on(instrument, buy_date).await?,
+                (None, None) => return Ok(None),
+            };
+            let fx_rate = self.lot_fx_rate(instrument, lot, lot.buy_date.unwrap_or(date));
+            cost_basis += buy_price * fx_rate * quantity;
+        }
+        Ok(Some(cost_basis))
+    }
+
+    /// Each instrument's cost basis and profit or loss on a date, in file order, over
+    /// its lots bought by then: realized by the sales up to date, at their sell_price
+    /// or else the close, and unrealized at the price on date for what's still held
+    pub(crate) async fn cost_basis_and_pnl(&self, date: NaiveDate) -> Vec<(String, f64, f64)> {
+        let mut pnls: Vec<(String, f64, f64)> = vec![];
+        for (instrument, lot) in self.lots() {
+            if lot.buy_date.is_some_and(|buy_date| buy_date > date) {
+                continue;
+            }
+            let (cost_basis, pnl) = match self.lot_pnl(instrument, lot, date).await {
+                Ok(lot_pnl) => lot_pnl,
+                Err(e) => {
+                    eprintln!("{e}");
+                    continue;
+                }
+            };
+            match pnls
+                .iter_mut()
+                .find(|(name, _, _)| *name == instrument.name)
+            {
+                Some((_, total_cost_basis, total_pnl)) => {
+                    *total_cost_basis += cost_basis;
+                    *total_pnl += pnl;
+                }
+                None => pnls.push((instrument.name.clone(), cost_basis, pnl)),
+            }
+        }
+        pnls
+    }
+
+    /// What a lot cost, at its buy_price or else the close on its buy_date, and its
+    /// realized plus unrealized profit or loss on a date, each price converted to the
+    /// base currency at the rate of the date it's paid or valued on
+    async fn lot_pnl(
+        &self,
+        instrument: &Instrument,
+        lot: &Lot,
+        date: NaiveDate,
+    ) -> Result<(f64, f64), std::io::Error> {
+        let buy_price = match (lot.buy_price, lot.buy_date) {
+            (Some(price), _) => price,
+            (None, Some(buy_date)) => self.close_on(instrument, buy_date).await?,
+            (None, None) => {
+                return Err(std::io::Error::other(format!(
+                    "No cost basis for a lot of {}: it has neither a buy_price nor a buy_date",
+                    instrument.name
+                )))
+            }
+        };
+        let fx_rate = |date| self.lot_fx_rate(instrument, lot, date);
+        let buy_price = buy_price * fx_rate(lot.buy_date.unwrap_or(date));
+        let mut pnl = 0.;
+        for sell in lot.sells.iter().filter(|sell| sell.date <= date) {
+            let sell_price = self.close_on(instrument, sell.date).await? * fx_rate(sell.date);
+            pnl += (sell_price - buy_price) * sell.quantity;
+        }
+        pnl += match lot.sell_date.filter(|sell_date| *sell_date <= date) {
+            Some(sell_date) => {
+                let sell_price = match lot.sell_price {
+                    Some(price) => price,
+                    None => self.close_on(instrument, sell_date).await?,
+                };
+                (sell_price * fx_rate(sell_date) - buy_price) * lot.quantity_on(sell_date)
+            }
+            None => {
+                let price = match self.price(date, &instrument.key()) {
+                    Some((price, _)) => price,
+                    None => self.close_on(instrument, date).await?,
+                };
+                (price * fx_rate(date) - buy_price) * lot.quantity_on(date)
+            }
+        };
+        Ok((buy_price * lot.quantity, pnl))
+    }
+
+    /// Every lot with its position, in file order
+    fn lots(&self) -> impl Iterator<Item = (&Instrument, &Lot)> {
+        self.instruments
+            .iter()
+            .flat_map(|instrument| instrument.lots.iter().map(move |lot| (instrument, lot)))
+    }
+
+    /// The purchase of a lot, its partial sells at the close and either its sale or
+    /// the value of what's left on date, in the base currency at the rates of their
+    /// dates
+    async fn lot_cash_flows(
+        &self,
+        instrument: &Instrument,
+        lot: &Lot,
+        date: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, f64)>, std::io::Error> {
+        let Some(buy_date) = lot.buy_date.filter(|buy_date| *buy_date <= date) else {
+            return Ok(vec![]);
+        };
+        let buy_price = match lot.buy_price {
+            Some(price) => price,
+            None => self.close_on(instrument, buy_date).await?,
+        };
+        let fx_rate = |date| self.lot_fx_rate(instrument, lot, date);
+        let mut flows = vec![(buy_date, -buy_price * lot.quantity * fx_rate(buy_date))];
+        for sell in lot.sells.iter().filter(|sell| sell.date <= date) {
+            let sell_price = self.close_on(instrument, sell.date).await?;
+            flows.push((sell.date, sell_price * sell.quantity * fx_rate(sell.date)));
+        }
+        let end_flow = match lot.sell_date.filter(|sell_date| *sell_date <= date) {
+            Some(sell_date) => {
+                let sell_price = match lot.sell_price {
+                    Some(price) => price,
+                    None => self.close_on(instrument, sell_date).await?,
+                };
+                (sell_date, sell_price * lot.quantity_on(sell_date))
+            }
+            None => {
+                let price = match self.price(date, &instrument.key()) {
+                    Some((price, _)) => price,
+                    None => self.close_on(instrument, date).await?,
+                };
+                (date, price * lot.quantity_on(date))
+            }
+        };
+        flows.push((end_flow.0, end_flow.1 * fx_rate(end_flow.0)));
+        Ok(flows)
+    }
+
+    /// The dates of the lots' purchases and sales, in file order
+    pub(crate) fn trade_dates(&self) -> Vec<NaiveDate> {
+        self.lots()
+            .flat_map(|(_, lot)| {
+                [lot.buy_date, lot.sell_date]
+                    .into_iter()
+                    .flatten()
+                    .chain(lot.sells.iter().map(|sell| sell.date))
+            })
+            .collect()
+    }
+
+    /// The purchases and sales of the lots between start and end (inclusive), in date
+    /// order, each with its description
+    pub(crate) fn transactions(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Vec<(NaiveDate, String)> {
+        let mut transactions = self
+            .lots()
+            .flat_map(|(instrument, lot)| {
+                // each partial sell, then what's left of the lot on its sell_date
+                let bought = lot.buy_date.map(|date| (date, "bought", lot.quantity));
+                let sells = lot
+                    .sells
+                    .iter()
+                    .map(|sell| (sell.date, "sold", sell.quantity));
+                let sold = lot
+                    .sell_date
+                    .map(|date| (date, "sold", lot.quantity_on(date)))
+                    .filter(|(_, _, quantity)| *quantity > 0.);
+                bought
+                    .into_iter()
+                    .chain(sells)
+                    .chain(sold)
+                    .map(|(date, action, quantity)| {
+                        (date, format!("{action} {quantity} {}", instrument.name))
+                    })
+            })
+            .filter(|(date, _)| *date >= start && *date <= end)
+            .collect::<Vec<_>>();
+        transactions.sort_by_key(|(date, _)| *date);
+        transactions
+    }
+
+    /// The fetched quotes further than max_pct percent from both the previous and
+    /// the next quotes of their instrument, e.g. a price off by 100x for a day, as
+    /// (name, date, price, previous price, next price)
+    pub(crate) fn suspicious_quotes(
+        &self,
+        max_pct: f64,
+    ) -> Vec<(String, NaiveDate, f64, f64, f64)> {
+        let sorted_dates = self.sorted_dates();
+        let deviates =
+            |price: f64, other: f64| other > 0. && (price / other - 1.).abs() * 100. > max_pct;
+        let mut keys = vec![];
+        for instrument in &self.instruments {
+            if !keys.contains(&instrument.key()) {
+                keys.push(instrument.key());
+            }
+        }
+        let mut suspicious = vec![];
+        for key in keys {
+            let prices = sorted_dates
+                .iter()
+                .filter_map(|date| match self.price(*date, &key) {
+                    Some((price, false)) => Some((*date, price)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            for window in prices.windows(3) {
+                let [(_, previous), (date, price), (_, next)] = window else {
+                    continue;
+                };
+                if deviates(*price, *previous) && deviates(*price, *next) {
+                    suspicious.push((key.1.clone(), *date, *price, *previous, *next));
+                }
+            }
+        }
+        suspicious
+    }
+
+    /// The stops and move thresholds crossed by the latest prices, in file order
+    pub(crate) fn alerts(&self) -> Vec<Alert> {
+        let sorted_dates = self.sorted_dates();
+        let mut alerts = vec![];
+        for instrument in &self.instruments {
+            let prices = sorted_dates
+                .iter()
+                .filter_map(|date| self.portfolio[date].get(&instrument.key()))
+                .collect::<Vec<_>>();
+            let (Some(first), Some(last)) = (prices.first(), prices.last()) else {
+                continue;
+            };
+            if let Some(stop_price) = instrument.stop_price() {
+                if **last < stop_price {
+                    alerts.push(Alert::Stop {
+                        name: instrument.name.clone(),
+                        price: **last,
+                        stop_price,
+                    });
+                }
+            }
+            if let Some(alert_pct) = instrument.alert_pct() {
+                if **first != 0. {
+                    let change_pct = (*last / *first - 1.) * 100.;
+                    if change_pct.abs() > alert_pct {
+                        alerts.push(Alert::Move {
+                            name: instrument.name.clone(),
+                            change_pct,
+                            alert_pct,
+                        });
+                    }
+                }
+            }
+        }
+        alerts
+    }
+
+    /// The gains realized by the lots sold during a year, one per sell, ordered by
+    /// sell date. Prices missing from the configuration are the close on the buy and
+    /// sell dates
+    pub(crate) async fn realized_gains(
+        &self,
+        year: i32,
+    ) -> Result<Vec<RealizedGain>, std::io::Error> {
+        let mut gains = vec![];
+        for (instrument, lot) in self.lots() {
+            // the partial sells, then the sale of what's left on sell_date
+            let sales = lot
+                .sells
+                .iter()
+                .map(|sell| (sell.date, None, sell.quantity))
+                .chain(
+                    lot.sell_date
+                        .map(|date| (date, lot.sell_price, lot.quantity_on(date))),
+                )
+                .filter(|(date, _, quantity)| date.year() == year && *quantity > 0.)
+                .collect::<Vec<_>>();
+            if sales.is_empty() {
+                continue;
+            }
+            let Some(buy_date) = lot.buy_date else {
+                return Err(std::io::Error::other(format!(
+                    "The lot of {} sold on {} has no buy_date",
+                    instrument.name, sales[0].0
+                )));
+            };
+            let buy_price = match lot.buy_price {
+                Some(price) => price,
+                None => self.close_on(instrument, buy_date).await?,
+            };
+            for (sell_date, sell_price, quantity) in sales {
+                let sell_price = match sell_price {
+                    Some(price) => price,
+                    None => self.close_on(instrument, sell_date).await?,
+                };
+                gains.push(RealizedGain {
+                    name: instrument.name.clone(),
+                    account: instrument.account.clone(),
+                    quantity,
+                    buy_date,
+                    sell_date,
+                    cost: buy_price * quantity,
+                    proceeds: sell_price * quantity,
+                });
+            }
+        }
+        gains.sort_by_key(|gain| gain.sell_date);
+        Ok(gains)
+    }
+
+    /// The closing price of an instrument on a date, rounded to its tick like the
+    /// portfolio's quotes
+    async fn close_on(
+        &self,
+        instrument: &Instrument,
+        date: NaiveDate,
+    ) -> Result<f64, std::io::Error> {
+        self.price_cacher
+            .download_price(
+                Arc::clone(&instrument.provider),
+                instrument.name.clone(),
+                date,
+            )
+            .await
+            .map(|(_, _, price, _)| self.round_to_tick(&instrument.key(), price))
+    }
+
+    /// Saves the total and the instruments' values on a date to the cache
+    pub(crate) fn save_snapshot(&self, file: &str, date: NaiveDate) -> Result<(), std::io::Error> {
+        self.price_cacher.store_snapshot(
+            file,
+            date,
+            self.portfolio_value(date),
+            &self.instruments_and_values(date),
+        )
+    }
+
+    pub(crate) fn cache_stats(&self) -> CacheStats {
+        self.price_cacher.stats()
+    }
+
+    /// The fetched quotes, ordered by date then provider and symbol
+    pub(crate) fn quotes(&self) -> Vec<(NaiveDate, &QuoteKey, f64)> {
+        let mut quotes = self
+            .portfolio
+            .iter()
+            .flat_map(|(date, prices)| prices.iter().map(|(key, price)| (*date, key, *price)))
+            .collect::<Vec<_>>();
+        quotes.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+        quotes
+    }
+}
+
+/// The currency of a minor unit, e.g. GBP for the pence London listings are quoted
+/// in (GBp), and the value of the unit in it
+fn major_currency(currency: &str) -> (&str, f64) {
+    match currency {
+        "GBp" | "GBX" => ("GBP", 0.01),
+        "ZAc" => ("ZAR", 0.01),
+        "ILA" => ("ILS", 0.01),
+        _ => (currency, 1.),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cash::Cash;
+    use std::path::PathBuf;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    /// The quote key of EUR cash
+    fn eur() -> QuoteKey {
+        ("Cash".to_owned(), "EUR".to_owned())
+    }
+
+    /// A portfolio of a single lot of EUR cash, priced 1 on each of the dates
+    fn portfolio(lot: Lot, dates: &[NaiveDate]) -> Portfolio {
+        let instrument = Instrument {
+            name: "EUR".to_owned(),
+            provider: Arc::new(Providers::Cash(Cash)),
+            account: None,
+            lots: vec![lot],
+        };
+        Portfolio {
+            instruments: vec![instrument],
+            portfolio: dates
+                .iter()
+                .map(|date| (*date, HashMap::from([(eur(), 1.)])))
+                .collect(),
+            extended: HashSet::default(),
+            currencies: HashMap::default(),
+            history_limits: HashMap::default(),
+            failed: HashSet::default(),
+            currency_precision: None,
+            model_fees: false,
+            base_currency: None,
+            fx_rates: HashMap::default(),
+            // the values are computed from the prices above, without the cache
+            price_cacher: Arc::new(
+                PriceCacher::with_path(PathBuf::from(":memory:"), CacheSettings::default())
+                    .unwrap(),
+            ),
+        }
+    }
+
+    #[test]
+    fn values_a_lot_from_its_buy_date() {
+        let dates = (1..=5).map(date).collect::<Vec<_>>();
+        let lot = Lot {
+            quantity: 100.,
+            buy_date: Some(date(3)),
+            ..Default::default()
+        };
+        let portfolio = portfolio(lot, &dates);
+        let values = dates
+            .iter()
+            .map(|date| portfolio.instruments_and_values(*date)[0].1)
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![0., 0., 100., 100., 100.]);
+        assert_eq!(portfolio.portfolio_value(date(2)), 0.);
+        assert_eq!(portfolio.portfolio_value(date(4)), 100.);
+    }
+
+    #[test]
+    fn values_a_lot_up_to_its_sell_date() {
+        let dates = (1..=5).map(date).collect::<Vec<_>>();
+        let lot = Lot {
+            quantity: 100.,
+            buy_date: Some(date(2)),
+            sell_date: Some(date(4)),
+            ..Default::default()
+        };
+        let portfolio = portfolio(lot, &dates);
+        let values = dates
+            .iter()
+            .map(|date| portfolio.portfolio_value(*date))
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![0., 100., 100., 100., 0.]);
+    }
+
+    #[test]
+    fn converts_the_values_to_the_base_currency() {
+        let dates = (1..=3).map(date).collect::<Vec<_>>();
+        let lot = Lot {
+            quantity: 100.,
+            ..Default::default()
+        };
+        let mut portfolio = portfolio(lot, &dates);
+        portfolio.currencies.insert(eur(), "EUR".to_owned());
+        assert_eq!(portfolio.foreign_currencies("USD"), vec!["EUR".to_owned()]);
+        let rates = BTreeMap::from([(date(2), 1.25), (date(3), 1.5)]);
+        portfolio.set_fx_rates("USD", HashMap::from([("EUR".to_owned(), rates)]));
+        let values = dates
+            .iter()
+            .map(|date| portfolio.portfolio_value(*date))
+            .collect::<Vec<_>>();
+        // the first rate standing for the day before it
+        assert_eq!(values, vec![125., 125., 150.]);
+        assert_eq!(portfolio.instruments_and_values(date(3))[0].1, 150.);
+    }
+
+    #[tokio::test]
+    async fn converts_the_cost_and_the_pnl_at_the_trade_dates() {
+        let dates = (1..=3).map(date).collect::<Vec<_>>();
+        let lot = Lot {
+            quantity: 100.,
+            buy_date: Some(date(1)),
+            buy_price: Some(1.),
+            ..Default::default()
+        };
+        let mut portfolio = portfolio(lot, &dates);
+        portfolio.currencies.insert(eur(), "EUR".to_owned());
+        let rates = BTreeMap::from([(date(1), 1.25), (date(3), 1.5)]);
+        portfolio.set_fx_rates("USD", HashMap::from([("EUR".to_owned(), rates)]));
+        // bought at 1.25 USD, now worth 1.5 USD for the same price in EUR
+        assert_eq!(
+            portfolio.cost_basis_and_pnl(date(3)).await,
+            vec![("EUR".to_owned(), 125., 25.)]
+        );
+        assert_eq!(portfolio.cost_basis(date(3)).await.unwrap(), Some(125.));
+        // the rate of the day before standing for date 2
+        assert_eq!(
+            portfolio.cost_basis_and_pnl(date(2)).await,
+            vec![("EUR".to_owned(), 125., 0.)]
+        );
+    }
+
+    #[test]
+    fn converts_a_hedged_lot_at_the_buy_date_rate() {
+        let dates = (1..=3).map(date).collect::<Vec<_>>();
+        let hedged = Lot {
+            quantity: 100.,
+            buy_date: Some(date(2)),
+            hedged: true,
+            ..Default::default()
+        };
+        let mut portfolio = portfolio(hedged, &dates);
+        // an older lot of the same symbol, unhedged
+        portfolio.instruments[0].lots.push(Lot {
+            quantity: 10.,
+            buy_date: Some(date(1)),
+            ..Default::default()
+        });
+        portfolio.currencies.insert(eur(), "EUR".to_owned());
+        let rates = BTreeMap::from([(date(1), 1.1), (date(2), 1.25), (date(3), 1.5)]);
+        portfolio.set_fx_rates("USD", HashMap::from([("EUR".to_owned(), rates)]));
+        // the hedged 100 at the rate of their buy date, the other 10 at the day's
+        assert_eq!(portfolio.portfolio_value(date(3)), 125. + 15.);
+        // without a buy_date, the rate of the first day
+        portfolio.instruments[0].lots[0].buy_date = None;
+        assert!((portfolio.portfolio_value(date(3)) - 125.).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn realizes_each_sell_of_a_trimmed_lot() {
+        let lot = Lot {
+            quantity: 100.,
+            buy_date: Some(date(1)),
+            buy_price: Some(0.5),
+            sells: vec![Sell {
+                date: date(2),
+                quantity: 40.,
+            }],
+            sell_date: Some(date(5)),
+            sell_price: Some(2.),
+            ..Default::default()
+        };
+        let portfolio = portfolio(lot, &[]);
+        // the 40 trimmed at the close of 1, the 60 left sold at 2
+        let gains = portfolio
+            .realized_gains(2024)
+            .await
+            .unwrap()
+            .iter()
+            .map(|gain| (gain.sell_date, gain.quantity, gain.cost, gain.proceeds))
+            .collect::<Vec<_>>();
+        assert_eq!(gains, [(date(2), 40., 20., 40.), (date(5), 60., 30., 120.)]);
+        assert!(portfolio.realized_gains(2023).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn orders_the_quotes_by_date_then_key() {
+        let mut portfolio = portfolio(Lot::default(), &[date(2), date(1)]);
+        let usd = ("Cash".to_owned(), "USD".to_owned());
+        portfolio
+            .portfolio
+            .get_mut(&date(1))
+            .unwrap()
+            .insert(usd.clone(), 1.);
+        assert_eq!(
+            portfolio.quotes(),
+            [
+                (date(1), &eur(), 1.),
+                (date(1), &usd, 1.),
+                (date(2), &eur(), 1.)
+            ]
+        );
+    }
+
+    #[test]
+    fn overrides_the_quantity_with_an_undated_lot() {
+        let lot = Lot {
+            quantity: 100.,
+            buy_date: Some(date(3)),
+            sells: vec![Sell {
+                date: date(4),
+                quantity: 40.,
+            }],
+            sell_date: Some(date(5)),
+            ..Default::default()
+        };
+        let mut portfolio = portfolio(lot.clone(), &[date(1), date(4), date(6)]);
+        portfolio.instruments[0].lots.push(lot);
+        portfolio.override_quantity("EUR", 10.).unwrap();
+        assert_eq!(portfolio.instruments[0].lots.len(), 1);
+        for day in [1, 4, 6] {
+            assert_eq!(portfolio.portfolio_value(date(day)), 10.);
+        }
+        assert!(portfolio.override_quantity("USD", 10.).is_err());
+    }
+
+    #[test]
+    fn marks_each_sell_of_a_trimmed_lot() {
+        let lot = Lot {
+            quantity: 100.,
+            buy_date: Some(date(1)),
+            sells: vec![
+                Sell {
+                    date: date(2),
+                    quantity: 40.,
+                },
+                Sell {
+                    date: date(9),
+                    quantity: 10.,
+                },
+            ],
+            sell_date: Some(date(5)),
+            ..Default::default()
+        };
+        let portfolio = portfolio(lot, &[]);
+        let name = &portfolio.instruments[0].name;
+        assert_eq!(
+            portfolio.transactions(date(2), date(8)),
+            [
+                (date(2), format!("sold 40 {name}")),
+                (date(5), format!("sold 60 {name}")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn matches_a_sell_spanning_lots_first_in_first_out() {
+        let old = Lot {
+            quantity: 10.,
+            buy_date: NaiveDate::from_ymd_opt(2023, 1, 10),
+            buy_price: Some(0.5),
+            ..Default::default()
+        };
+        let mut portfolio = portfolio(old, &[]);
+        // listed on the recent lot, the sell takes from the old one first
+        portfolio.instruments[0].lots.push(Lot {
+            quantity: 10.,
+            buy_date: Some(date(1)),
+            buy_price: Some(2.),
+            sells: vec![Sell {
+                date: date(5),
+                quantity: 15.,
+            }],
+            ..Default::default()
+        });
+        portfolio.match_sells();
+        assert_eq!(portfolio.instruments[0].quantity_on(date(5)), 5.);
+        let gains = portfolio
+            .realized_gains(2024)
+            .await
+            .unwrap()
+            .iter()
+            .map(|gain| (gain.buy_date, gain.quantity, gain.cost, gain.is_long_term()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            gains,
+            [
+                (NaiveDate::from_ymd_opt(2023, 1, 10).unwrap(), 10., 5., true),
+                (date(1), 5., 10., false)
+            ]
+        );
+    }
+
+    #[test]
+    fn breaks_down_the_total_net_of_fees_and_converted() {
+        let dates = (1..=3).map(date).collect::<Vec<_>>();
+        let lot = Lot {
+            quantity: 100.,
+            buy_date: NaiveDate::from_ymd_opt(2023, 3, 1),
+            expense_ratio: Some(10.),
+            yield_pct: Some(2.),
+            ..Default::default()
+        };
+        let mut portfolio = portfolio(lot, &dates);
+        portfolio.set_model_fees(true);
+        portfolio.currencies.insert(eur(), "EUR".to_owned());
+        let rates = BTreeMap::from([(date(1), 1.5)]);
+        portfolio.set_fx_rates("USD", HashMap::from([("EUR".to_owned(), rates)]));
+        let total = portfolio.portfolio_value(date(3));
+        // a year of fees deducted
+        assert!(total < 150. * 0.91 && total > 150. * 0.89, "{total}");
+        assert_eq!(
+            portfolio
+                .portfolio_value_by_asset_class(date(3))
+                .into_values()
+                .sum::<f64>(),
+            total
+        );
+        assert_eq!(portfolio.portfolio_value_by_account(date(3))["-"], total);
+        assert_eq!(portfolio.portfolio_value_by_currency(date(3))["EUR"], total);
+        assert_eq!(portfolio.weighted_yield(date(3)), Some(2.));
+    }
+
+    #[test]
+    fn rounds_the_breakdowns_like_the_total() {
+        let lot = Lot {
+            quantity: 1.004,
+            ..Default::default()
+        };
+        let mut portfolio = portfolio(lot.clone(), &[date(1)]);
+        portfolio.instruments[0].account = Some("Broker".to_owned());
+        portfolio.instruments.push(Instrument {
+            name: "EUR".to_owned(),
+            provider: Arc::new(Providers::Cash(Cash)),
+            account: Some("Bank".to_owned()),
+            lots: vec![lot],
+        });
+        portfolio.set_currency_precision(Some(2));
+        assert_eq!(portfolio.portfolio_value(date(1)), 2.);
+        assert_eq!(
+            portfolio.portfolio_value_by_account(date(1)),
+            BTreeMap::from([("Bank".to_owned(), 1.), ("Broker".to_owned(), 1.)])
+        );
+        assert_eq!(
+            portfolio.portfolio_value_by_currency(date(1)),
+            BTreeMap::from([(String::new(), 2.)])
+        );
+    }
+
+    #[tokio::test]
+    async fn accounts_for_the_partial_sells() {
+        let dates = (1..=3).map(date).collect::<Vec<_>>();
+        let lot = Lot {
+            quantity: 100.,
+            buy_date: Some(date(1)),
+            buy_price: Some(1.),
+            sells: vec![Sell {
+                date: date(2),
+                quantity: 40.,
+            }],
+            ..Default::default()
+        };
+        let mut portfolio = portfolio(lot, &dates);
+        // the 40 units sold at the close of 1, the 60 left worth 2 each
+        portfolio
+            .portfolio
+            .insert(date(3), HashMap::from([(eur(), 2.)]));
+        assert_eq!(portfolio.cost_basis(date(3)).await.unwrap(), Some(60.));
+        let curve = portfolio.equity_curve().await.unwrap();
+        assert_eq!(curve.last(), Some(&(date(3), 160.)));
+        let instrument = &portfolio.instruments[0];
+        let flows = portfolio
+            .lot_cash_flows(instrument, &instrument.lots[0], date(3))
+            .await
+            .unwrap();
+        assert_eq!(
+            flows,
+            vec![(date(1), -100.), (date(2), 40.), (date(3), 120.)]
+        );
+    }
+
+    #[test]
+    fn keeps_apart_a_symbol_priced_by_two_providers() {
+        let dates = vec![date(1)];
+        let lot = Lot {
+            quantity: 10.,
+            ..Default::default()
+        };
+        let mut portfolio = portfolio(lot.clone(), &dates);
+        let fx = Arc::new(Providers::Fx(crate::fx::Fx::new(
+            "http://127.0.0.1:9",
+            None,
+        )));
+        portfolio.instruments.push(Instrument {
+            name: "EUR".to_owned(),
+            provider: fx,
+            account: None,
+            lots: vec![Lot {
+                quantity: 2.,
+                ..lot
+            }],
+        });
+        let fx_eur = ("Frankfurter".to_owned(), "EUR".to_owned());
+        portfolio
+            .portfolio
+            .get_mut(&date(1))
+            .unwrap()
+            .insert(fx_eur, 4.);
+        // 10 units of the cash at 1 and 2 of the other EUR at 4
+        assert_eq!(portfolio.portfolio_value(date(1)), 18.);
+        assert_eq!(
+            portfolio.instruments_and_values(date(1)),
+            vec![("EUR".to_owned(), 10.), ("EUR".to_owned(), 8.)]
+        );
+    }
+
+    #[test]
+    fn weights_the_contributions_by_the_average_shares() {
+        let dates = (1..=3).map(date).collect::<Vec<_>>();
+        let lot = Lot {
+            quantity: 50.,
+            ..Default::default()
+        };
+        let mut portfolio = portfolio(lot.clone(), &dates);
+        portfolio.instruments.push(Instrument {
+            name: "USD".to_owned(),
+            provider: Arc::new(Providers::Cash(Cash)),
+            account: None,
+            lots: vec![lot],
+        });
+        let usd = ("Cash".to_owned(), "USD".to_owned());
+        for (day, price) in [(1, 1.), (2, 2.), (3, 3.)] {
+            portfolio
+                .portfolio
+                .get_mut(&date(day))
+                .unwrap()
+                .insert(usd.clone(), price);
+        }
+        // the USD tripling while its share grows from 1/2 to 2/3 and 3/4
+        let contributions = portfolio.contributions(&dates);
+        assert_eq!(contributions[0], ("EUR".to_owned(), 0.));
+        assert_eq!(contributions[1].0, "USD");
+        assert!((contributions[1].1 - 2. * 23. / 36.).abs() < 1e-12);
+    }
+
+    #[tokio::test]
+    async fn stores_a_range_of_prices() {
+        let dates = (1..=4).map(date).collect::<Vec<_>>();
+        let mut portfolio = portfolio(Lot::default(), &[]);
+        let jh = tokio::spawn(async {
+            Ok(vec![
+                ("EUR".to_owned(), date(1), 1., "EUR".to_owned()),
+                ("EUR".to_owned(), date(3), 3., "EUR".to_owned()),
+            ])
+        });
+        let errors = portfolio
+            .wait_for_price_ranges(vec![(eur(), dates.clone(), jh)], false)
+            .await;
+        // the days missing from the range fail like their own downloads would
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            portfolio.failed_quotes(),
+            vec![(date(2), eur()), (date(4), eur())]
+        );
+        let prices = dates
+            .iter()
+            .map(|day| portfolio.price(*day, &eur()).map(|(price, _)| price))
+            .collect::<Vec<_>>();
+        assert_eq!(prices, [Some(1.), None, Some(3.), None]);
+    }
+}
@@ -1,79 +1,937 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
 
-use chrono::NaiveDate;
+use chrono::{Days, NaiveDate, TimeDelta, Utc};
 use dirs::home_dir;
-use rusqlite::{self, Connection};
+use rusqlite::{self, Connection, OpenFlags, Transaction, TransactionBehavior};
+use serde::Serialize;
 
-use crate::{provider::Provider, providers::Providers};
+use crate::{provider::Provider, providers::Providers, yfinance::Resolution};
+
+const DATE_FORMATTER: &str = "%Y-%m-%d";
+const TIMESTAMP_FORMATTER: &str = "%Y-%m-%d %H:%M:%S";
+
+/// The prices of the dates older than this many days are settled, their cached rows
+/// never expiring
+const SETTLED_DAYS: u64 = 7;
+
+/// Counters collected while downloading prices, reported by --profile
+#[derive(Debug, Default, Clone)]
+pub(crate) struct CacheStats {
+    pub(crate) hits: usize,
+    pub(crate) misses: usize,
+    /// time spent querying the cache, summed over all the download tasks
+    pub(crate) lookup_time: Duration,
+    /// requests sent to each provider, by provider name
+    pub(crate) requests: HashMap<String, usize>,
+}
+
+/// Command line options controlling the cache
+#[derive(Debug, Default, Clone)]
+pub(crate) struct CacheSettings {
+    /// the number of decimals the cached prices are rounded to, unrounded if None
+    pub(crate) price_decimals: Option<u32>,
+    /// ignores the cached prices, downloading them all again
+    pub(crate) skip_reads: bool,
+    /// leaves the cache untouched by the downloaded prices
+    pub(crate) skip_writes: bool,
+    /// opens the database read-only, e.g. a cache shared by the users of a server,
+    /// so that nothing is written to it
+    pub(crate) read_only: bool,
+    /// the number of connections to the cache database, so that concurrent
+    /// downloads don't wait on each other's queries
+    pub(crate) connections: usize,
+    /// only the prices cached on or before this day are read and nothing is
+    /// downloaded, replaying what was known then
+    pub(crate) as_of: Option<NaiveDate>,
+    /// the file a line is appended to for each price lookup, by --audit-log
+    pub(crate) audit_log: Option<String>,
+    /// the SQLite database of the cache, ~/.livestock.sql if None
+    pub(crate) file: Option<PathBuf>,
+    /// how long the prices of the recent days stay cached before being downloaded
+    /// again, forever if None
+    pub(crate) ttl: Option<Duration>,
+}
+
+/// A line of the audit log: the lookup of a symbol's prices, in the cache and then
+/// at the provider when missing
+#[derive(Serialize)]
+struct AuditRecord<'a> {
+    timestamp: String,
+    provider: &'a str,
+    symbol: &'a str,
+    date: NaiveDate,
+    /// the last date of a range
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_date: Option<NaiveDate>,
+    cache: &'static str,
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
 
 #[derive(Debug)]
 pub struct PriceCacher {
-    connection: Mutex<Connection>,
+    connections: Vec<Mutex<Connection>>,
+    /// the connection waited on when they're all busy, in turns
+    next_connection: AtomicUsize,
+    stats: Mutex<CacheStats>,
+    /// the tickers whose resolution was saved, by provider name
+    stored_resolutions: Mutex<HashSet<(String, String)>>,
+    audit_log: Option<Mutex<File>>,
+    settings: CacheSettings,
 }
 
 impl PriceCacher {
-    pub(crate) fn new() -> Self {
-        let home = home_dir().unwrap().to_str().unwrap().to_owned();
-        let connection = rusqlite::Connection::open(home + "/.livestock.sql").unwrap();
-        connection
-            .execute(
-                "CREATE TABLE IF NOT EXISTS cache (
+    /// Opens the cache of the settings' file, or else ~/.livestock.sql
+    pub(crate) fn new(settings: CacheSettings) -> Result<Self, std::io::Error> {
+        let path = match &settings.file {
+            Some(file) => file.clone(),
+            None => home_dir()
+                .ok_or_else(|| {
+                    std::io::Error::other(
+                        "Unable to find the home directory holding the cache, see --cache-file",
+                    )
+                })?
+                .join(".livestock.sql"),
+        };
+        Self::with_path(path, settings)
+    }
+
+    /// Opens the cache at a path, creating it unless it's opened read-only
+    pub(crate) fn with_path(
+        path: PathBuf,
+        settings: CacheSettings,
+    ) -> Result<Self, std::io::Error> {
+        let cache_error = |e: rusqlite::Error| {
+            std::io::Error::other(format!("Unable to open the cache {}: {e}", path.display()))
+        };
+        let opened = (0..settings.connections.max(1))
+            .map(|_| Self::open(&path, settings.read_only))
+            .collect::<Result<Vec<_>, _>>();
+        // a read-only cache which doesn't exist yet is replaced by an empty one
+        // in memory, the prices are downloaded every time
+        let (connections, in_memory) = match opened {
+            Ok(connections) => (connections, false),
+            Err(e) if settings.read_only => {
+                eprintln!(
+                    "Warning: unable to open the cache {} read-only: {e}",
+                    path.display()
+                );
+                let connections = (0..settings.connections.max(1))
+                    .map(|_| Connection::open_in_memory())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(cache_error)?;
+                (connections, true)
+            }
+            Err(e) => return Err(cache_error(e)),
+        };
+        let audit_log = settings.audit_log.as_ref().and_then(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| eprintln!("Warning: unable to open the audit log {path}: {e}"))
+                .ok()
+        });
+        let cacher = Self {
+            connections: connections.into_iter().map(Mutex::new).collect(),
+            next_connection: AtomicUsize::new(0),
+            stats: Mutex::new(CacheStats::default()),
+            stored_resolutions: Mutex::new(HashSet::default()),
+            audit_log: audit_log.map(Mutex::new),
+            settings,
+        };
+        if !cacher.settings.read_only || in_memory {
+            cacher.create_tables().map_err(cache_error)?;
+        }
+        Ok(cacher)
+    }
+
+    /// Appends a JSON line describing a lookup to the audit log, if there's one
+    fn audit(
+        &self,
+        provider_name: &str,
+        symbol: &str,
+        dates: (NaiveDate, Option<NaiveDate>),
+        cache_hit: bool,
+        start: Instant,
+        error: Option<&std::io::Error>,
+    ) {
+        let Some(audit_log) = &self.audit_log else {
+            return;
+        };
+        let record = AuditRecord {
+            timestamp: Utc::now().to_rfc3339(),
+            provider: provider_name,
+            symbol,
+            date: dates.0,
+            end_date: dates.1,
+            cache: if cache_hit { "hit" } else { "miss" },
+            latency_ms: start.elapsed().as_millis(),
+            error: error.map(ToString::to_string),
+        };
+        if let Ok(line) = serde_json::to_string(&record) {
+            // a failed write only loses the line, the prices are still returned
+            let _ = writeln!(audit_log.lock().unwrap(), "{line}");
+        }
+    }
+
+    /// Creates the cache tables, or updates the ones created by an older version
+    fn create_tables(&self) -> rusqlite::Result<()> {
+        let connection = self.connection();
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS cache (
                 provider TEXT NOT NULL,
                 symbol TEXT NOT NULL,
                 date TEXT NOT NULL,
-                price REAL NOT NULL
+                price REAL NOT NULL,
+                currency TEXT,
+                created_at TEXT
                 )",
+            (),
+        )?;
+        Self::add_missing_column(&connection, "currency", "TEXT")?;
+        Self::add_missing_column(&connection, "created_at", "TEXT")?;
+        connection.execute(
+            "CREATE INDEX IF NOT EXISTS cache_lookup ON cache (provider, symbol, date)",
+            (),
+        )?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS resolutions (
+                provider TEXT NOT NULL,
+                ticker TEXT NOT NULL,
+                symbol TEXT NOT NULL,
+                query TEXT NOT NULL,
+                matches INTEGER NOT NULL,
+                quote_type TEXT NOT NULL,
+                exchange TEXT NOT NULL,
+                resolved_on TEXT NOT NULL
+                )",
+            (),
+        )?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS snapshots (
+                taken_at TEXT NOT NULL,
+                file TEXT NOT NULL,
+                date TEXT NOT NULL,
+                total REAL NOT NULL
+                )",
+            (),
+        )?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS snapshot_values (
+                taken_at TEXT NOT NULL,
+                file TEXT NOT NULL,
+                symbol TEXT NOT NULL,
+                value REAL NOT NULL
+                )",
+            (),
+        )?;
+        Ok(())
+    }
+
+    /// Opens a connection to the database, which other processes may be using too:
+    /// readers don't block the writer, and a busy database is waited for
+    fn open(path: &Path, read_only: bool) -> rusqlite::Result<Connection> {
+        let connection = if read_only {
+            Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?
+        } else {
+            let connection = Connection::open(path)?;
+            let _ = connection.pragma_update(None, "journal_mode", "WAL");
+            connection
+        };
+        let _ = connection.busy_timeout(Duration::from_secs(5));
+        Ok(connection)
+    }
+
+    /// An idle connection, or else the next one in turn once it's released
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        if let Some(connection) = self
+            .connections
+            .iter()
+            .find_map(|connection| connection.try_lock().ok())
+        {
+            return connection;
+        }
+        let next = self.next_connection.fetch_add(1, Ordering::Relaxed) % self.connections.len();
+        self.connections[next].lock().unwrap()
+    }
+
+    /// Runs the statements of a write in a transaction, so that the processes sharing
+    /// the cache never see one half done. It takes the write lock up front, waiting
+    /// for the other writers rather than failing to upgrade a read
+    fn write(
+        &self,
+        statements: impl FnOnce(&Transaction) -> rusqlite::Result<()>,
+    ) -> rusqlite::Result<()> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        statements(&transaction)?;
+        transaction.commit()
+    }
+
+    /// Adds a column to the cache table of databases created by older versions
+    fn add_missing_column(
+        connection: &Connection,
+        column: &str,
+        definition: &str,
+    ) -> rusqlite::Result<()> {
+        let exists = connection
+            .prepare("SELECT 1 FROM pragma_table_info('cache') WHERE name=?1")
+            .and_then(|mut statement| statement.exists([column]))?;
+        if !exists {
+            connection.execute(
+                &format!("ALTER TABLE cache ADD COLUMN {column} {definition}"),
                 (),
-            )
-            .unwrap();
-        Self {
-            connection: Mutex::new(connection),
+            )?;
         }
+        Ok(())
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        self.stats.lock().unwrap().clone()
     }
 
     fn get_provider_name(provider: &Providers) -> String {
         match provider {
-            Providers::YFinance(yfinance) => yfinance.get_provider_name(),
-            Providers::XFRA(xfra) => xfra.get_provider_name(),
+            Providers::YFinance(yfinance) => yfinance.get_cache_name(),
+            Providers::Xfra(xfra) => xfra.get_provider_name(),
+            Providers::AlphaVantage(alpha_vantage) => alpha_vantage.get_provider_name(),
+            Providers::CoinGecko(coin_gecko) => coin_gecko.get_provider_name(),
+            Providers::Fx(fx) => fx.get_provider_name(),
+            Providers::Cash(cash) => cash.get_provider_name(),
+        }
+    }
+
+    /// The condition on the rows cached by the end of the --cache-as-of day, the rows
+    /// cached before the insertions were timestamped being left out
+    fn as_of_condition(&self) -> String {
+        match self.settings.as_of.and_then(|as_of| as_of.succ_opt()) {
+            Some(next_day) => format!(" and created_at < '{}'", next_day.format(DATE_FORMATTER)),
+            None => String::new(),
+        }
+    }
+
+    /// The condition on the rows cached less than --cache-ttl ago, for the recent
+    /// dates. Replaying --cache-as-of, nothing expires
+    fn ttl_condition(&self) -> String {
+        let (Some(ttl), None) = (self.settings.ttl, self.settings.as_of) else {
+            return String::new();
+        };
+        let now = Utc::now();
+        let (Ok(ttl), Some(settled)) = (
+            TimeDelta::from_std(ttl),
+            now.date_naive().checked_sub_days(Days::new(SETTLED_DAYS)),
+        ) else {
+            return String::new();
+        };
+        // the rows cached before the insertions were timestamped expire too
+        format!(
+            " and (date < '{}' or created_at >= '{}')",
+            settled.format(DATE_FORMATTER),
+            (now - ttl).format(TIMESTAMP_FORMATTER)
+        )
+    }
+
+    /// The error of a price that isn't in the cache as of the --cache-as-of day
+    fn not_cached_as_of(&self, ticker: &str, date: NaiveDate) -> Option<std::io::Error> {
+        self.settings.as_of.map(|as_of| {
+            std::io::Error::other(format!(
+                "No price of {ticker} on {date} was cached by {as_of}"
+            ))
+        })
+    }
+
+    /// The price and currency cached for a ticker on a date
+    pub(crate) fn cached_price(
+        &self,
+        provider: &Providers,
+        ticker: &str,
+        date: NaiveDate,
+    ) -> Option<(f64, String)> {
+        if self.settings.skip_reads {
+            return None;
+        }
+        // rows cached before the currency was recorded are downloaded again
+        self.connection()
+            .query_row_and_then(
+                &format!(
+                    "SELECT price, currency FROM cache
+                    WHERE provider=?1 and symbol=?2 and date=?3 and currency IS NOT NULL{}{}
+                    ORDER BY created_at DESC LIMIT 1",
+                    self.as_of_condition(),
+                    self.ttl_condition()
+                ),
+                (
+                    Self::get_provider_name(provider),
+                    ticker,
+                    date.format(DATE_FORMATTER).to_string(),
+                ),
+                |row| Ok::<_, rusqlite::Error>((row.get(0)?, row.get(1)?)),
+            )
+            .ok()
+    }
+
+    /// Rounds a downloaded price as configured, so that the float noise of the
+    /// providers doesn't change the cached history between runs
+    fn round_price(&self, result: &mut (String, NaiveDate, f64, String)) {
+        if let Some(decimals) = self.settings.price_decimals {
+            let scale = 10f64.powi(decimals as i32);
+            result.2 = (result.2 * scale).round() / scale;
+        }
+    }
+
+    /// Saves a downloaded price, overwriting the rows cached for the same day earlier
+    /// on the same day. --cache-as-of reads the prices as of the end of a day, so only
+    /// the last row of each day is kept: a price has a row per day it was downloaded
+    /// on, the expiring ones being downloaded again until they settle
+    fn store(&self, provider_name: &str, result: &(String, NaiveDate, f64, String)) {
+        if self.settings.skip_writes || self.settings.read_only {
+            return;
+        }
+        let now = Utc::now();
+        let date = result.1.format(DATE_FORMATTER).to_string();
+        let _ = self.write(|transaction| {
+            // the rows cached before the insertions were timestamped are replaced too
+            transaction.execute(
+                "DELETE FROM cache WHERE provider=?1 and symbol=?2 and date=?3
+                and (created_at IS NULL or created_at >= ?4)",
+                (
+                    provider_name,
+                    &result.0,
+                    &date,
+                    now.format(DATE_FORMATTER).to_string(),
+                ),
+            )?;
+            transaction.execute(
+                "INSERT INTO cache (provider, symbol, date, price, currency, created_at)
+                VALUES(?1, ?2, ?3, ?4, ?5, ?6)",
+                (
+                    provider_name,
+                    &result.0,
+                    &date,
+                    result.2,
+                    &result.3,
+                    now.format(TIMESTAMP_FORMATTER).to_string(),
+                ),
+            )?;
+            Ok(())
+        });
+    }
+
+    /// Saves how the provider matched a ticker to one of its symbols, once per run,
+    /// replacing the previous match
+    fn store_resolution(&self, provider: &Providers, ticker: &str) {
+        if self.settings.skip_writes || self.settings.read_only {
+            return;
+        }
+        let Some(resolution) = provider.resolution(ticker) else {
+            return;
+        };
+        let provider_name = provider.get_provider_name();
+        if !self
+            .stored_resolutions
+            .lock()
+            .unwrap()
+            .insert((provider_name.clone(), ticker.to_owned()))
+        {
+            return;
+        }
+        let _ = self.write(|transaction| {
+            transaction.execute(
+                "DELETE FROM resolutions WHERE provider=?1 and ticker=?2",
+                (&provider_name, ticker),
+            )?;
+            transaction.execute(
+                "INSERT INTO resolutions
+                (provider, ticker, symbol, query, matches, quote_type, exchange, resolved_on)
+                VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                (
+                    &provider_name,
+                    ticker,
+                    resolution.symbol,
+                    resolution.query,
+                    resolution.matches,
+                    resolution.quote_type,
+                    resolution.exchange,
+                    Utc::now().date_naive().format(DATE_FORMATTER).to_string(),
+                ),
+            )?;
+            Ok(())
+        });
+    }
+
+    /// The saved matches of tickers to provider symbols, by provider and ticker, with
+    /// the day each one was made
+    pub(crate) fn resolutions(&self) -> Vec<(String, String, Resolution, String)> {
+        let connection = self.connection();
+        let Ok(mut statement) = connection.prepare(
+            "SELECT provider, ticker, symbol, query, matches, quote_type, exchange, resolved_on
+            FROM resolutions ORDER BY provider, ticker",
+        ) else {
+            return vec![];
+        };
+        let rows = statement.query_map((), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                Resolution {
+                    symbol: row.get(2)?,
+                    query: row.get(3)?,
+                    matches: row.get(4)?,
+                    quote_type: row.get(5)?,
+                    exchange: row.get(6)?,
+                },
+                row.get::<_, String>(7)?,
+            ))
+        });
+        match rows {
+            Ok(rows) => rows.filter_map(Result::ok).collect(),
+            Err(_) => vec![],
         }
     }
 
+    /// Saves the total value of a configuration file's portfolio on a date and the
+    /// value of each instrument, keyed by the time of the run
+    pub(crate) fn store_snapshot(
+        &self,
+        file: &str,
+        date: NaiveDate,
+        total: f64,
+        values: &[(String, f64)],
+    ) -> Result<(), std::io::Error> {
+        if self.settings.read_only {
+            return Err(std::io::Error::other(
+                "The cache is read-only, the snapshot isn't saved",
+            ));
+        }
+        let taken_at = Utc::now().format(TIMESTAMP_FORMATTER).to_string();
+        self.write(|transaction| {
+            transaction.execute(
+                "INSERT INTO snapshots (taken_at, file, date, total) VALUES(?1, ?2, ?3, ?4)",
+                (
+                    &taken_at,
+                    file,
+                    date.format(DATE_FORMATTER).to_string(),
+                    total,
+                ),
+            )?;
+            for (symbol, value) in values {
+                transaction.execute(
+                    "INSERT INTO snapshot_values (taken_at, file, symbol, value)
+                    VALUES(?1, ?2, ?3, ?4)",
+                    (&taken_at, file, symbol, value),
+                )?;
+            }
+            Ok(())
+        })
+        .map_err(|e| std::io::Error::other(format!("Unable to save the snapshot: {e}")))
+    }
+
+    /// The totals saved for a configuration file on or after a date, in date order,
+    /// the latest snapshot of each date
+    pub(crate) fn snapshots(&self, file: &str, since: NaiveDate) -> Vec<(NaiveDate, f64)> {
+        let connection = self.connection();
+        let Ok(mut statement) = connection.prepare(
+            // the total is read from the row having the latest taken_at
+            "SELECT date, total, MAX(taken_at) FROM snapshots
+            WHERE file=?1 and date>=?2
+            GROUP BY date ORDER BY date",
+        ) else {
+            return vec![];
+        };
+        let rows = statement.query_map((file, since.format(DATE_FORMATTER).to_string()), |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, f64>(1)?))
+        });
+        let Ok(rows) = rows else {
+            return vec![];
+        };
+        rows.filter_map(Result::ok)
+            .filter_map(|(date, total)| {
+                Some((
+                    NaiveDate::parse_from_str(&date, DATE_FORMATTER).ok()?,
+                    total,
+                ))
+            })
+            .collect()
+    }
+
+    /// The prices of the dates not cached yet are downloaded and cached, then the
+    /// prices of all the dates returned. Providers supporting ranges are queried once
+    /// for the span of the missing dates, the others date by date, each price being
+    /// cached as it arrives so that a retry only downloads what's still missing
+    pub async fn download_prices_bulk(
+        &self,
+        provider: Arc<Providers>,
+        ticker: String,
+        dates: Vec<NaiveDate>,
+    ) -> Result<Vec<(String, NaiveDate, f64, String)>, std::io::Error> {
+        if !provider.supports_ranges() {
+            let mut prices = vec![];
+            let mut last_error = None;
+            for date in dates {
+                match self
+                    .download_price(Arc::clone(&provider), ticker.clone(), date)
+                    .await
+                {
+                    Ok(price) => prices.push(price),
+                    Err(e) => last_error = Some(e),
+                }
+            }
+            return match last_error {
+                Some(e) if prices.is_empty() => Err(e),
+                _ => Ok(prices),
+            };
+        }
+
+        let (Some(first), Some(last)) = (dates.first(), dates.last()) else {
+            return Ok(vec![]);
+        };
+        let provider_name = Self::get_provider_name(&provider);
+        let lookup_start = Instant::now();
+        let mut prices = self.cached_prices(&provider, &ticker, *first, *last);
+        let missing = dates
+            .iter()
+            .filter(|date| !prices.iter().any(|price| price.1 == **date))
+            .copied()
+            .collect::<Vec<_>>();
+        {
+            let mut stats = self.stats.lock().unwrap();
+            stats.lookup_time += lookup_start.elapsed();
+            stats.hits += dates.len() - missing.len();
+            stats.misses += missing.len();
+            if !missing.is_empty() {
+                *stats.requests.entry(provider_name.clone()).or_default() += 1;
+            }
+        }
+        let (Some(first_missing), Some(last_missing)) = (missing.first(), missing.last()) else {
+            let range = (*first, Some(*last));
+            self.audit(&provider_name, &ticker, range, true, lookup_start, None);
+            return Ok(prices);
+        };
+        let range = (*first_missing, Some(*last_missing));
+        if let Some(e) = self.not_cached_as_of(&ticker, *first_missing) {
+            self.audit(
+                &provider_name,
+                &ticker,
+                range,
+                false,
+                lookup_start,
+                Some(&e),
+            );
+            return if prices.is_empty() {
+                Err(e)
+            } else {
+                Ok(prices)
+            };
+        }
+
+        let downloaded = provider
+            .download_prices_bulk(&ticker, *first_missing, *last_missing)
+            .await;
+        let error = downloaded.as_ref().err();
+        self.audit(&provider_name, &ticker, range, false, lookup_start, error);
+        // the cached prices are still good when the missing ones can't be downloaded
+        let mut downloaded = match downloaded {
+            Ok(downloaded) => downloaded,
+            Err(_) if !prices.is_empty() => return Ok(prices),
+            Err(e) => return Err(e),
+        };
+        self.store_resolution(&provider, &ticker);
+        for result in &mut downloaded {
+            self.round_price(result);
+            self.store(&provider_name, result);
+        }
+        prices.retain(|price| !downloaded.iter().any(|result| result.1 == price.1));
+        prices.extend(downloaded);
+        prices.sort_by_key(|price| price.1);
+        Ok(prices)
+    }
+
+    /// The prices cached for a ticker between start and end (inclusive), in date order
+    pub(crate) fn cached_prices(
+        &self,
+        provider: &Providers,
+        ticker: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Vec<(String, NaiveDate, f64, String)> {
+        if self.settings.skip_reads {
+            return vec![];
+        }
+        let connection = self.connection();
+        let Ok(mut statement) = connection.prepare(&format!(
+            // the price is read from the row having the latest created_at
+            "SELECT date, price, currency, MAX(created_at) FROM cache
+            WHERE provider=?1 and symbol=?2 and date BETWEEN ?3 AND ?4 and currency IS NOT NULL{}{}
+            GROUP BY date ORDER BY date",
+            self.as_of_condition(),
+            self.ttl_condition()
+        )) else {
+            return vec![];
+        };
+        let rows = statement.query_map(
+            (
+                Self::get_provider_name(provider),
+                ticker,
+                start.format(DATE_FORMATTER).to_string(),
+                end.format(DATE_FORMATTER).to_string(),
+            ),
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, f64>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            },
+        );
+        let Ok(rows) = rows else {
+            return vec![];
+        };
+        rows.filter_map(Result::ok)
+            .filter_map(|(date, price, currency)| {
+                let date = NaiveDate::parse_from_str(&date, DATE_FORMATTER).ok()?;
+                Some((ticker.to_owned(), date, price, currency))
+            })
+            .collect()
+    }
+
     pub async fn download_price(
         &self,
         provider: Arc<Providers>,
         ticker: String,
         date: NaiveDate,
-    ) -> Result<(String, NaiveDate, f64), std::io::Error> {
-        const DATE_FORMATTER: &str = "%Y-%m-%d";
+    ) -> Result<(String, NaiveDate, f64, String), std::io::Error> {
+        // cash is always worth its amount, there's nothing to cache
+        if let Providers::Cash(cash) = provider.as_ref() {
+            return cash.download_price(ticker, date).await;
+        }
         // try matching it in the cache
         let provider_name = Self::get_provider_name(&provider);
-        let cached_price: rusqlite::Result<f64> =
-            self.connection.lock().unwrap().query_row_and_then(
-                "SELECT price FROM cache WHERE provider=?1 and symbol=?2 and date=?3",
-                (
-                    provider_name.clone(),
-                    ticker.clone(),
-                    date.format(DATE_FORMATTER).to_string(),
-                ),
-                |row| row.get(0),
-            );
+        let lookup_start = Instant::now();
+        let cached_price = self.cached_price(&provider, &ticker, date);
+        {
+            let mut stats = self.stats.lock().unwrap();
+            stats.lookup_time += lookup_start.elapsed();
+            if cached_price.is_some() {
+                stats.hits += 1;
+            } else {
+                stats.misses += 1;
+                *stats.requests.entry(provider_name.clone()).or_default() += 1;
+            }
+        }
         match cached_price {
-            Ok(price) => Ok((ticker, date, price)),
-            Err(_) => {
+            Some((price, currency)) => {
+                self.audit(
+                    &provider_name,
+                    &ticker,
+                    (date, None),
+                    true,
+                    lookup_start,
+                    None,
+                );
+                Ok((ticker, date, price, currency))
+            }
+            None => {
+                if let Some(e) = self.not_cached_as_of(&ticker, date) {
+                    self.audit(
+                        &provider_name,
+                        &ticker,
+                        (date, None),
+                        false,
+                        lookup_start,
+                        Some(&e),
+                    );
+                    return Err(e);
+                }
                 // not found in the cache, try resolving it
-                let result = provider.download_price(&ticker, date).await?;
-                // cache the result
-                let _ = self.connection.lock().unwrap().execute(
-                    "INSERT INTO cache (provider, symbol, date, price) VALUES(?1, ?2, ?3, ?4)",
-                    (
-                        provider_name,
-                        result.0.clone(),
-                        result.1.format(DATE_FORMATTER).to_string(),
-                        result.2,
-                    ),
+                let result = provider.download_price(&ticker, date).await;
+                let error = result.as_ref().err();
+                self.audit(
+                    &provider_name,
+                    &ticker,
+                    (date, None),
+                    false,
+                    lookup_start,
+                    error,
                 );
+                let mut result = result?;
+                self.store_resolution(&provider, &ticker);
+                self.round_price(&mut result);
+                // cache the result, read instead of the older rows from now on, unless
+                // it's the price of a session still in progress
+                if provider.is_final(date) {
+                    self.store(&provider_name, &result);
+                }
                 Ok(result)
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cash::Cash,
+        coingecko::CoinGecko,
+        test_server::{ok, serve},
+    };
+
+    /// An in-memory cache holding a price of EUR cached at a time
+    fn cache(ttl: Option<Duration>, date: NaiveDate, created_at: &str) -> PriceCacher {
+        let settings = CacheSettings {
+            ttl,
+            ..Default::default()
+        };
+        let cache = PriceCacher::with_path(PathBuf::from(":memory:"), settings).unwrap();
+        cache
+            .connection()
+            .execute(
+                "INSERT INTO cache (provider, symbol, date, price, currency, created_at)
+                VALUES('Cash', 'EUR', ?1, 1, 'EUR', ?2)",
+                (date.format(DATE_FORMATTER).to_string(), created_at),
+            )
+            .unwrap();
+        cache
+    }
+
+    #[test]
+    fn expires_the_recent_prices() {
+        let today = Utc::now().date_naive();
+        let an_hour_ago = (Utc::now() - TimeDelta::hours(1))
+            .format(TIMESTAMP_FORMATTER)
+            .to_string();
+        let cached = |ttl: Option<Duration>| {
+            cache(ttl, today, &an_hour_ago)
+                .cached_price(&Providers::Cash(Cash), "EUR", today)
+                .is_some()
+        };
+        assert!(cached(None));
+        assert!(cached(Some(Duration::from_secs(7200))));
+        assert!(!cached(Some(Duration::from_secs(60))));
+    }
+
+    #[test]
+    fn keeps_the_settled_prices() {
+        let a_month_ago = Utc::now().date_naive() - Days::new(30);
+        let cache = cache(
+            Some(Duration::from_secs(60)),
+            a_month_ago,
+            "2000-01-01 00:00:00",
+        );
+        assert!(cache
+            .cached_price(&Providers::Cash(Cash), "EUR", a_month_ago)
+            .is_some());
+    }
+
+    #[test]
+    fn reads_the_newest_price_as_of_a_day() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let cache = cache(None, date, "2024-03-01 18:00:00");
+        cache
+            .connection()
+            .execute(
+                "INSERT INTO cache (provider, symbol, date, price, currency, created_at)
+                VALUES('Cash', 'EUR', ?1, 2, 'EUR', '2024-04-02 09:00:00')",
+                (date.format(DATE_FORMATTER).to_string(),),
+            )
+            .unwrap();
+        let mut cache = cache;
+        let mut price = |as_of: Option<NaiveDate>| {
+            cache.settings.as_of = as_of;
+            let price = cache.cached_price(&Providers::Cash(Cash), "EUR", date);
+            let prices = cache.cached_prices(&Providers::Cash(Cash), "EUR", date, date);
+            // a single price read, whether for the day or a range
+            assert_eq!(
+                price.as_ref().map(|(price, _)| *price),
+                prices.iter().map(|(_, _, price, _)| *price).next()
+            );
+            assert!(prices.len() <= 1);
+            price.map(|(price, _)| price)
+        };
+        assert_eq!(price(None), Some(2.));
+        assert_eq!(price(NaiveDate::from_ymd_opt(2024, 3, 31)), Some(1.));
+        assert_eq!(price(NaiveDate::from_ymd_opt(2024, 2, 29)), None);
+    }
+
+    #[test]
+    fn overwrites_the_prices_refreshed_the_same_day() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let cache = cache(None, date, "2024-03-01 18:00:00");
+        let earlier_today = Utc::now().format("%Y-%m-%d 00:00:00").to_string();
+        cache
+            .connection()
+            .execute(
+                "INSERT INTO cache (provider, symbol, date, price, currency, created_at)
+                VALUES('Cash', 'EUR', ?1, 2, 'EUR', ?2), ('Cash', 'EUR', ?1, 2, 'EUR', NULL)",
+                (date.format(DATE_FORMATTER).to_string(), earlier_today),
+            )
+            .unwrap();
+        cache.store("Cash", &("EUR".to_owned(), date, 3., "EUR".to_owned()));
+        let prices = cache
+            .connection()
+            .prepare("SELECT price FROM cache ORDER BY created_at")
+            .unwrap()
+            .query_map((), |row| row.get::<_, f64>(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        // the row of an earlier day stays for --cache-as-of
+        assert_eq!(prices, [1., 3.]);
+    }
+
+    #[test]
+    fn fails_on_a_missing_directory() {
+        let path = PathBuf::from("/nonexistent/dir/cache.sql");
+        let error = PriceCacher::with_path(path, CacheSettings::default()).err();
+        assert!(error
+            .unwrap()
+            .to_string()
+            .contains("/nonexistent/dir/cache.sql"));
+    }
+
+    #[test]
+    fn fails_on_a_file_which_isnt_a_database() {
+        let path = std::env::temp_dir().join(format!("livestock-{}.txt", std::process::id()));
+        std::fs::write(
+            &path,
+            "not a database, just some text that's long enough".repeat(50),
+        )
+        .unwrap();
+        let result = PriceCacher::with_path(path.clone(), CacheSettings::default());
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn caches_only_the_final_prices() {
+        let bitcoin = include_str!("../fixtures/coingecko/bitcoin.json");
+        let live = include_str!("../fixtures/coingecko/simple_price.json");
+        let (base_url, _) = serve(vec![ok(bitcoin), ok(live)]);
+        let provider = Arc::new(Providers::CoinGecko(CoinGecko::new(&base_url, None)));
+        let cache =
+            PriceCacher::with_path(PathBuf::from(":memory:"), CacheSettings::default()).unwrap();
+        let past = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let today = Utc::now().date_naive();
+        for date in [past, today] {
+            cache
+                .download_price(Arc::clone(&provider), "bitcoin".to_owned(), date)
+                .await
+                .unwrap();
+        }
+        // today's price changes until the day ends
+        assert!(cache.cached_price(&provider, "bitcoin", past).is_some());
+        assert_eq!(cache.cached_price(&provider, "bitcoin", today), None);
+    }
+}
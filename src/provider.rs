@@ -5,9 +5,39 @@ pub(crate) trait Provider {
     type ErrorType: Debug;
 
     fn get_provider_name(&self) -> String;
+
+    /// The number of past days the provider has prices for, None if unlimited
+    fn max_history_days(&self) -> Option<u32> {
+        None
+    }
+
+    /// Returns the symbol, the date, the price and the currency of the price
     async fn download_price(
         &self,
         name: String,
         date: NaiveDate,
-    ) -> Result<(String, NaiveDate, f64), Self::ErrorType>;
+    ) -> Result<(String, NaiveDate, f64, String), Self::ErrorType>;
+
+    /// Returns the prices of the days between start and end (inclusive) having one.
+    /// Queries one day at a time, providers able to fetch a range in a single
+    /// request override it
+    async fn download_prices_bulk(
+        &self,
+        name: String,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<(String, NaiveDate, f64, String)>, Self::ErrorType> {
+        let mut prices = vec![];
+        let mut last_error = None;
+        for date in start.iter_days().take_while(|date| *date <= end) {
+            match self.download_price(name.clone(), date).await {
+                Ok(price) => prices.push(price),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        match last_error {
+            Some(e) if prices.is_empty() => Err(e),
+            _ => Ok(prices),
+        }
+    }
 }
@@ -1,12 +1,163 @@
+use std::{fmt::Display, time::Duration};
+
 use chrono::NaiveDate;
 
 use crate::provider::Provider;
-use crate::{xfra::XFRA, yfinance::YFinance};
+use crate::{
+    alphavantage::{self, AlphaVantage},
+    cash::Cash,
+    coingecko::{self, CoinGecko},
+    fx::{self, Fx},
+    xfra::Xfra,
+    yfinance::{PriceField, Resolution, Session, YFinance},
+};
+
+/// A provider the configuration file can name: its key and how it's built
+pub(crate) struct ProviderEntry {
+    pub(crate) key: &'static str,
+    pub(crate) description: &'static str,
+    build: fn(&ProviderSettings) -> Providers,
+}
+
+/// The providers the configuration file can name, as built by Providers::build
+pub(crate) const REGISTRY: [ProviderEntry; 6] = [
+    ProviderEntry {
+        key: "Yahoo",
+        description: "Yahoo! Finance: stocks, ETFs and indices by ticker (e.g. AAPL, VOW3.DE)",
+        build: |settings| {
+            Providers::YFinance(YFinance::new(
+                false,
+                settings.default_exchange_suffix.clone(),
+                settings.session,
+                settings.price_field,
+                settings.user_agent.as_deref(),
+                settings.search_timeout,
+            ))
+        },
+    },
+    ProviderEntry {
+        key: "XFRA",
+        description: "Boerse Frankfurt: securities by ISIN, e.g. bonds",
+        build: |settings| {
+            Providers::Xfra(Xfra::new(
+                &settings.xfra_base_url,
+                settings.user_agent.as_deref(),
+            ))
+        },
+    },
+    ProviderEntry {
+        key: "AlphaVantage",
+        description: "Alpha Vantage: US equities by ticker, with the key in ALPHAVANTAGE_API_KEY",
+        build: |settings| {
+            Providers::AlphaVantage(AlphaVantage::new(
+                alphavantage::DEFAULT_BASE_URL,
+                std::env::var(alphavantage::API_KEY_VARIABLE).ok(),
+                settings.user_agent.as_deref(),
+            ))
+        },
+    },
+    ProviderEntry {
+        key: "CoinGecko",
+        description: "CoinGecko: crypto currencies by coin id (e.g. bitcoin), in USD",
+        build: |settings| {
+            Providers::CoinGecko(CoinGecko::new(
+                coingecko::DEFAULT_BASE_URL,
+                settings.user_agent.as_deref(),
+            ))
+        },
+    },
+    ProviderEntry {
+        key: "FX",
+        description: "Frankfurter: the ECB's daily exchange rates by currency pair (e.g. EURUSD)",
+        build: |settings| {
+            Providers::Fx(Fx::new(
+                fx::DEFAULT_BASE_URL,
+                settings.user_agent.as_deref(),
+            ))
+        },
+    },
+    ProviderEntry {
+        key: "Cash",
+        description: "Cash by currency code, always worth its amount",
+        build: |_| Providers::Cash(Cash),
+    },
+];
+
+/// Whether the configuration file can name a provider by this key
+pub(crate) fn is_provider_key(key: &str) -> bool {
+    REGISTRY.iter().any(|entry| entry.key == key)
+}
+
+/// The provider keys of the configuration file, comma separated
+pub(crate) fn provider_keys() -> String {
+    REGISTRY
+        .iter()
+        .map(|entry| entry.key)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Command line options forwarded to the providers when building them
+#[derive(Debug, Clone)]
+pub(crate) struct ProviderSettings {
+    /// exchange suffix appended by Yahoo to tickers without one
+    pub(crate) default_exchange_suffix: Option<String>,
+    /// the trading session Yahoo prices are taken from
+    pub(crate) session: Session,
+    /// the field of the Yahoo quotes taken as the price
+    pub(crate) price_field: PriceField,
+    /// the provider key of the instruments listed outside a provider block
+    pub(crate) default_provider: String,
+    /// the scheme and host XFRA prices are queried from
+    pub(crate) xfra_base_url: String,
+    /// the User-Agent header sent to the providers, their default if None
+    pub(crate) user_agent: Option<String>,
+    /// how long Yahoo's symbol search may take before the ticker is used as it is
+    pub(crate) search_timeout: Duration,
+    /// the number of past days each provider key has prices for, overriding the
+    /// providers' own limits
+    pub(crate) max_history: Vec<(String, u32)>,
+}
+
+impl ProviderSettings {
+    /// The number of past days a provider has prices for, as configured for its key
+    /// or else as the provider declares, None if unlimited
+    pub(crate) fn max_history_days(&self, provider_key: &str, provider: &Providers) -> Option<u32> {
+        self.max_history
+            .iter()
+            .rev()
+            .find(|(key, _)| key == provider_key)
+            .map(|(_, days)| *days)
+            .or_else(|| provider.max_history_days())
+    }
+}
+
+/// The kind of instruments a provider prices, for the asset class breakdown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum AssetClass {
+    Equity,
+    Crypto,
+    Cash,
+}
+
+impl Display for AssetClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AssetClass::Equity => write!(f, "equity/ETF"),
+            AssetClass::Crypto => write!(f, "crypto"),
+            AssetClass::Cash => write!(f, "cash"),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub(crate) enum Providers {
     YFinance(YFinance),
-    XFRA(XFRA),
+    Xfra(Xfra),
+    AlphaVantage(AlphaVantage),
+    CoinGecko(CoinGecko),
+    Fx(Fx),
+    Cash(Cash),
 }
 
 impl Providers {
@@ -14,31 +165,152 @@ impl Providers {
         &self,
         name: &str,
         date: NaiveDate,
-    ) -> Result<(String, NaiveDate, f64), std::io::Error> {
+    ) -> Result<(String, NaiveDate, f64, String), std::io::Error> {
         match self {
             Providers::YFinance(yfinance) => yfinance
                 .download_price(name.to_owned(), date)
                 .await
                 .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
-            Providers::XFRA(xfra) => xfra
+            Providers::Xfra(xfra) => xfra
                 .download_price(name.to_owned(), date)
                 .await
                 .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+            Providers::AlphaVantage(alpha_vantage) => alpha_vantage
+                .download_price(name.to_owned(), date)
+                .await
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+            Providers::CoinGecko(coin_gecko) => coin_gecko
+                .download_price(name.to_owned(), date)
+                .await
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+            Providers::Fx(fx) => fx
+                .download_price(name.to_owned(), date)
+                .await
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+            Providers::Cash(cash) => cash.download_price(name.to_owned(), date).await,
+        }
+    }
+
+    pub(crate) async fn download_prices_bulk(
+        &self,
+        name: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<(String, NaiveDate, f64, String)>, std::io::Error> {
+        match self {
+            Providers::YFinance(yfinance) => yfinance
+                .download_prices_bulk(name.to_owned(), start, end)
+                .await
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+            Providers::Xfra(xfra) => xfra
+                .download_prices_bulk(name.to_owned(), start, end)
+                .await
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+            Providers::AlphaVantage(alpha_vantage) => alpha_vantage
+                .download_prices_bulk(name.to_owned(), start, end)
+                .await
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+            Providers::CoinGecko(coin_gecko) => coin_gecko
+                .download_prices_bulk(name.to_owned(), start, end)
+                .await
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+            Providers::Fx(fx) => fx
+                .download_prices_bulk(name.to_owned(), start, end)
+                .await
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+            Providers::Cash(cash) => cash.download_prices_bulk(name.to_owned(), start, end).await,
+        }
+    }
+
+    /// Describes how a price is derived from the provider's response
+    pub(crate) async fn explain(
+        &self,
+        name: &str,
+        date: NaiveDate,
+    ) -> Result<String, std::io::Error> {
+        match self {
+            Providers::YFinance(yfinance) => yfinance
+                .explain(name, date)
+                .await
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+            Providers::Xfra(xfra) => xfra.explain(name, date).await,
+            Providers::AlphaVantage(alpha_vantage) => alpha_vantage.explain(name, date).await,
+            Providers::CoinGecko(coin_gecko) => coin_gecko.explain(name, date).await,
+            Providers::Fx(fx) => fx.explain(name, date).await,
+            Providers::Cash(_) => Ok(format!("Cash in {name}, always worth 1 {name}")),
+        }
+    }
+
+    /// How the provider matched a name to one of its symbols, for the providers
+    /// searching for them
+    pub(crate) fn resolution(&self, name: &str) -> Option<Resolution> {
+        match self {
+            Providers::YFinance(yfinance) => yfinance.resolution(name),
+            Providers::Xfra(_)
+            | Providers::AlphaVantage(_)
+            | Providers::CoinGecko(_)
+            | Providers::Fx(_)
+            | Providers::Cash(_) => None,
+        }
+    }
+
+    /// Whether a date's price won't change anymore, so that it can be cached
+    pub(crate) fn is_final(&self, date: NaiveDate) -> bool {
+        match self {
+            Providers::YFinance(yfinance) => yfinance.is_final(date),
+            Providers::CoinGecko(_) => !CoinGecko::is_live(date),
+            Providers::Xfra(_)
+            | Providers::AlphaVantage(_)
+            | Providers::Fx(_)
+            | Providers::Cash(_) => true,
+        }
+    }
+
+    /// Whether a range of prices is downloaded in a single request
+    pub(crate) fn supports_ranges(&self) -> bool {
+        match self {
+            Providers::YFinance(yfinance) => yfinance.supports_ranges(),
+            Providers::Xfra(_) | Providers::AlphaVantage(_) => true,
+            Providers::CoinGecko(_) | Providers::Fx(_) | Providers::Cash(_) => false,
+        }
+    }
+
+    pub(crate) fn max_history_days(&self) -> Option<u32> {
+        match self {
+            Providers::YFinance(yfinance) => yfinance.max_history_days(),
+            Providers::Xfra(xfra) => xfra.max_history_days(),
+            Providers::AlphaVantage(alpha_vantage) => alpha_vantage.max_history_days(),
+            Providers::CoinGecko(coin_gecko) => coin_gecko.max_history_days(),
+            Providers::Fx(fx) => fx.max_history_days(),
+            Providers::Cash(cash) => cash.max_history_days(),
+        }
+    }
+
+    pub(crate) fn asset_class(&self) -> AssetClass {
+        match self {
+            Providers::YFinance(_) | Providers::Xfra(_) | Providers::AlphaVantage(_) => {
+                AssetClass::Equity
+            }
+            Providers::CoinGecko(_) => AssetClass::Crypto,
+            Providers::Fx(_) | Providers::Cash(_) => AssetClass::Cash,
         }
     }
 
     pub(crate) fn get_provider_name(&self) -> String {
         match self {
             Providers::YFinance(yfinance) => yfinance.get_provider_name(),
-            Providers::XFRA(xfra) => xfra.get_provider_name(),
+            Providers::Xfra(xfra) => xfra.get_provider_name(),
+            Providers::AlphaVantage(alpha_vantage) => alpha_vantage.get_provider_name(),
+            Providers::CoinGecko(coin_gecko) => coin_gecko.get_provider_name(),
+            Providers::Fx(fx) => fx.get_provider_name(),
+            Providers::Cash(cash) => cash.get_provider_name(),
         }
     }
 
-    pub(crate) fn build(typestr: &str) -> Option<Self> {
-        match typestr {
-            "Yahoo" => return Some(Providers::YFinance(YFinance::new(false))),
-            "XFRA" => return Some(Providers::XFRA(XFRA::new())),
-            _ => None,
-        }
+    pub(crate) fn build(typestr: &str, settings: &ProviderSettings) -> Option<Self> {
+        REGISTRY
+            .iter()
+            .find(|entry| entry.key == typestr)
+            .map(|entry| (entry.build)(settings))
     }
 }
@@ -0,0 +1,111 @@
+use chrono::NaiveDate;
+
+/// The split ratios looked for, both ways
+const COMMON_RATIOS: [u32; 9] = [2, 3, 4, 5, 6, 8, 10, 15, 20];
+
+/// How far from a common ratio a price jump can be, as a fraction of the ratio,
+/// allowing for the stock's own move on the day
+const TOLERANCE: f64 = 0.1;
+
+/// A price jump between two consecutive quotes matching a common split ratio
+#[derive(Debug)]
+pub(crate) struct SplitCandidate {
+    /// the first day quoted after the split
+    pub(crate) date: NaiveDate,
+    /// the new shares per old share, e.g. 2 for a 2:1 split or 0.5 for a 1:2 reverse split
+    pub(crate) ratio: f64,
+    pub(crate) price_before: f64,
+    pub(crate) price_after: f64,
+}
+
+impl SplitCandidate {
+    /// The ratio written as new:old shares, e.g. "2:1" or "1:10"
+    pub(crate) fn ratio_label(&self) -> String {
+        if self.ratio >= 1. {
+            format!("{:.0}:1", self.ratio)
+        } else {
+            format!("1:{:.0}", 1. / self.ratio)
+        }
+    }
+}
+
+/// The jumps between consecutive prices, in date order, close to a common split ratio
+pub(crate) fn detect(prices: &[(NaiveDate, f64)]) -> Vec<SplitCandidate> {
+    prices
+        .windows(2)
+        .filter_map(|pair| {
+            let [(_, before), (date, after)] = pair else {
+                return None;
+            };
+            if *before <= 0. || *after <= 0. {
+                return None;
+            }
+            let jump = before / after;
+            let ratio = COMMON_RATIOS
+                .iter()
+                .map(|ratio| *ratio as f64)
+                .find_map(|ratio| {
+                    if (jump / ratio - 1.).abs() <= TOLERANCE {
+                        Some(ratio)
+                    } else if (jump * ratio - 1.).abs() <= TOLERANCE {
+                        Some(1. / ratio)
+                    } else {
+                        None
+                    }
+                })?;
+            Some(SplitCandidate {
+                date: *date,
+                ratio,
+                price_before: *before,
+                price_after: *after,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prices(values: &[f64]) -> Vec<(NaiveDate, f64)> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(day, value)| {
+                (
+                    NaiveDate::from_ymd_opt(2024, 6, day as u32 + 1).unwrap(),
+                    *value,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn detects_a_split_despite_the_move_of_the_day() {
+        // a 4:1 split on the 3rd, the stock also rising 5% that day
+        let candidates = detect(&prices(&[400., 410., 107.625, 108.]));
+        assert_eq!(candidates.len(), 1);
+        let candidate = &candidates[0];
+        assert_eq!(candidate.date, NaiveDate::from_ymd_opt(2024, 6, 3).unwrap());
+        assert_eq!(candidate.ratio, 4.);
+        assert_eq!(candidate.ratio_label(), "4:1");
+        assert_eq!(
+            (candidate.price_before, candidate.price_after),
+            (410., 107.625)
+        );
+    }
+
+    #[test]
+    fn detects_a_reverse_split() {
+        let candidates = detect(&prices(&[1.2, 1.1, 11.5]));
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].ratio, 0.1);
+        assert_eq!(candidates[0].ratio_label(), "1:10");
+    }
+
+    #[test]
+    fn ignores_the_ordinary_moves() {
+        // a 30% drop, a 60% drop (2.5x, between the ratios) and a missing price
+        assert!(detect(&prices(&[100., 70., 28., 0., 30.])).is_empty());
+    }
+}
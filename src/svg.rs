@@ -0,0 +1,274 @@
+use std::{fmt::Write, fs, io};
+
+use chrono::NaiveDate;
+
+// a line chart drawn as plain SVG elements: the series as polylines over a grid of
+// value ticks, the dates under the x-axis and a legend naming the series
+
+const WIDTH: f64 = 900.;
+const HEIGHT: f64 = 450.;
+const MARGIN_LEFT: f64 = 80.;
+const MARGIN_RIGHT: f64 = 20.;
+const MARGIN_TOP: f64 = 40.;
+const MARGIN_BOTTOM: f64 = 50.;
+
+/// The number of dates labelled under the x-axis, at most
+const DATE_LABELS: usize = 6;
+
+/// Colors of the series, darker than the terminal's to show on white
+const COLORS: [&str; 4] = ["#d62728", "#2ca02c", "#1f77b4", "#bcbd22"];
+
+/// The round step giving about this many ticks between min and max, e.g. 20 or 500.
+/// A flat series spans its own magnitude instead, e.g. 1000 for a constant 1500
+fn tick_step(min: f64, max: f64, ticks: f64) -> f64 {
+    let span = if max > min {
+        max - min
+    } else {
+        10f64.powf(max.abs().max(1.).log10().floor())
+    };
+    let raw = (span / ticks).max(f64::EPSILON);
+    let magnitude = 10f64.powf(raw.log10().floor());
+    [1., 2., 5., 10.]
+        .into_iter()
+        .map(|multiple| multiple * magnitude)
+        .find(|step| *step >= raw)
+        .unwrap_or(10. * magnitude)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Writes the named series to an SVG file as a line chart. Like the terminal
+/// charts, the x-axis has one step per date having a value
+pub(crate) fn write_chart(
+    path: &str,
+    title: &str,
+    series: &[(String, Vec<(NaiveDate, f64)>)],
+) -> Result<(), io::Error> {
+    // the markup would otherwise end up in e.g. a .png that no viewer opens
+    if !path.to_lowercase().ends_with(".svg") {
+        return Err(io::Error::other(format!(
+            "Unable to write the chart to {path}: only SVG charts are drawn, name it .svg"
+        )));
+    }
+    let mut dates = series
+        .iter()
+        .flat_map(|(_, values)| values.iter().map(|(date, _)| *date))
+        .collect::<Vec<_>>();
+    dates.sort();
+    dates.dedup();
+    let values = series
+        .iter()
+        .flat_map(|(_, values)| values.iter().map(|(_, value)| *value))
+        .filter(|value| value.is_finite());
+    let (min, max) = values.fold((f64::MAX, f64::MIN), |(min, max), value| {
+        (min.min(value), max.max(value))
+    });
+    if dates.len() < 2 || min > max {
+        return Err(io::Error::other(format!(
+            "Unable to write the chart to {path}: fewer than two days with values"
+        )));
+    }
+
+    let step = tick_step(min, max, 5.);
+    // a flat series is drawn across the middle of the chart
+    let (min, max) = if min == max {
+        (min - step / 2., max + step / 2.)
+    } else {
+        (min, max)
+    };
+    let y_min = (min / step).floor() * step;
+    let y_max = ((max / step).ceil() * step).max(y_min + step);
+    let plot_width = WIDTH - MARGIN_LEFT - MARGIN_RIGHT;
+    let plot_height = HEIGHT - MARGIN_TOP - MARGIN_BOTTOM;
+    let x_of = |date: &NaiveDate| {
+        let index = dates.partition_point(|axis_date| axis_date < date);
+        MARGIN_LEFT + plot_width * index as f64 / (dates.len() - 1) as f64
+    };
+    let y_of = |value: f64| MARGIN_TOP + plot_height * (y_max - value) / (y_max - y_min);
+
+    let mut svg = String::new();
+    // writing to a String can't fail
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" viewBox="0 0 {WIDTH} {HEIGHT}" font-family="sans-serif" font-size="12">"#
+    );
+    let _ = writeln!(
+        svg,
+        r#"<rect width="{WIDTH}" height="{HEIGHT}" fill="white"/>"#
+    );
+    let _ = writeln!(
+        svg,
+        r#"<text x="{}" y="24" text-anchor="middle" font-size="16">{}</text>"#,
+        WIDTH / 2.,
+        escape(title)
+    );
+
+    // the value grid and its labels, with as many decimals as the step has
+    let decimals = (-step.log10().floor()).max(0.) as usize;
+    let ticks = ((y_max - y_min) / step).round() as usize;
+    for tick in (0..=ticks).map(|index| y_min + index as f64 * step) {
+        let y = y_of(tick);
+        let _ = writeln!(
+            svg,
+            r##"<line x1="{MARGIN_LEFT}" y1="{y:.1}" x2="{:.1}" y2="{y:.1}" stroke="#ddd"/>"##,
+            WIDTH - MARGIN_RIGHT
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}" text-anchor="end">{tick:.decimals$}</text>"#,
+            MARGIN_LEFT - 8.,
+            y + 4.
+        );
+    }
+
+    // the axes and the dates, the first and last ones always labelled
+    let bottom = HEIGHT - MARGIN_BOTTOM;
+    let _ = writeln!(
+        svg,
+        r#"<polyline points="{MARGIN_LEFT},{MARGIN_TOP} {MARGIN_LEFT},{bottom} {},{bottom}" fill="none" stroke="black"/>"#,
+        WIDTH - MARGIN_RIGHT
+    );
+    let labels = DATE_LABELS.min(dates.len());
+    for label in 0..labels {
+        let date = dates[label * (dates.len() - 1) / (labels - 1)];
+        let x = x_of(&date);
+        let _ = writeln!(
+            svg,
+            r#"<line x1="{x:.1}" y1="{bottom}" x2="{x:.1}" y2="{}" stroke="black"/>"#,
+            bottom + 5.
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{x:.1}" y="{}" text-anchor="middle">{date}</text>"#,
+            bottom + 20.
+        );
+    }
+
+    // the series, then the legend naming them
+    for ((name, values), color) in series.iter().zip(COLORS.iter().cycle()) {
+        let points = values
+            .iter()
+            .filter(|(_, value)| value.is_finite())
+            .map(|(date, value)| format!("{:.1},{:.1}", x_of(date), y_of(*value)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let _ = writeln!(
+            svg,
+            r#"<polyline points="{points}" fill="none" stroke="{color}" stroke-width="2"><title>{}</title></polyline>"#,
+            escape(name)
+        );
+    }
+    for (index, ((name, _), color)) in series.iter().zip(COLORS.iter().cycle()).enumerate() {
+        let y = MARGIN_TOP + 12. + index as f64 * 18.;
+        let x = MARGIN_LEFT + 12.;
+        let _ = writeln!(
+            svg,
+            r#"<line x1="{x}" y1="{y}" x2="{}" y2="{y}" stroke="{color}" stroke-width="2"/>"#,
+            x + 20.
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}">{}</text>"#,
+            x + 26.,
+            y + 4.,
+            escape(name)
+        );
+    }
+    svg.push_str("</svg>\n");
+
+    fs::write(path, svg)
+        .map_err(|e| io::Error::other(format!("Unable to write the chart to {path}: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    /// The markup of a chart of the series
+    fn chart(series: &[(String, Vec<(NaiveDate, f64)>)]) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "livestock-{}-{}.svg",
+            std::process::id(),
+            series[0].0
+        ));
+        let path = path.to_str().unwrap();
+        write_chart(path, "Tom & Jerry's <portfolio>", series).unwrap();
+        let svg = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+        svg
+    }
+
+    /// The labels of the value grid, from the bottom up
+    fn value_labels(svg: &str) -> Vec<&str> {
+        svg.lines()
+            .filter(|line| line.contains(r#"text-anchor="end""#))
+            .filter_map(|line| line.split('>').nth(1)?.split('<').next())
+            .collect()
+    }
+
+    #[test]
+    fn rounds_the_steps() {
+        assert_eq!(tick_step(0., 100., 5.), 20.);
+        assert_eq!(tick_step(1000., 3400., 5.), 500.);
+        assert_eq!(tick_step(0.1, 0.2, 5.), 0.02);
+    }
+
+    #[test]
+    fn steps_a_flat_series_by_its_magnitude() {
+        assert_eq!(tick_step(1500., 1500., 5.), 200.);
+        assert_eq!(tick_step(0., 0., 5.), 0.2);
+    }
+
+    #[test]
+    fn draws_the_series_and_the_grid() {
+        let series = vec![
+            (
+                "AAPL".to_owned(),
+                vec![(date(1), 100.), (date(2), 150.), (date(3), 200.)],
+            ),
+            ("MSFT".to_owned(), vec![(date(2), 120.), (date(3), 130.)]),
+        ];
+        let svg = chart(&series);
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        assert!(svg.contains("Tom &amp; Jerry's &lt;portfolio&gt;"));
+        assert_eq!(
+            value_labels(&svg),
+            ["100", "120", "140", "160", "180", "200"]
+        );
+        // the first and last dates at the ends of the x-axis, the top and the bottom
+        // of the grid at the maximum and the minimum
+        assert!(svg.contains(
+            r##"<polyline points="80.0,400.0 480.0,220.0 880.0,40.0" fill="none" stroke="#d62728""##
+        ));
+        assert!(svg.contains(
+            r##"<polyline points="480.0,328.0 880.0,292.0" fill="none" stroke="#2ca02c""##
+        ));
+        assert!(svg.contains(">2024-03-01</text>"));
+        assert!(svg.contains(">2024-03-03</text>"));
+    }
+
+    #[test]
+    fn draws_a_flat_series_with_round_labels() {
+        let series = vec![("Cash".to_owned(), vec![(date(1), 1500.), (date(2), 1500.)])];
+        let svg = chart(&series);
+        assert_eq!(value_labels(&svg), ["1400", "1600"]);
+        assert!(svg.contains(r#"<polyline points="80.0,220.0 880.0,220.0""#));
+    }
+
+    #[test]
+    fn refuses_other_image_formats() {
+        let series = vec![("Cash".to_owned(), vec![(date(1), 1.), (date(2), 1.)])];
+        let error = write_chart("value.png", "Cash", &series).unwrap_err();
+        assert!(error.to_string().contains("only SVG charts"));
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt::Display;
+
+const COLUMN_SEPARATOR: &str = "  ";
+
+/// A plain text table whose columns are as wide as their widest cell.
+/// The columns whose cells are all numbers (e.g. 12.5, +3.10%, 1,000 or n/a)
+/// are right aligned, the others left aligned
+#[derive(Debug, Default)]
+pub(crate) struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+    /// printed last, below a separator line
+    totals: Option<Vec<String>>,
+}
+
+impl Table {
+    pub(crate) fn new(headers: &[&str]) -> Self {
+        Self {
+            headers: headers.iter().map(|header| header.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn add_row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    pub(crate) fn set_totals(&mut self, totals: Vec<String>) {
+        self.totals = Some(totals);
+    }
+
+    /// Appends a column whose cells are derived from the rows' first cells, the
+    /// totals' cell being left empty
+    pub(crate) fn add_column(&mut self, header: &str, cell: impl Fn(&str) -> String) {
+        self.headers.push(header.to_owned());
+        for row in &mut self.rows {
+            let value = cell(row.first().map(String::as_str).unwrap_or_default());
+            row.push(value);
+        }
+        if let Some(totals) = &mut self.totals {
+            totals.resize(self.headers.len(), String::new());
+        }
+    }
+
+    fn is_number(cell: &str) -> bool {
+        cell == "n/a"
+            || cell
+                .trim_end_matches('%')
+                .replace(',', "")
+                .parse::<f64>()
+                .is_ok()
+    }
+
+    fn body(&self) -> impl Iterator<Item = &Vec<String>> {
+        self.rows.iter().chain(self.totals.iter())
+    }
+
+    fn widths(&self) -> Vec<usize> {
+        (0..self.headers.len())
+            .map(|column| {
+                self.body()
+                    .filter_map(|row| row.get(column))
+                    .chain(std::iter::once(&self.headers[column]))
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    fn right_aligned(&self) -> Vec<bool> {
+        (0..self.headers.len())
+            .map(|column| {
+                self.body().next().is_some()
+                    && self
+                        .body()
+                        .filter_map(|row| row.get(column))
+                        .all(|cell| cell.is_empty() || Self::is_number(cell))
+            })
+            .collect()
+    }
+
+    fn write_row(
+        f: &mut std::fmt::Formatter<'_>,
+        row: &[String],
+        widths: &[usize],
+        right_aligned: &[bool],
+    ) -> std::fmt::Result {
+        let mut line = String::new();
+        for (column, width) in widths.iter().enumerate() {
+            let cell = row.get(column).map(String::as_str).unwrap_or_default();
+            if column > 0 {
+                line.push_str(COLUMN_SEPARATOR);
+            }
+            if right_aligned[column] {
+                line.push_str(&format!("{cell:>width$}"));
+            } else {
+                line.push_str(&format!("{cell:<width$}"));
+            }
+        }
+        writeln!(f, "{}", line.trim_end())
+    }
+}
+
+impl Display for Table {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let widths = self.widths();
+        let right_aligned = self.right_aligned();
+        Self::write_row(f, &self.headers, &widths, &right_aligned)?;
+        for row in &self.rows {
+            Self::write_row(f, row, &widths, &right_aligned)?;
+        }
+        if let Some(totals) = &self.totals {
+            let separator_width = widths.iter().sum::<usize>()
+                + COLUMN_SEPARATOR.len() * widths.len().saturating_sub(1);
+            writeln!(f, "{}", "-".repeat(separator_width))?;
+            Self::write_row(f, totals, &widths, &right_aligned)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(cells: &[&str]) -> Vec<String> {
+        cells.iter().map(|cell| cell.to_string()).collect()
+    }
+
+    fn holdings() -> Table {
+        let mut table = Table::new(&["Symbol", "Value"]);
+        table.add_row(row(&["AAPL", "100"]));
+        table.add_row(row(&["MSFT", "20.5"]));
+        table.set_totals(row(&["Total", "120.5"]));
+        table
+    }
+
+    #[test]
+    fn aligns_the_numbers_right_and_the_text_left() {
+        let mut table = Table::new(&["Symbol", "Value", "Change"]);
+        table.add_row(row(&["AAPL", "1,234.50", "+3.10%"]));
+        table.add_row(row(&["VWCE", "n/a", "-0.5%"]));
+        assert_eq!(
+            table.to_string(),
+            "Symbol     Value  Change\n\
+             AAPL    1,234.50  +3.10%\n\
+             VWCE         n/a   -0.5%\n"
+        );
+    }
+
+    #[test]
+    fn prints_the_totals_below_a_separator() {
+        assert_eq!(
+            holdings().to_string(),
+            "Symbol  Value\n\
+             AAPL      100\n\
+             MSFT     20.5\n\
+             -------------\n\
+             Total   120.5\n"
+        );
+    }
+
+    #[test]
+    fn adds_a_column_derived_from_the_first_cells() {
+        let mut table = holdings();
+        table.add_column("Name", |symbol| match symbol {
+            "AAPL" => "Apple".to_owned(),
+            _ => "Microsoft".to_owned(),
+        });
+        assert_eq!(
+            table.to_string(),
+            "Symbol  Value  Name\n\
+             AAPL      100  Apple\n\
+             MSFT     20.5  Microsoft\n\
+             ------------------------\n\
+             Total   120.5\n"
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+// a local HTTP server answering the providers' requests with recorded responses,
+// so that their parsing is tested without network
+
+/// A JSON response, after which the connection is closed
+pub(crate) fn ok(body: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+/// A 404 with a JSON body
+pub(crate) fn not_found(body: &str) -> String {
+    format!(
+        "HTTP/1.1 404 Not Found\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+/// A 429 asking to retry after a number of seconds
+pub(crate) fn rate_limited(retry_after: u64) -> String {
+    format!("HTTP/1.1 429 Too Many Requests\r\nRetry-After: {retry_after}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+}
+
+/// Answers the requests with the responses in order, a connection answering
+/// as many requests as it's given responses. Returns the base URL to query and
+/// the head of each request received, lowercased
+pub(crate) fn serve_connections(responses: Vec<Vec<String>>) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let requests = Arc::new(Mutex::new(vec![]));
+    let received = Arc::clone(&requests);
+    thread::spawn(move || {
+        for connection_responses in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            for response in connection_responses {
+                let mut head = String::new();
+                while reader.read_line(&mut head).unwrap() > 0 && !head.ends_with("\r\n\r\n") {}
+                received.lock().unwrap().push(head.to_lowercase());
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        }
+    });
+    (format!("http://{address}"), requests)
+}
+
+/// Answers the requests with the responses in order, one per connection
+pub(crate) fn serve(responses: Vec<String>) -> (String, Arc<Mutex<Vec<String>>>) {
+    serve_connections(
+        responses
+            .into_iter()
+            .map(|response| vec![response])
+            .collect(),
+    )
+}
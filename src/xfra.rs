@@ -1,88 +1,405 @@
-use std::{collections::HashMap, sync::Mutex};
+use std::time::Duration;
 
 use chrono::NaiveDate;
-use reqwest;
+use reqwest::{header, StatusCode};
 
 use crate::provider::Provider;
 
+/// Boerse Frankfurt quotes everything in euros
+const CURRENCY: &str = "EUR";
+
+/// The public Boerse Frankfurt API
+pub(crate) const DEFAULT_BASE_URL: &str = "https://api.boerse-frankfurt.de";
+
+/// The website calling the API, which it expects as the origin of the requests
+const ORIGIN: &str = "https://www.boerse-frankfurt.de";
+
+/// How many times a rate limited request is sent again
+const RATE_LIMIT_RETRIES: u32 = 3;
+
+/// The longest Retry-After waited for, a rate limit lasting longer is reported
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// The User-Agent of the requests unless told otherwise, as the API sometimes
+/// rejects reqwest's lack of one
+const DEFAULT_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
 /// Get the data from XFRA API
-/// E.g. https://api.boerse-frankfurt.de/v1/data/price_information/single?isin=SOME_ISIN_HERE&mic=XFRA
+/// E.g. https://api.boerse-frankfurt.de/v1/data/price_history/single?isin=SOME_ISIN_HERE&mic=XFRA&from=2024-03-01&to=2024-03-01
 #[derive(Debug)]
-pub struct XFRA {
-    /// because the XFRA API doesn't allow yet to query a specific date, we use
-    /// this cache in order to avoid redundant queries
-    cache: Mutex<HashMap<String, f64>>,
+pub struct Xfra {
+    /// the API's scheme and host, e.g. a caching proxy in front of the public API
+    base_url: String,
+    /// shared by the requests so that their connections are kept alive and reused
+    client: reqwest::Client,
 }
 
-impl XFRA {
-    pub(crate) fn new() -> Self {
+impl Xfra {
+    pub(crate) fn new(base_url: &str, user_agent: Option<&str>) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent(user_agent.unwrap_or(DEFAULT_USER_AGENT))
+            .build();
         Self {
-            cache: Mutex::new(HashMap::default()),
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            client: client.unwrap(),
         }
     }
+
+    /// The raw daily prices of an ISIN between two dates (inclusive)
+    async fn query(
+        &self,
+        isin: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<String, std::io::Error> {
+        let url = format!(
+            "{}/v1/data/price_history/single?isin={isin}&mic=XFRA&from={from}&to={to}",
+            self.base_url
+        );
+        let mut retries = 0;
+        let response = loop {
+            let request = self
+                .client
+                .get(&url)
+                .header(header::ACCEPT, "application/json")
+                .header(header::ORIGIN, ORIGIN)
+                .header(header::REFERER, format!("{ORIGIN}/"));
+            let response = request.send().await.map_err(|_| {
+                std::io::Error::other(format!("XFRA: Invalid response while querying for {isin}"))
+            })?;
+            if response.status() != StatusCode::TOO_MANY_REQUESTS {
+                break response;
+            }
+            // waits as long as the API asks to, in seconds, before trying again
+            let retry_after = response
+                .headers()
+                .get(header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(Duration::from_secs(1));
+            if retries == RATE_LIMIT_RETRIES || retry_after > MAX_RETRY_AFTER {
+                return Err(std::io::Error::other(format!(
+                    "XFRA: rate limited while querying for {isin}, retry after {}s",
+                    retry_after.as_secs()
+                )));
+            }
+            retries += 1;
+            tokio::time::sleep(retry_after).await;
+        };
+        // an error page, e.g. the HTML of a 503, isn't a price history
+        if !response.status().is_success() {
+            return Err(std::io::Error::other(format!(
+                "XFRA: HTTP status {} while querying for {isin}",
+                response.status()
+            )));
+        }
+        response.text().await.map_err(|_| {
+            std::io::Error::other(format!(
+                "XFRA: Invalid text in response while querying for {isin}"
+            ))
+        })
+    }
+
+    /// The close of each day of a price history response having one and whether
+    /// the prices are traded in percent
+    fn parse_history(
+        isin: &str,
+        response: &str,
+    ) -> Result<(Vec<(NaiveDate, f64)>, bool), std::io::Error> {
+        let json: serde_json::Value = serde_json::from_str(response).map_err(|e| {
+            std::io::Error::other(format!("XFRA: invalid price history for {isin}: {e}"))
+        })?;
+        let Some(days) = json.get("data").and_then(serde_json::Value::as_array) else {
+            return Err(std::io::Error::other(format!(
+                "XFRA: error retrieving the data key for {isin}"
+            )));
+        };
+        // the days without trades have no close, which may also be given as a string
+        let closes = days
+            .iter()
+            .filter_map(|day| {
+                let date = day.get("date")?.as_str()?.parse().ok()?;
+                let close = day.get("close")?;
+                let close = match close.as_str() {
+                    Some(text) => text.trim().parse().ok()?,
+                    None => close.as_f64()?,
+                };
+                Some((date, close))
+            })
+            .collect();
+        let traded_in_percent = json
+            .get("tradedInPercent")
+            .and_then(serde_json::Value::as_bool)
+            .unwrap_or_default();
+        Ok((closes, traded_in_percent))
+    }
+
+    /// The daily closes of an ISIN between two dates (inclusive), in euros
+    async fn closes(
+        &self,
+        isin: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, f64)>, std::io::Error> {
+        let response = self.query(isin, from, to).await?;
+        let (closes, traded_in_percent) = Self::parse_history(isin, &response)?;
+        // divide the price by 100 in the case the price is traded in percent
+        Ok(closes
+            .into_iter()
+            .map(|(date, close)| {
+                if traded_in_percent {
+                    (date, close / 100.0)
+                } else {
+                    (date, close)
+                }
+            })
+            .collect())
+    }
+
+    /// Describes how the price of an ISIN on a date is derived, for the explain command
+    pub(crate) async fn explain(
+        &self,
+        isin: &str,
+        date: NaiveDate,
+    ) -> Result<String, std::io::Error> {
+        let response = self.query(isin, date, date).await?;
+        let (closes, traded_in_percent) = Self::parse_history(isin, &response)?;
+        let mut explanation = format!("Raw response: {response}");
+        match closes.iter().find(|(close_date, _)| *close_date == date) {
+            Some((_, close)) => {
+                explanation.push_str(&format!("\nClose on {date}: {close}"));
+                if traded_in_percent {
+                    explanation.push_str(&format!(
+                        "\nTraded in percent, dividing by 100: {}",
+                        close / 100.0
+                    ));
+                }
+            }
+            None => explanation.push_str(&format!("\nNo close on {date}, not a trading day")),
+        }
+        Ok(explanation)
+    }
 }
 
-impl Provider for XFRA {
+impl Provider for Xfra {
     type ErrorType = std::io::Error;
 
     fn get_provider_name(&self) -> String {
         "XFRA".to_owned()
     }
 
-    /// Downloads the price for a given ISIN
+    /// Downloads the close of an ISIN on a date, an error if it wasn't traded that day
     async fn download_price(
         &self,
         isin: String,
         date: NaiveDate,
-    ) -> Result<(String, NaiveDate, f64), Self::ErrorType> {
-        if let Some(cache_result) = self.cache.lock().unwrap().get(&isin) {
-            return Ok((isin, date, *cache_result));
+    ) -> Result<(String, NaiveDate, f64, String), Self::ErrorType> {
+        let closes = self.closes(&isin, date, date).await?;
+        match closes
+            .into_iter()
+            .find(|(close_date, _)| *close_date == date)
+        {
+            Some((_, close)) => Ok((isin, date, close, CURRENCY.to_owned())),
+            None => Err(std::io::Error::other(format!(
+                "XFRA: no price for {isin} on {date}"
+            ))),
         }
+    }
 
-        // TODO: use a keepalive http connection instead of doing 3-way handshake for each request
-        let url = format!(
-            "https://api.boerse-frankfurt.de/v1/data/price_information/single?isin={isin}&mic=XFRA"
+    /// Fetches the daily closes of the whole range in one request
+    async fn download_prices_bulk(
+        &self,
+        isin: String,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<(String, NaiveDate, f64, String)>, Self::ErrorType> {
+        Ok(self
+            .closes(&isin, start, end)
+            .await?
+            .into_iter()
+            .filter(|(date, _)| *date >= start && *date <= end)
+            .map(|(date, close)| (isin.clone(), date, close, CURRENCY.to_owned()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{ok, rate_limited, serve, serve_connections};
+
+    const BOND_IN_PERCENT: &str = include_str!("../fixtures/xfra/bond_in_percent.json");
+    const SHARE_IN_EUROS: &str = include_str!("../fixtures/xfra/share_in_euros.json");
+    const NON_TRADING_DAY: &str = include_str!("../fixtures/xfra/non_trading_day.json");
+    const UNKNOWN_ISIN: &str = include_str!("../fixtures/xfra/unknown_isin.json");
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    #[tokio::test]
+    async fn queries_the_close_of_the_date() {
+        let (base_url, requests) = serve(vec![ok(SHARE_IN_EUROS)]);
+        let xfra = Xfra::new(&base_url, None);
+        let (isin, day, price, currency) = xfra
+            .download_price("DE0007164600".to_owned(), date(1))
+            .await
+            .unwrap();
+        assert_eq!(
+            (isin.as_str(), day, price),
+            ("DE0007164600", date(1), 172.34)
         );
-        let response = reqwest::get(url)
+        assert_eq!(currency, "EUR");
+        assert!(requests.lock().unwrap()[0].starts_with(
+            "get /v1/data/price_history/single?isin=de0007164600&mic=xfra&from=2024-03-01&to=2024-03-01 "
+        ));
+    }
+
+    #[tokio::test]
+    async fn reuses_the_connection() {
+        let keep_alive = ok(SHARE_IN_EUROS).replace("Connection: close", "Connection: keep-alive");
+        // a single connection answers both requests
+        let (base_url, requests) = serve_connections(vec![vec![keep_alive.clone(), keep_alive]]);
+        let xfra = Xfra::new(&base_url, None);
+        for _ in 0..2 {
+            // a new connection would never be answered
+            let download = xfra.download_price("DE0007164600".to_owned(), date(1));
+            tokio::time::timeout(Duration::from_secs(5), download)
+                .await
+                .expect("a second connection was opened")
+                .unwrap();
+        }
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn sends_a_user_agent() {
+        let (base_url, requests) = serve(vec![ok(SHARE_IN_EUROS), ok(SHARE_IN_EUROS)]);
+        Xfra::new(&base_url, None)
+            .download_price("DE0007164600".to_owned(), date(1))
             .await
-            .map_err(|_| {
-                std::io::Error::other(format!("XFRA: Invalid response while querying for {isin}"))
-            })?
-            .text()
+            .unwrap();
+        Xfra::new(&base_url, Some("Mozilla/5.0"))
+            .download_price("DE0007164600".to_owned(), date(1))
             .await
-            .map_err(|_| {
-                std::io::Error::other(format!(
-                    "XFRA: Invalid text in response while querying for {isin}"
-                ))
-            })?;
+            .unwrap();
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].contains("\r\nuser-agent: livestock/"));
+        assert!(requests[1].contains("\r\nuser-agent: mozilla/5.0\r\n"));
+    }
 
-        let json: serde_json::Value = serde_json::from_str(&response).unwrap();
-        let price = match json.get("lastPrice") {
-            Some(value) => value,
-            None => {
-                return Err(std::io::Error::other(format!(
-                    "XFRA: error retrieving the lastPrice key for {isin}"
-                )));
-            }
-        };
+    #[tokio::test]
+    async fn divides_the_prices_traded_in_percent() {
+        let (base_url, _) = serve(vec![ok(BOND_IN_PERCENT)]);
+        let xfra = Xfra::new(&base_url, None);
+        let (_, _, price, _) = xfra
+            .download_price("DE0001102408".to_owned(), date(1))
+            .await
+            .unwrap();
+        assert!((price - 0.9887).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn fails_on_a_non_trading_day() {
+        let (base_url, _) = serve(vec![ok(NON_TRADING_DAY)]);
+        let xfra = Xfra::new(&base_url, None);
+        let error = xfra
+            .download_price("DE0007164600".to_owned(), date(2))
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("no price for DE0007164600 on 2024-03-02"));
+    }
 
-        let mut float_price =
-            serde_json::from_value(price.clone()).expect("XFRA: error transforming price to float");
+    #[tokio::test]
+    async fn fails_without_data() {
+        let (base_url, _) = serve(vec![ok(UNKNOWN_ISIN)]);
+        let xfra = Xfra::new(&base_url, None);
+        let error = xfra
+            .download_price("DE000A1EWWW0".to_owned(), date(1))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("data"));
+    }
 
-        // divide the price by 100 in the case the price is traded in percent
-        match json.get("tradedInPercent") {
-            Some(value) => match serde_json::from_value(value.clone()) {
-                Ok(boolean_value) => {
-                    if boolean_value {
-                        float_price /= 100.0;
-                    }
-                }
-                Err(_) => {}
-            },
-            None => {}
+    #[tokio::test]
+    async fn downloads_a_range_at_once() {
+        let (base_url, requests) = serve(vec![ok(BOND_IN_PERCENT)]);
+        let xfra = Xfra::new(&base_url, None);
+        let start = NaiveDate::from_ymd_opt(2024, 2, 28).unwrap();
+        let mut prices = xfra
+            .download_prices_bulk("DE0001102408".to_owned(), start, date(1))
+            .await
+            .unwrap();
+        prices.sort_by_key(|(_, date, _, _)| *date);
+        // the 28th has no close
+        let dates = prices
+            .iter()
+            .map(|(_, date, _, _)| *date)
+            .collect::<Vec<_>>();
+        assert_eq!(dates, vec![start.succ_opt().unwrap(), date(1)]);
+        assert!((prices[0].2 - 0.9875).abs() < 1e-9);
+        assert!(requests.lock().unwrap()[0].contains("&from=2024-02-28&to=2024-03-01 "));
+    }
+
+    #[test]
+    fn rejects_what_isnt_json() {
+        for body in [
+            "",
+            "lastPrice=98.87",
+            "<html><body>503 Service Unavailable</body></html>",
+        ] {
+            assert!(Xfra::parse_history("DE0001102408", body).is_err());
+        }
+    }
+
+    #[test]
+    fn reads_the_prices_given_as_strings() {
+        let response = BOND_IN_PERCENT.replace("98.87", "\"98.87\"");
+        let (closes, traded_in_percent) = Xfra::parse_history("DE0001102408", &response).unwrap();
+        assert!(closes.contains(&(date(1), 98.87)));
+        assert!(traded_in_percent);
+    }
+
+    #[tokio::test]
+    async fn fails_on_an_error_page() {
+        let html = "<html><body>503 Service Unavailable</body></html>";
+        let unavailable = format!(
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{html}",
+            html.len()
+        );
+        let (base_url, _) = serve(vec![unavailable, ok(html)]);
+        let xfra = Xfra::new(&base_url, None);
+        for _ in 0..2 {
+            assert!(xfra
+                .download_price("DE0001102408".to_owned(), date(1))
+                .await
+                .is_err());
         }
+    }
 
-        self.cache.lock().unwrap().insert(isin.clone(), float_price);
-        Ok((isin, date, float_price))
+    #[tokio::test]
+    async fn retries_when_rate_limited() {
+        let (base_url, requests) = serve(vec![rate_limited(0), ok(SHARE_IN_EUROS)]);
+        let xfra = Xfra::new(&base_url, None);
+        let (_, _, price, _) = xfra
+            .download_price("DE0007164600".to_owned(), date(1))
+            .await
+            .unwrap();
+        assert_eq!(price, 172.34);
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn reports_a_rate_limit_lasting_too_long() {
+        let (base_url, _) = serve(vec![rate_limited(3600)]);
+        let xfra = Xfra::new(&base_url, None);
+        let error = xfra
+            .download_price("DE0007164600".to_owned(), date(1))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("rate limited"));
     }
 }
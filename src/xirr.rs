@@ -0,0 +1,103 @@
+use chrono::NaiveDate;
+
+/// The annual rate at which the cash flows' net present value is 0, None if
+/// there's no such rate (e.g. the flows are all of the same sign).
+/// Found by bisection, which unlike Newton's method can't diverge
+pub(crate) fn xirr(cash_flows: &[(NaiveDate, f64)]) -> Option<f64> {
+    let first_date = cash_flows.iter().map(|(date, _)| *date).min()?;
+    if !cash_flows.iter().any(|(_, amount)| *amount > 0.)
+        || !cash_flows.iter().any(|(_, amount)| *amount < 0.)
+    {
+        return None;
+    }
+    let npv = |rate: f64| {
+        cash_flows
+            .iter()
+            .map(|(date, amount)| {
+                let years = (*date - first_date).num_days() as f64 / 365.;
+                amount / (1. + rate).powf(years)
+            })
+            .sum::<f64>()
+    };
+
+    let mut low = -0.999_999;
+    let mut low_npv = npv(low);
+    let mut high = 1.;
+    while npv(high).signum() == low_npv.signum() {
+        high *= 2.;
+        if high > 1e6 {
+            return None;
+        }
+    }
+    for _ in 0..200 {
+        let middle = (low + high) / 2.;
+        let middle_npv = npv(middle);
+        if middle_npv.signum() == low_npv.signum() {
+            low = middle;
+            low_npv = middle_npv;
+        } else {
+            high = middle;
+        }
+        if high - low < 1e-10 {
+            break;
+        }
+    }
+    Some((low + high) / 2.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn finds_the_rate_of_a_year() {
+        let cash_flows = [(date(2023, 1, 1), -1000.), (date(2024, 1, 1), 1100.)];
+        let rate = xirr(&cash_flows).unwrap();
+        assert!((rate - 0.1).abs() < 1e-8, "{rate}");
+    }
+
+    #[test]
+    fn finds_the_rate_of_irregular_flows() {
+        // the example of the XIRR spreadsheet function, 37.34%
+        let cash_flows = [
+            (date(2008, 1, 1), -10000.),
+            (date(2008, 3, 1), 2750.),
+            (date(2008, 10, 30), 4250.),
+            (date(2009, 2, 15), 3250.),
+            (date(2009, 4, 1), 2750.),
+        ];
+        let rate = xirr(&cash_flows).unwrap();
+        assert!((rate - 0.373362535).abs() < 1e-6, "{rate}");
+    }
+
+    #[test]
+    fn finds_a_loss() {
+        let cash_flows = [(date(2023, 1, 1), -1000.), (date(2024, 1, 1), 500.)];
+        let rate = xirr(&cash_flows).unwrap();
+        assert!((rate + 0.5).abs() < 1e-8, "{rate}");
+    }
+
+    #[test]
+    fn has_no_rate_for_flows_of_a_single_sign() {
+        let inflows = [(date(2023, 1, 1), 1000.), (date(2024, 1, 1), 1100.)];
+        let outflows = [(date(2023, 1, 1), -1000.), (date(2024, 1, 1), -1100.)];
+        assert_eq!(xirr(&inflows), None);
+        assert_eq!(xirr(&outflows), None);
+        assert_eq!(xirr(&[]), None);
+    }
+
+    #[test]
+    fn has_no_rate_when_the_value_never_crosses_zero() {
+        // the net present value peaks at about -500, at a rate of about 100%
+        let cash_flows = [
+            (date(2023, 1, 1), -1000.),
+            (date(2024, 1, 1), 2000.),
+            (date(2025, 1, 1), -2000.),
+        ];
+        assert_eq!(xirr(&cash_flows), None);
+    }
+}
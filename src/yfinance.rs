@@ -3,9 +3,11 @@ use std::{
     error::Error,
     fmt::{Debug, Display},
     sync::Mutex,
+    time::Duration as StdDuration,
 };
 
-use chrono::NaiveDate;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use clap::ValueEnum;
 use yahoo_finance_api::{
     self as yf,
     time::{Duration, OffsetDateTime},
@@ -54,72 +56,464 @@ impl Display for YFinanceError {
 
 impl Error for YFinanceError {}
 
+/// The trading session a price is taken from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Session {
+    /// The close of the regular session
+    #[default]
+    Regular,
+    /// The last pre-market price
+    Pre,
+    /// The last post-market price
+    Post,
+}
+
+/// The field of a daily quote taken as the price
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum PriceField {
+    #[default]
+    Close,
+    Open,
+    High,
+    Low,
+    /// The volume weighted average price, approximated by the typical price
+    /// (high + low + close) / 3 since the quotes are daily
+    Vwap,
+}
+
+impl PriceField {
+    fn of(self, quote: &yf::Quote) -> f64 {
+        match self {
+            PriceField::Close => quote.close,
+            PriceField::Open => quote.open,
+            PriceField::High => quote.high,
+            PriceField::Low => quote.low,
+            PriceField::Vwap => (quote.high + quote.low + quote.close) / 3.,
+        }
+    }
+}
+
+/// How a ticker was matched to a Yahoo symbol
+#[derive(Debug, Clone)]
+pub(crate) struct Resolution {
+    pub(crate) symbol: String,
+    /// the search query which found it, e.g. with the default exchange suffix
+    pub(crate) query: String,
+    /// the number of quotes the search returned
+    pub(crate) matches: usize,
+    pub(crate) quote_type: String,
+    pub(crate) exchange: String,
+}
+
+impl Resolution {
+    /// Whether the search returned other quotes than the chosen one
+    pub(crate) fn is_ambiguous(&self) -> bool {
+        self.matches > 1
+    }
+}
+
 #[derive(Debug)]
 pub struct YFinance {
     provider: tokio::sync::Mutex<DebugHolder<YahooConnector>>,
-    ticker_resolver_cache: Mutex<HashMap<String, String>>,
+    ticker_resolver_cache: Mutex<HashMap<String, Resolution>>,
     debug: bool,
+    /// exchange suffix (e.g. ".DE") tried first for tickers without one
+    default_exchange_suffix: Option<String>,
+    session: Session,
+    price_field: PriceField,
+    /// how long a symbol search may take before the ticker is used as it is
+    search_timeout: StdDuration,
 }
 
 impl YFinance {
-    pub(crate) fn new(debug: bool) -> Self {
+    pub(crate) fn new(
+        debug: bool,
+        default_exchange_suffix: Option<String>,
+        session: Session,
+        price_field: PriceField,
+        user_agent: Option<&str>,
+        search_timeout: StdDuration,
+    ) -> Self {
+        // the library sends a browser's User-Agent unless told otherwise
+        let connector = match user_agent {
+            Some(user_agent) => yf::YahooConnector::builder().build_with_agent(user_agent),
+            None => yf::YahooConnector::new(),
+        };
         Self {
             provider: tokio::sync::Mutex::new(DebugHolder {
-                inner: yf::YahooConnector::new().unwrap(),
+                inner: connector.unwrap(),
             }),
             ticker_resolver_cache: Mutex::new(HashMap::default()),
             debug,
+            default_exchange_suffix,
+            session,
+            price_field,
+            search_timeout,
         }
     }
 
-    async fn resolve_symbol(
-        &self,
-        ticker: &str,
-    ) -> Result<String, <YFinance as Provider>::ErrorType> {
-        if let Some(cache_result) = self.ticker_resolver_cache.lock().unwrap().get(ticker) {
-            return Ok(cache_result.clone());
+    /// The currency of the quotes, Yahoo omits it for a few instruments (e.g. indices)
+    fn currency(metadata: &yf::YMetaData) -> String {
+        metadata
+            .currency
+            .clone()
+            .unwrap_or_else(|| "USD".to_owned())
+    }
+
+    /// Only the regular session's daily closes are available as a range
+    pub(crate) fn supports_ranges(&self) -> bool {
+        self.session == Session::Regular
+    }
+
+    /// Whether a date's price won't change anymore, a pre or post market session of
+    /// today possibly being still in progress
+    pub(crate) fn is_final(&self, date: NaiveDate) -> bool {
+        self.session == Session::Regular || date < Utc::now().date_naive()
+    }
+
+    /// The name the prices are cached under, so that the sessions and the price
+    /// fields don't mix
+    pub(crate) fn get_cache_name(&self) -> String {
+        let mut qualifiers = vec![];
+        match self.session {
+            Session::Regular => {}
+            Session::Pre => qualifiers.push("pre-market".to_owned()),
+            Session::Post => qualifiers.push("post-market".to_owned()),
         }
+        if self.price_field != PriceField::Close {
+            qualifiers.push(format!("{:?}", self.price_field).to_lowercase());
+        }
+        if qualifiers.is_empty() {
+            self.get_provider_name()
+        } else {
+            format!("{} ({})", self.get_provider_name(), qualifiers.join(", "))
+        }
+    }
 
-        let search_result = self
+    /// The last price of the pre or post market session on a date.
+    /// Yahoo only keeps the extended hours quotes of the last few sessions
+    async fn download_session_price(
+        &self,
+        yahoo_symbol: &str,
+        ticker: &str,
+        date: NaiveDate,
+    ) -> Result<(f64, String), YFinanceError> {
+        let response = self
             .provider
             .lock()
             .await
             .inner
-            .search_ticker(&ticker)
+            .get_quote_period_interval(yahoo_symbol, "5d", "5m", true)
             .await
-            .map_err(|err| {
-                YFinanceError::new(ticker, &chrono::Utc::now().naive_utc().into(), err)
-            })?;
+            .map_err(|err| YFinanceError::new(ticker, &date, err))?;
+        let metadata = response
+            .metadata()
+            .map_err(|err| YFinanceError::new(ticker, &date, err))?;
+        let currency = Self::currency(&metadata);
+
+        let (periods, current_period) = match self.session {
+            Session::Pre => (
+                metadata.trading_periods.pre,
+                metadata.current_trading_period.pre,
+            ),
+            _ => (
+                metadata.trading_periods.post,
+                metadata.current_trading_period.post,
+            ),
+        };
+        let period_date = |start: u32, gmtoffset: i32| {
+            DateTime::from_timestamp(start as i64 + gmtoffset as i64, 0)
+                .map(|date_time| date_time.date_naive())
+        };
+        let period = periods
+            .unwrap_or_default()
+            .into_iter()
+            .flatten()
+            .chain(std::iter::once(current_period))
+            .find(|period| period_date(period.start, period.gmtoffset) == Some(date))
+            .ok_or_else(|| YFinanceError::new(ticker, &date, yf::YahooError::EmptyDataSet))?;
+
+        response
+            .quotes()
+            .map_err(|err| YFinanceError::new(ticker, &date, err))?
+            .iter()
+            .rev()
+            .find(|quote| {
+                quote.timestamp >= period.start as u64 && quote.timestamp < period.end as u64
+            })
+            .map(|quote| (self.price_field.of(quote), currency))
+            .ok_or_else(|| YFinanceError::new(ticker, &date, yf::YahooError::EmptyDataSet))
+    }
+
+    /// Describes how the price of a ticker is derived, for the explain command
+    pub(crate) async fn explain(
+        &self,
+        ticker: &str,
+        date: NaiveDate,
+    ) -> Result<String, YFinanceError> {
+        let yahoo_symbol = self.resolve_symbol(ticker).await?;
+        let mut explanation = format!(
+            "Resolved Yahoo symbol: {yahoo_symbol}\nSession: {:?}\nPrice field: {:?}",
+            self.session, self.price_field
+        );
+        let start = OffsetDateTime::from_unix_timestamp(
+            date.and_time(NaiveTime::MIN).and_utc().timestamp(),
+        )
+        .unwrap();
+        let (response, price) = if self.session == Session::Regular {
+            let response = self
+                .provider
+                .lock()
+                .await
+                .inner
+                .get_quote_history_interval(
+                    &yahoo_symbol,
+                    start,
+                    start.checked_add(Duration::days(1)).unwrap(),
+                    "1d",
+                )
+                .await
+                .map_err(|err| YFinanceError::new(ticker, &date, err))?;
+            let price = response
+                .last_quote()
+                .map(|quote| self.price_field.of(&quote));
+            (response, price)
+        } else {
+            let response = self
+                .provider
+                .lock()
+                .await
+                .inner
+                .get_quote_period_interval(&yahoo_symbol, "5d", "5m", true)
+                .await
+                .map_err(|err| YFinanceError::new(ticker, &date, err))?;
+            let price = self
+                .download_session_price(&yahoo_symbol, ticker, date)
+                .await
+                .map(|(price, _)| price);
+            (response, price.map_err(|err| err.inner))
+        };
+        explanation.push_str(&format!("\nRaw response: {response:#?}"));
+        match price {
+            Ok(price) => explanation.push_str(&format!("\nPrice: {price}")),
+            Err(err) => explanation.push_str(&format!("\nNo price: {err:?}")),
+        }
+        Ok(explanation)
+    }
 
-        if search_result.quotes.len() < 1 {
-            eprintln!("Error matching symbol {ticker}");
+    async fn resolve_symbol(
+        &self,
+        ticker: &str,
+    ) -> Result<String, <YFinance as Provider>::ErrorType> {
+        if let Some(cache_result) = self.ticker_resolver_cache.lock().unwrap().get(ticker) {
+            return Ok(cache_result.symbol.clone());
+        }
+
+        // try the listing on the default exchange first, e.g. SAP.DE before SAP.
+        // Yahoo's search finds the listings of an ISIN as it is
+        let isin = is_isin(ticker);
+        let mut queries = vec![];
+        if let Some(suffix) = &self.default_exchange_suffix {
+            if !ticker.contains('.') && !isin {
+                queries.push(format!("{ticker}.{}", suffix.trim_start_matches('.')));
+            }
+        }
+        queries.push(ticker.to_owned());
+
+        let mut resolution = None;
+        let mut rejected_types = vec![];
+        for query in &queries {
+            let provider = self.provider.lock().await;
+            // another download of the ticker may have resolved it while this one waited
+            if let Some(cache_result) = self.ticker_resolver_cache.lock().unwrap().get(ticker) {
+                return Ok(cache_result.symbol.clone());
+            }
+            let search =
+                tokio::time::timeout(self.search_timeout, provider.inner.search_ticker(query))
+                    .await;
+            drop(provider);
+            // a failed search falls back to the ticker as it is, which is often
+            // already a Yahoo symbol, e.g. VOW3.DE
+            let outcome = match search {
+                Ok(Ok(result)) => Ok(result),
+                Ok(Err(err)) => Err(format!("{err}")),
+                Err(_) => Err(format!(
+                    "no answer within {}s",
+                    self.search_timeout.as_secs()
+                )),
+            };
+            let result = match outcome {
+                Ok(result) => result,
+                Err(reason) => {
+                    eprintln!("Warning: the search for {query} failed ({reason}), using {ticker} as the symbol");
+                    resolution = Some(Resolution {
+                        symbol: ticker.to_owned(),
+                        query: ticker.to_owned(),
+                        matches: 0,
+                        quote_type: String::new(),
+                        exchange: String::new(),
+                    });
+                    break;
+                }
+            };
+            if result.quotes.len() > 1 && self.debug {
+                eprintln!("Multiple matches for {query} - using the first equity or ETF");
+                eprintln!(
+                    "{}",
+                    result
+                        .quotes
+                        .iter()
+                        .map(|q| format!("{} ({})", q.symbol, q.quote_type))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                );
+            }
+            match Self::choose_quote(
+                &result.quotes,
+                isin,
+                self.default_exchange_suffix.as_deref(),
+            ) {
+                Some(quote) => {
+                    resolution = Some(Resolution {
+                        symbol: quote.symbol.clone(),
+                        query: query.clone(),
+                        matches: result.quotes.len(),
+                        quote_type: quote.quote_type.clone(),
+                        exchange: quote.exchange.clone(),
+                    });
+                    break;
+                }
+                None => rejected_types.extend(
+                    result
+                        .quotes
+                        .iter()
+                        .map(|quote| quote.quote_type.to_lowercase()),
+                ),
+            }
+        }
+
+        let Some(resolution) = resolution else {
+            if rejected_types.is_empty() {
+                eprintln!("Error matching symbol {ticker}");
+            } else {
+                eprintln!(
+                    "Error matching symbol {ticker}: only {} matches",
+                    rejected_types.join(", ")
+                );
+            }
             return Err(YFinanceError::new(
                 ticker,
                 &chrono::Utc::now().naive_utc().into(),
                 yahoo_finance_api::YahooError::DataInconsistency,
             ));
-        } else if search_result.quotes.len() > 1 && self.debug {
-            eprintln!("Multiple matches for {ticker} - using the first match");
-            eprintln!(
-                "{}",
-                search_result
-                    .quotes
-                    .iter()
-                    .map(|q| q.symbol.clone())
-                    .reduce(|mut acc, s| {
-                        acc.push_str(" ");
-                        acc.push_str(&s);
-                        acc
-                    })
-                    .unwrap()
-            );
-        }
+        };
+        let symbol = resolution.symbol.clone();
         self.ticker_resolver_cache
             .lock()
             .unwrap()
-            .insert(ticker.to_owned(), search_result.quotes[0].symbol.clone());
-        Ok(search_result.quotes[0].symbol.clone())
+            .insert(ticker.to_owned(), resolution);
+        Ok(symbol)
     }
+
+    /// How a ticker was matched to a Yahoo symbol, once it was. The tickers used as
+    /// they are after a failed search weren't matched, so they are searched again
+    /// on the next run
+    pub(crate) fn resolution(&self, ticker: &str) -> Option<Resolution> {
+        self.ticker_resolver_cache
+            .lock()
+            .unwrap()
+            .get(ticker)
+            .filter(|resolution| resolution.matches > 0)
+            .cloned()
+    }
+
+    /// The search result a ticker resolves to: for an ISIN its listing on the default
+    /// exchange if it has one, or else the best match
+    fn choose_quote<'a>(
+        quotes: &'a [yf::YQuoteItem],
+        isin: bool,
+        default_exchange_suffix: Option<&str>,
+    ) -> Option<&'a yf::YQuoteItem> {
+        let listing = default_exchange_suffix.filter(|_| isin).and_then(|suffix| {
+            let suffix = format!(".{}", suffix.trim_start_matches('.'));
+            quotes.iter().find(|quote| quote.symbol.ends_with(&suffix))
+        });
+        listing.or_else(|| Self::best_match(quotes))
+    }
+
+    /// The daily prices of a chart response by trading day, and their currency.
+    /// The quotes are timestamped in UTC, the exchange's offset gives their day
+    fn daily_prices(
+        response: &yf::YResponse,
+        price_field: PriceField,
+    ) -> Result<(Vec<(NaiveDate, f64)>, String), yf::YahooError> {
+        let metadata = response.metadata()?;
+        let prices = response
+            .quotes()?
+            .into_iter()
+            .filter_map(|quote| {
+                let date = DateTime::from_timestamp(
+                    quote.timestamp as i64 + metadata.gmtoffset as i64,
+                    0,
+                )?
+                .date_naive();
+                Some((date, price_field.of(&quote)))
+            })
+            .collect();
+        Ok((prices, Self::currency(&metadata)))
+    }
+
+    /// The first equity or ETF among the search results, or else the first one which
+    /// isn't a currency or a derivative, which can't be what a portfolio lists
+    fn best_match(quotes: &[yf::YQuoteItem]) -> Option<&yf::YQuoteItem> {
+        const PREFERRED_QUOTE_TYPES: [&str; 2] = ["EQUITY", "ETF"];
+        const REJECTED_QUOTE_TYPES: [&str; 3] = ["CURRENCY", "FUTURE", "OPTION"];
+        quotes
+            .iter()
+            .find(|quote| PREFERRED_QUOTE_TYPES.contains(&quote.quote_type.as_str()))
+            .or_else(|| {
+                quotes
+                    .iter()
+                    .find(|quote| !REJECTED_QUOTE_TYPES.contains(&quote.quote_type.as_str()))
+            })
+    }
+}
+
+/// Whether a ticker is an ISIN: a country code, nine letters or digits and a check
+/// digit, which is the Luhn checksum of the others with the letters as numbers (A=10)
+pub(crate) fn is_isin(ticker: &str) -> bool {
+    let bytes = ticker.as_bytes();
+    if bytes.len() != 12
+        || !bytes[..2].iter().all(u8::is_ascii_uppercase)
+        || !bytes[2..11]
+            .iter()
+            .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit())
+        || !bytes[11].is_ascii_digit()
+    {
+        return false;
+    }
+    let digits = ticker
+        .chars()
+        .filter_map(|c| c.to_digit(36))
+        .flat_map(|value| {
+            if value >= 10 {
+                vec![value / 10, value % 10]
+            } else {
+                vec![value]
+            }
+        })
+        .collect::<Vec<_>>();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, digit)| match (i % 2, digit * 2) {
+            (1, doubled) if doubled > 9 => doubled - 9,
+            (1, doubled) => doubled,
+            _ => *digit,
+        })
+        .sum();
+    sum.is_multiple_of(10)
 }
 
 impl Provider for YFinance {
@@ -133,8 +527,15 @@ impl Provider for YFinance {
         &self,
         ticker: String,
         date: NaiveDate,
-    ) -> Result<(String, NaiveDate, f64), Self::ErrorType> {
+    ) -> Result<(String, NaiveDate, f64, String), Self::ErrorType> {
         let yahoo_symbol = self.resolve_symbol(&ticker).await?;
+        if self.session != Session::Regular {
+            let (price, currency) = self
+                .download_session_price(&yahoo_symbol, &ticker, date)
+                .await?;
+            return Ok((ticker, date, price, currency));
+        }
+
         let date_time = date.and_hms_opt(0, 0, 0).unwrap();
 
         let start = OffsetDateTime::from_unix_timestamp(date_time.and_utc().timestamp()).unwrap();
@@ -148,6 +549,153 @@ impl Provider for YFinance {
             .get_quote_history_interval(&yahoo_symbol, start, end, "1d")
             .await
             .map_err(|err| YFinanceError::new(&ticker, &date, err))?;
-        Ok((ticker, date, quote.last_quote().unwrap().close))
+        let currency = Self::currency(
+            &quote
+                .metadata()
+                .map_err(|err| YFinanceError::new(&ticker, &date, err))?,
+        );
+        let last_quote = quote
+            .last_quote()
+            .map_err(|err| YFinanceError::new(&ticker, &date, err))?;
+        Ok((ticker, date, self.price_field.of(&last_quote), currency))
+    }
+
+    /// Fetches the daily quotes of the whole range in one request
+    async fn download_prices_bulk(
+        &self,
+        ticker: String,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<(String, NaiveDate, f64, String)>, Self::ErrorType> {
+        if self.session != Session::Regular {
+            // the extended hours quotes are only available one day at a time
+            let mut prices = vec![];
+            for date in start.iter_days().take_while(|date| *date <= end) {
+                if let Ok(price) = self.download_price(ticker.clone(), date).await {
+                    prices.push(price);
+                }
+            }
+            return Ok(prices);
+        }
+        let yahoo_symbol = self.resolve_symbol(&ticker).await?;
+
+        let to_offset_date_time = |date: NaiveDate| {
+            OffsetDateTime::from_unix_timestamp(date.and_time(NaiveTime::MIN).and_utc().timestamp())
+                .unwrap()
+        };
+        let response = self
+            .provider
+            .lock()
+            .await
+            .inner
+            .get_quote_history_interval(
+                &yahoo_symbol,
+                to_offset_date_time(start),
+                to_offset_date_time(end)
+                    .checked_add(Duration::days(1))
+                    .unwrap(),
+                "1d",
+            )
+            .await
+            .map_err(|err| YFinanceError::new(&ticker, &start, err))?;
+        let (prices, currency) = Self::daily_prices(&response, self.price_field)
+            .map_err(|err| YFinanceError::new(&ticker, &start, err))?;
+        Ok(prices
+            .into_iter()
+            .filter(|(date, _)| *date >= start && *date <= end)
+            .map(|(date, price)| (ticker.clone(), date, price, currency.clone()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(fixture: &str) -> yf::YSearchResult {
+        let json = serde_json::from_str(fixture).unwrap();
+        yf::YSearchResult::from_opt(&yf::YSearchResultOpt::from_json(json).unwrap())
+    }
+
+    fn chart(fixture: &str) -> yf::YResponse {
+        yf::YResponse::from_json(serde_json::from_str(fixture).unwrap()).unwrap()
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    #[test]
+    fn picks_the_first_equity_or_etf_of_several_matches() {
+        let result = search(include_str!("../fixtures/yahoo/search_multi_match.json"));
+        let quote = YFinance::choose_quote(&result.quotes, false, None).unwrap();
+        assert_eq!(quote.symbol, "VWCE.DE");
+    }
+
+    #[test]
+    fn rejects_currencies_and_derivatives() {
+        let result = search(include_str!("../fixtures/yahoo/search_no_match.json"));
+        assert!(YFinance::choose_quote(&result.quotes, false, None).is_none());
+    }
+
+    #[test]
+    fn prefers_the_listing_of_an_isin_on_the_default_exchange() {
+        let result = search(include_str!("../fixtures/yahoo/search_isin.json"));
+        let on_xetra = YFinance::choose_quote(&result.quotes, true, Some(".DE")).unwrap();
+        assert_eq!(on_xetra.symbol, "EUNL.DE");
+        let unlisted = YFinance::choose_quote(&result.quotes, true, Some("PA")).unwrap();
+        assert_eq!(unlisted.symbol, "SWDA.L");
+        // the suffix only picks among the listings of an ISIN
+        let ticker = YFinance::choose_quote(&result.quotes, false, Some(".DE")).unwrap();
+        assert_eq!(ticker.symbol, "SWDA.L");
+    }
+
+    #[test]
+    fn dates_the_quotes_in_the_exchange_time_zone() {
+        let response = chart(include_str!("../fixtures/yahoo/chart_daily.json"));
+        let (prices, currency) = YFinance::daily_prices(&response, PriceField::Close).unwrap();
+        // the quote without a close is left out
+        assert_eq!(
+            prices,
+            vec![
+                (NaiveDate::from_ymd_opt(2024, 2, 28).unwrap(), 87.55),
+                (date(1), 88.02)
+            ]
+        );
+        assert_eq!(currency, "EUR");
+    }
+
+    #[test]
+    fn takes_the_price_field() {
+        let response = chart(include_str!("../fixtures/yahoo/chart_daily.json"));
+        let (prices, _) = YFinance::daily_prices(&response, PriceField::High).unwrap();
+        assert_eq!(prices.last(), Some(&(date(1), 88.3)));
+        let (prices, _) = YFinance::daily_prices(&response, PriceField::Vwap).unwrap();
+        let (_, vwap) = prices.last().unwrap();
+        assert!((vwap - (88.3 + 87.6 + 88.02) / 3.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn defaults_to_dollars_without_a_currency() {
+        let response = chart(include_str!("../fixtures/yahoo/chart_no_currency.json"));
+        let (prices, currency) = YFinance::daily_prices(&response, PriceField::Close).unwrap();
+        assert_eq!(prices.len(), 1);
+        assert_eq!(currency, "USD");
+    }
+
+    #[test]
+    fn fails_on_an_empty_chart() {
+        let response = chart(include_str!("../fixtures/yahoo/chart_empty.json"));
+        assert!(YFinance::daily_prices(&response, PriceField::Close).is_err());
+        assert!(response.last_quote().is_err());
+    }
+
+    #[test]
+    fn recognizes_isins_by_their_check_digit() {
+        assert!(is_isin("IE00B4L5Y983"));
+        assert!(is_isin("US0378331005"));
+        assert!(!is_isin("IE00B4L5Y984"));
+        assert!(!is_isin("AAPL"));
+        assert!(!is_isin("ie00b4l5y983"));
     }
 }